@@ -0,0 +1,12 @@
+ALTER TABLE requests DROP COLUMN IF EXISTS tags;
+
+ALTER TABLE rules ADD COLUMN IF NOT EXISTS allow BOOLEAN NOT NULL DEFAULT TRUE;
+
+UPDATE rules SET allow = action NOT IN ('deny', 'tarpit', 'challenge');
+
+ALTER TABLE rules
+    DROP CONSTRAINT IF EXISTS rules_action_check,
+    DROP COLUMN IF EXISTS tag,
+    DROP COLUMN IF EXISTS challenge_url,
+    DROP COLUMN IF EXISTS tarpit_seconds,
+    DROP COLUMN IF EXISTS action;
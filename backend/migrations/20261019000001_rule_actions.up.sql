@@ -0,0 +1,18 @@
+-- Replace the allow/deny boolean with an explicit rule action
+ALTER TABLE rules
+    ADD COLUMN IF NOT EXISTS action TEXT NOT NULL DEFAULT 'allow',
+    ADD COLUMN IF NOT EXISTS tarpit_seconds INT NOT NULL DEFAULT 10,
+    ADD COLUMN IF NOT EXISTS challenge_url TEXT,
+    ADD COLUMN IF NOT EXISTS tag TEXT;
+
+UPDATE rules SET action = CASE WHEN allow THEN 'allow' ELSE 'deny' END;
+
+ALTER TABLE rules
+    ADD CONSTRAINT rules_action_check
+    CHECK (action IN ('allow', 'deny', 'tarpit', 'log_only', 'challenge', 'rate_limit_only'));
+
+ALTER TABLE rules DROP COLUMN IF EXISTS allow;
+
+-- Tags added by log-only rules to captured requests
+ALTER TABLE requests
+    ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,3 @@
+ALTER TABLE rules
+    DROP COLUMN IF EXISTS rate_limit_header,
+    DROP COLUMN IF EXISTS rate_limit_key;
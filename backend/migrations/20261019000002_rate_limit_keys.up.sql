@@ -0,0 +1,4 @@
+-- Per-rule rate-limit key: which parts of the request identify a client
+ALTER TABLE rules
+    ADD COLUMN IF NOT EXISTS rate_limit_key TEXT[] NOT NULL DEFAULT '{ip}',
+    ADD COLUMN IF NOT EXISTS rate_limit_header TEXT;
@@ -0,0 +1,5 @@
+ALTER TABLE rules
+    DROP CONSTRAINT IF EXISTS rules_rate_limit_algorithm_check,
+    DROP COLUMN IF EXISTS rate_limit_burst,
+    DROP COLUMN IF EXISTS rate_limit_rate,
+    DROP COLUMN IF EXISTS rate_limit_algorithm;
@@ -0,0 +1,9 @@
+-- Per-rule rate-limit algorithm: fail2ban-style sliding window or GCRA throttling
+ALTER TABLE rules
+    ADD COLUMN IF NOT EXISTS rate_limit_algorithm TEXT NOT NULL DEFAULT 'sliding_window',
+    ADD COLUMN IF NOT EXISTS rate_limit_rate DOUBLE PRECISION NOT NULL DEFAULT 1,
+    ADD COLUMN IF NOT EXISTS rate_limit_burst INT NOT NULL DEFAULT 10;
+
+ALTER TABLE rules
+    ADD CONSTRAINT rules_rate_limit_algorithm_check
+    CHECK (rate_limit_algorithm IN ('sliding_window', 'gcra'));
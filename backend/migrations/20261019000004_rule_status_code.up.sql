@@ -0,0 +1,2 @@
+-- Response status condition: rules with it only apply to access-log lines
+ALTER TABLE rules ADD COLUMN IF NOT EXISTS status_code TEXT;
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_bans_active;
+DROP TABLE IF EXISTS rate_limit_arrivals;
+DROP TABLE IF EXISTS rate_limit_hits;
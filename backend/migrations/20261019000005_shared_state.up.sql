@@ -0,0 +1,18 @@
+-- Rate-limit counters shared between replicas (STATE_STORE=postgres).
+-- Unlogged: they are short-lived and can be lost on a crash.
+CREATE UNLOGGED TABLE IF NOT EXISTS rate_limit_hits (
+    rule_id INT NOT NULL,
+    subject TEXT NOT NULL,
+    hit_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_rate_limit_hits_key ON rate_limit_hits(rule_id, subject, hit_at);
+
+CREATE UNLOGGED TABLE IF NOT EXISTS rate_limit_arrivals (
+    rule_id INT NOT NULL,
+    subject TEXT NOT NULL,
+    tat TIMESTAMP WITH TIME ZONE NOT NULL,
+    PRIMARY KEY (rule_id, subject)
+);
+
+CREATE INDEX IF NOT EXISTS idx_bans_active ON bans(ip_address) WHERE NOT expired;
@@ -0,0 +1,3 @@
+ALTER TABLE rules
+    DROP CONSTRAINT IF EXISTS rules_over_limit_status_check,
+    DROP COLUMN IF EXISTS over_limit_status;
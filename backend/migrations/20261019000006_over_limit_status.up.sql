@@ -0,0 +1,8 @@
+-- Status answered when a rule's rate limit is exceeded: 'auto' (403 for the
+-- sliding window ban, 429 for GCRA), '403' or '429'
+ALTER TABLE rules
+    ADD COLUMN IF NOT EXISTS over_limit_status TEXT NOT NULL DEFAULT 'auto';
+
+ALTER TABLE rules
+    ADD CONSTRAINT rules_over_limit_status_check
+    CHECK (over_limit_status IN ('auto', '403', '429'));
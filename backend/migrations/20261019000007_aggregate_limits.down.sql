@@ -0,0 +1,9 @@
+DROP TABLE IF EXISTS rule_cooldowns;
+DROP TABLE IF EXISTS rule_aggregate_hits;
+
+ALTER TABLE rules
+    DROP CONSTRAINT IF EXISTS rules_aggregate_action_check,
+    DROP COLUMN IF EXISTS aggregate_action,
+    DROP COLUMN IF EXISTS aggregate_cooldown_seconds,
+    DROP COLUMN IF EXISTS aggregate_window_seconds,
+    DROP COLUMN IF EXISTS aggregate_limit;
@@ -0,0 +1,25 @@
+-- Aggregate limit per rule: aggregate_limit matches from any source within
+-- aggregate_window_seconds switch the rule to aggregate_action for
+-- aggregate_cooldown_seconds. 0 disables it.
+ALTER TABLE rules
+    ADD COLUMN IF NOT EXISTS aggregate_limit INT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS aggregate_window_seconds BIGINT NOT NULL DEFAULT 60,
+    ADD COLUMN IF NOT EXISTS aggregate_cooldown_seconds BIGINT NOT NULL DEFAULT 300,
+    ADD COLUMN IF NOT EXISTS aggregate_action TEXT NOT NULL DEFAULT 'deny';
+
+ALTER TABLE rules
+    ADD CONSTRAINT rules_aggregate_action_check
+    CHECK (aggregate_action IN ('deny', 'challenge'));
+
+-- Aggregate counters shared between replicas (STATE_STORE=postgres)
+CREATE UNLOGGED TABLE IF NOT EXISTS rule_aggregate_hits (
+    rule_id INT NOT NULL,
+    hit_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_rule_aggregate_hits ON rule_aggregate_hits(rule_id, hit_at);
+
+CREATE UNLOGGED TABLE IF NOT EXISTS rule_cooldowns (
+    rule_id INT PRIMARY KEY,
+    until TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_bans_banned_at;
+DROP TABLE IF EXISTS ban_escalations;
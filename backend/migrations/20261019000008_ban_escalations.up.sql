@@ -0,0 +1,10 @@
+-- Escalation level of each subject and when it was last banned, so repeat
+-- offenders keep escalating across restarts. A level decays after
+-- ban_count_decay_days without bans.
+CREATE TABLE IF NOT EXISTS ban_escalations (
+    subject TEXT PRIMARY KEY,
+    level INT NOT NULL,
+    last_ban_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_bans_banned_at ON bans(banned_at);
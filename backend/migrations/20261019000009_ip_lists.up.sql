@@ -0,0 +1,11 @@
+-- Global allowlist and denylist, checked before bans and rules.
+-- address is a CIDR range (single IPs are stored as /32 or /128).
+CREATE TABLE IF NOT EXISTS ip_lists (
+    id SERIAL PRIMARY KEY,
+    list TEXT NOT NULL CHECK (list IN ('allow', 'deny')),
+    address TEXT NOT NULL,
+    comment TEXT,
+    expires_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (list, address)
+);
@@ -0,0 +1,24 @@
+-- External IP blocklist feeds, fetched in the background.
+-- source is an http(s) URL, a file:// URL or a local path.
+CREATE TABLE IF NOT EXISTS feeds (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    source TEXT NOT NULL,
+    format TEXT NOT NULL DEFAULT 'plain'
+        CHECK (format IN ('plain', 'spamhaus_drop', 'firehol_netset', 'csv')),
+    -- 0-based column holding the address, for the csv format
+    csv_column INT NOT NULL DEFAULT 0 CHECK (csv_column >= 0),
+    refresh_seconds INT NOT NULL DEFAULT 3600 CHECK (refresh_seconds >= 60),
+    -- Deny every listed address before bans and rules
+    deny BOOLEAN NOT NULL DEFAULT FALSE,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    last_fetch_at TIMESTAMP WITH TIME ZONE,
+    last_status TEXT,
+    entry_count INT NOT NULL DEFAULT 0,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- A rule with a feed only matches clients listed in it
+ALTER TABLE rules
+    ADD COLUMN IF NOT EXISTS feed_id INT REFERENCES feeds(id) ON DELETE SET NULL;
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_bans_created_at;
+ALTER TABLE bans DROP COLUMN IF EXISTS expired_at;
+DROP TABLE IF EXISTS bouncers;
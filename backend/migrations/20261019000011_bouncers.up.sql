@@ -0,0 +1,17 @@
+-- Bouncers consuming the CrowdSec-compatible decisions stream. Only the
+-- SHA-256 of the API key is stored.
+CREATE TABLE IF NOT EXISTS bouncers (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    api_key_hash TEXT NOT NULL UNIQUE,
+    last_pull_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- When a ban stopped being in force, so the stream can report it as deleted
+ALTER TABLE bans ADD COLUMN IF NOT EXISTS expired_at TIMESTAMP WITH TIME ZONE;
+
+UPDATE bans SET expired_at = banned_at + make_interval(secs => ban_duration_seconds)
+WHERE expired AND expired_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_bans_created_at ON bans(created_at);
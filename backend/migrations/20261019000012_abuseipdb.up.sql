@@ -0,0 +1,3 @@
+-- AbuseIPDB categories reported when the rule bans an IP (empty: no report)
+ALTER TABLE rules
+    ADD COLUMN IF NOT EXISTS abuseipdb_categories INT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,2 @@
+DELETE FROM settings WHERE key LIKE 'recidive_%';
+ALTER TABLE bans DROP COLUMN IF EXISTS triggered_by;
@@ -0,0 +1,11 @@
+-- Bans issued by the recidive jail keep the ids of the bans that triggered them
+ALTER TABLE bans
+    ADD COLUMN IF NOT EXISTS triggered_by INT[];
+
+-- Recidive policy (disabled by default): 5 rule bans within 1 day ban for 1 week
+INSERT INTO settings (key, value) VALUES
+    ('recidive_enabled', 'false'),
+    ('recidive_max_bans', '5'),
+    ('recidive_find_days', '1'),
+    ('recidive_ban_seconds', '604800')
+ON CONFLICT (key) DO NOTHING;
@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS idx_requests_decision;
+
+ALTER TABLE requests
+    DROP CONSTRAINT IF EXISTS requests_decision_check,
+    DROP COLUMN IF EXISTS reason,
+    DROP COLUMN IF EXISTS decision;
@@ -0,0 +1,22 @@
+-- What shuul did with each captured request, and why
+ALTER TABLE requests
+    ADD COLUMN IF NOT EXISTS decision TEXT NOT NULL DEFAULT 'allow',
+    ADD COLUMN IF NOT EXISTS reason TEXT;
+
+-- Best effort for existing requests: the current action of the rule they matched
+UPDATE requests
+SET decision = CASE rules.action
+        WHEN 'allow' THEN 'allow'
+        WHEN 'log_only' THEN 'monitor'
+        WHEN 'rate_limit_only' THEN 'monitor'
+        ELSE 'deny'
+    END,
+    reason = rules.action
+FROM rules
+WHERE requests.rule_id = rules.id;
+
+ALTER TABLE requests
+    ADD CONSTRAINT requests_decision_check
+    CHECK (decision IN ('allow', 'deny', 'banned', 'rate_limited', 'monitor'));
+
+CREATE INDEX IF NOT EXISTS idx_requests_decision ON requests(decision);
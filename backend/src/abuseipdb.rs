@@ -0,0 +1,158 @@
+//! # Reportes a `AbuseIPDB`
+//!
+//! Tarea que vacía la cola de [`AbuseReport`] y los envía a la API de
+//! `AbuseIPDB` (`POST /api/v2/report`), saltándose las IPs ya reportadas en los
+//! últimos 15 minutos. Un reporte fallido no cuenta, así que el siguiente ban
+//! de la IP se vuelve a reportar.
+//!
+//! Se configura con variables de entorno:
+//!
+//! - `ABUSEIPDB_API_KEY`: clave de la API; sin ella no se reporta nada.
+//! - `ABUSEIPDB_URL`: endpoint de reporte, para apuntar a un mock en pruebas.
+
+use crate::models::{AbuseReport, AbuseReporter, ReportDedup};
+use std::env::var;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info};
+
+const DEFAULT_URL: &str = "https://api.abuseipdb.com/api/v2/report";
+/// Timeout of a report request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Where and how reports are sent.
+#[derive(Debug, Clone)]
+pub struct ReporterConfig {
+    url: String,
+    api_key: String,
+}
+
+impl ReporterConfig {
+    /// Reads the configuration; `None` if the API key is unset.
+    pub fn from_env() -> Option<Self> {
+        let api_key = var("ABUSEIPDB_API_KEY")
+            .ok()
+            .filter(|key| !key.is_empty())?;
+        let url = var("ABUSEIPDB_URL")
+            .ok()
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| DEFAULT_URL.to_string());
+        Some(Self { url, api_key })
+    }
+}
+
+/// Starts the reporter if `AbuseIPDB` is configured, returning its queue.
+pub fn start() -> Option<AbuseReporter> {
+    let config = ReporterConfig::from_env()?;
+    let (reporter, receiver) = AbuseReporter::channel();
+    spawn(config, receiver);
+    Some(reporter)
+}
+
+/// Spawns the task sending the queued reports.
+pub fn spawn(config: ReporterConfig, mut receiver: mpsc::Receiver<AbuseReport>) {
+    info!("Reporting bans to AbuseIPDB at {}", config.url);
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut dedup = ReportDedup::default();
+        while let Some(report) = receiver.recv().await {
+            if !dedup.allow(report.ip, Instant::now()) {
+                debug!("{} already reported to AbuseIPDB recently", report.ip);
+                continue;
+            }
+            match send(&client, &config, &report).await {
+                Ok(()) => debug!("Reported {} to AbuseIPDB", report.ip),
+                Err(e) => {
+                    error!("AbuseIPDB report of {} failed: {e}", report.ip);
+                    dedup.forget(report.ip);
+                },
+            }
+        }
+    });
+}
+
+async fn send(
+    client: &reqwest::Client,
+    config: &ReporterConfig,
+    report: &AbuseReport,
+) -> Result<(), reqwest::Error> {
+    client
+        .post(&config.url)
+        .header("Key", &config.api_key)
+        .header("Accept", "application/json")
+        .form(&[
+            ("ip", report.ip.to_string()),
+            ("categories", report.categories_param()),
+            ("comment", report.comment.clone()),
+        ])
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, extract::State, http::HeaderMap, routing};
+    use std::sync::{Arc, Mutex};
+
+    type MockRequests = Arc<Mutex<Vec<(String, String)>>>;
+
+    async fn mock_report(
+        State(requests): State<MockRequests>,
+        headers: HeaderMap,
+        body: String,
+    ) -> &'static str {
+        let key = headers
+            .get("Key")
+            .and_then(|key| key.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        requests.lock().unwrap().push((key, body));
+        "{}"
+    }
+
+    #[tokio::test]
+    async fn test_reports_reach_mock_once_per_ip() {
+        let requests = MockRequests::default();
+        let app = Router::new()
+            .route("/api/v2/report", routing::post(mock_report))
+            .with_state(Arc::clone(&requests));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (reporter, receiver) = AbuseReporter::channel();
+        spawn(
+            ReporterConfig {
+                url: format!("http://{address}/api/v2/report"),
+                api_key: "secret".to_string(),
+            },
+            receiver,
+        );
+        let ip = "192.0.2.1".parse().unwrap();
+        for _ in 0..2 {
+            reporter.report(AbuseReport::new(ip, &[18, 21], "brute force".to_string()).unwrap());
+        }
+        let other = "192.0.2.2".parse().unwrap();
+        reporter.report(AbuseReport::new(other, &[19], "bad bot".to_string()).unwrap());
+
+        for _ in 0..50 {
+            if requests.lock().unwrap().len() >= 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].0, "secret");
+        assert_eq!(
+            requests[0].1,
+            "ip=192.0.2.1&categories=18%2C21&comment=brute+force"
+        );
+        assert!(requests[1].1.starts_with("ip=192.0.2.2&categories=19"));
+    }
+}
@@ -0,0 +1,116 @@
+//! # Bouncer de `CrowdSec`
+//!
+//! Consulta periódicamente `/v1/decisions/stream` de una LAPI de `CrowdSec` y
+//! aplica las decisiones en [`AppState::crowdsec`]. La primera consulta (y la
+//! siguiente a un fallo) pide `startup=true`, que devuelve todas las
+//! decisiones activas; el resto sólo las nuevas y las borradas.
+//!
+//! Se configura con variables de entorno:
+//!
+//! - `CROWDSEC_LAPI_URL`: URL base de la LAPI, p. ej. `http://crowdsec:8080`.
+//! - `CROWDSEC_API_KEY`: clave del bouncer (`cscli bouncers add shuul`).
+//! - `CROWDSEC_POLL_SECONDS`: intervalo entre consultas (10 por defecto).
+//! - `CROWDSEC_CAPTCHA_URL`: challenge para las decisiones `captcha`; sin él
+//!   se deniegan.
+
+use crate::models::{AppState, DecisionStream};
+use std::env::var;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+/// Timeout of a stream request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connection to a `CrowdSec` LAPI.
+#[derive(Debug, Clone)]
+pub struct LapiConfig {
+    url: String,
+    api_key: String,
+    interval: Duration,
+}
+
+impl LapiConfig {
+    /// Reads the configuration; `None` if the LAPI URL or key are unset.
+    pub fn from_env() -> Option<Self> {
+        let url = var("CROWDSEC_LAPI_URL")
+            .ok()
+            .filter(|url| !url.is_empty())?;
+        let api_key = var("CROWDSEC_API_KEY").ok().filter(|key| !key.is_empty())?;
+        let seconds = var("CROWDSEC_POLL_SECONDS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(10)
+            .max(1);
+        Some(Self {
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            interval: Duration::from_secs(seconds),
+        })
+    }
+
+    /// Challenge URL for `captcha` decisions.
+    pub fn captcha_url() -> Option<String> {
+        var("CROWDSEC_CAPTCHA_URL")
+            .ok()
+            .filter(|url| !url.is_empty())
+    }
+}
+
+/// Spawns the polling loop.
+pub fn spawn(app_state: &Arc<AppState>, config: LapiConfig) {
+    info!(
+        "CrowdSec bouncer polling {} every {}s",
+        config.url,
+        config.interval.as_secs()
+    );
+    let app_state = Arc::clone(app_state);
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut interval = tokio::time::interval(config.interval);
+        let mut startup = true;
+        loop {
+            interval.tick().await;
+            match pull(&client, &config, startup).await {
+                Ok(stream) => {
+                    let Ok(mut decisions) = app_state.crowdsec.lock() else {
+                        error!("CrowdSec decisions mutex poisoned");
+                        continue;
+                    };
+                    let now = chrono::Utc::now();
+                    let (added, deleted) = decisions.apply(stream, startup, now);
+                    decisions.prune(now);
+                    if added > 0 || deleted > 0 {
+                        debug!(
+                            "CrowdSec decisions: {added} added, {deleted} deleted, {} active",
+                            decisions.count()
+                        );
+                    }
+                    startup = false;
+                },
+                Err(e) => {
+                    error!("CrowdSec LAPI pull failed: {e}");
+                    // Deletions may have been missed: start over
+                    startup = true;
+                },
+            }
+        }
+    });
+}
+
+async fn pull(
+    client: &reqwest::Client,
+    config: &LapiConfig,
+    startup: bool,
+) -> Result<DecisionStream, reqwest::Error> {
+    client
+        .get(format!("{}/v1/decisions/stream", config.url))
+        .query(&[("startup", startup)])
+        .header("X-Api-Key", &config.api_key)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
@@ -0,0 +1,118 @@
+//! # Descarga de feeds
+//!
+//! Tarea en segundo plano que descarga los feeds de listas de bloqueo
+//! ([`Feed`]) cuando vence su intervalo de refresco, los parsea y sustituye su
+//! conjunto de IPs en [`AppState::feeds`]. El resultado (`ok` o el error) y el
+//! número de entradas quedan en la tabla `feeds`.
+//!
+//! Al arrancar se descargan todos los feeds activos, porque el conjunto en
+//! memoria está vacío. Si una descarga falla se mantiene el conjunto anterior.
+
+use crate::models::{AppState, Feed, parse_feed};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+/// How often the feeds are checked for a due refresh.
+const CHECK_INTERVAL: Duration = Duration::from_mins(1);
+/// Timeout of an http(s) fetch.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Spawns the refresh loop.
+pub fn spawn(app_state: &Arc<AppState>) {
+    let app_state = Arc::clone(app_state);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        let mut loaded = false;
+        loop {
+            interval.tick().await;
+            match Feed::read_all(&app_state.pool).await {
+                Ok(feeds) => {
+                    let now = chrono::Utc::now();
+                    for feed in feeds
+                        .iter()
+                        .filter(|feed| feed.active && (!loaded || feed.is_due(now)))
+                    {
+                        // Failures are logged and recorded in the feed
+                        let _ = refresh(&app_state, feed).await;
+                    }
+                    loaded = true;
+                },
+                Err(e) => error!("Cannot read feeds: {e}"),
+            }
+        }
+    });
+}
+
+/// Fetches and parses a feed, replaces its addresses and records the outcome.
+/// Returns the number of entries loaded.
+///
+/// # Errors
+/// The fetch error, also recorded as the feed status.
+pub async fn refresh(app_state: &AppState, feed: &Feed) -> Result<usize, String> {
+    let result = fetch(&feed.source).await.map(|body| {
+        let parsed = parse_feed(feed.format, feed.csv_column(), &body);
+        if parsed.invalid > 0 {
+            debug!("Feed {}: {} invalid lines", feed.name, parsed.invalid);
+        }
+        let count = parsed.ranges.len();
+        match app_state.feeds.lock() {
+            Ok(mut feeds) => feeds.replace(feed.id, feed.deny, parsed.ranges),
+            Err(e) => error!("Feeds cache poisoned: {e}"),
+        }
+        count
+    });
+    let (status, count) = match &result {
+        Ok(count) => {
+            info!("Feed {} refreshed: {count} entries", feed.name);
+            ("ok".to_string(), Some(*count))
+        },
+        Err(e) => {
+            error!("Feed {} fetch failed: {e}", feed.name);
+            (e.clone(), None)
+        },
+    };
+    if let Err(e) = Feed::record_fetch(&app_state.pool, feed.id, &status, count).await {
+        error!("Cannot record fetch of feed {}: {e}", feed.name);
+    }
+    result
+}
+
+/// Reads the body of a feed source: an http(s) URL, a file:// URL or a path.
+async fn fetch(source: &str) -> Result<String, String> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        let response = reqwest::Client::new()
+            .get(source)
+            .timeout(FETCH_TIMEOUT)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| e.to_string())?;
+        return response.text().await.map_err(|e| e.to_string());
+    }
+    let path = source.strip_prefix("file://").unwrap_or(source);
+    tokio::fs::read_to_string(Path::new(path))
+        .await
+        .map_err(|e| format!("{path}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FeedFormat;
+
+    #[tokio::test]
+    async fn test_fetch_local_file() {
+        let path = std::env::temp_dir().join(format!("shuul-feed-{}.netset", std::process::id()));
+        std::fs::write(&path, "# FireHOL level1\n1.2.3.0/24\n5.6.7.8\n").unwrap();
+
+        let body = fetch(&format!("file://{}", path.display())).await.unwrap();
+        assert_eq!(body, fetch(&path.display().to_string()).await.unwrap());
+        let parsed = parse_feed(FeedFormat::FireholNetset, 0, &body);
+        assert_eq!(parsed.ranges.len(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(fetch(&path.display().to_string()).await.is_err());
+    }
+}
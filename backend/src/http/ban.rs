@@ -1,35 +1,82 @@
 //! # Endpoints de bans
 //!
-//! CRUD para bans activos: listar, banear manualmente, desbanear.
+//! CRUD para bans activos: listar, banear manualmente, desbanear. También el
+//! historial de bans de un sujeto y el informe de reincidentes, ambos leídos
+//! de la tabla `bans`.
+//!
+//! El listado está paginado como el de peticiones y reglas, se puede ordenar
+//! por tiempo restante, escalado o fecha del ban y filtrar por regla, país,
+//! motivo o CIDR. Cada ban incluye país, ciudad y ASN del sujeto.
+//!
+//! Un ban puede ser permanente (`permanent: true`) y uno activo se puede
+//! editar (motivo, tiempo restante, permanente). `/bans/bulk` banea una
+//! lista pegada de sujetos o desbanea por regla, país, CIDR o todo. Al
+//! desbanear se olvidan los contadores de rate limiting del sujeto y, si se
+//! pide, su nivel de escalado.
+//!
+//! `GET /bans/crowdsec` lista las decisiones traídas de `CrowdSec`, que no se
+//! pueden desbanear desde aquí.
+//!
+//! `GET /bans/export` vuelca los bans activos en formato de cortafuegos o
+//! proxy, con `ETag` para que un cron pueda consultarlo sin descargarlo cada
+//! vez.
+//!
+//! El campo `ip_address` admite cualquier sujeto: una IP (`1.2.3.4`), un
+//! rango (`1.2.3.0/24`), un ASN (`AS64500`), un país (`country:ES`) o una
+//! clave (`key:...`). Los bans de ASN y de país se comprueban con la
+//! geolocalización de cada petición.
 
+use crate::constants::{DEFAULT_LIMIT, DEFAULT_PAGE};
 use crate::models::error::AppError;
-use crate::models::{ApiResponse, AppState, Data};
+use crate::models::{
+    ApiResponse, AppState, BanEscalation, BanFilter, BanHistoryEntry, BanInfo, BanListing,
+    BanRecord, BanRemaining, BanSort, BanUpdate, Data, ExportFormat, PagedResponse, Pagination,
+    Subject, parse_address, parse_subjects, render_bans,
+};
 use axum::{
     Json, Router,
     extract::{Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    },
+    response::{IntoResponse, Response},
     routing,
 };
+use chrono::Utc;
+use openssl::sha::sha256;
 use serde::{Deserialize, Serialize};
-use std::net::IpAddr;
+use std::fmt::Write;
 use std::sync::Arc;
 pub fn ban_router() -> Router<Arc<AppState>> {
     Router::new()
         .route("/", routing::get(list_handler))
         .route("/", routing::post(ban_handler))
+        .route("/", routing::patch(update_handler))
         .route("/", routing::delete(unban_handler))
+        .route("/bulk", routing::post(bulk_ban_handler))
+        .route("/bulk", routing::delete(bulk_unban_handler))
         .route("/info", routing::get(info_handler))
+        .route("/history", routing::get(history_handler))
+        .route("/offenders", routing::get(offenders_handler))
+        .route("/export", routing::get(export_handler))
+        .route("/crowdsec", routing::get(crowdsec_handler))
 }
 
-#[derive(Debug, Serialize)]
-pub struct BanResponse {
-    pub ip_address: String,
+#[derive(Debug, Deserialize)]
+pub struct ListBansParams {
+    pub page: Option<u32>,
+    pub limit: Option<u32>,
+    /// `time_remaining`, `escalation_level` or `banned_at` (default).
+    pub sort_by: Option<String>,
+    pub asc: Option<bool>,
     pub rule_id: Option<i32>,
-    pub reason: String,
-    pub ban_duration_seconds: i64,
-    pub escalation_level: u32,
-    pub time_remaining_seconds: u64,
+    /// ISO code or part of the country name.
+    pub country: Option<String>,
+    /// Part of the ban reason.
+    pub reason: Option<String>,
+    /// Only IPs and ranges inside this network.
+    pub cidr: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,95 +85,246 @@ pub struct BanRequest {
     pub rule_id: Option<i32>,
     pub reason: Option<String>,
     pub ban_duration_seconds: Option<i64>,
+    /// The ban never expires; `ban_duration_seconds` is ignored.
+    pub permanent: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateBanRequest {
+    pub ip_address: String,
+    pub rule_id: Option<i32>,
+    pub reason: Option<String>,
+    /// New remaining time, counted from now.
+    pub time_remaining_seconds: Option<i64>,
+    /// Seconds added to the ban, or removed if negative.
+    pub extend_seconds: Option<i64>,
+    /// Makes the ban permanent. Takes precedence over the other durations.
+    pub permanent: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UnbanParams {
     pub ip_address: String,
     pub rule_id: Option<i32>,
+    /// Also reset the escalation level of the subject.
+    pub reset_escalation: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkBanRequest {
+    /// Subjects separated by whitespace, commas or new lines.
+    pub addresses: String,
+    pub rule_id: Option<i32>,
+    pub reason: Option<String>,
+    pub ban_duration_seconds: Option<i64>,
+    /// The bans never expire; `ban_duration_seconds` is ignored.
+    pub permanent: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkBanResponse {
+    pub banned: usize,
+    /// Entries that are not a valid subject.
+    pub invalid: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkUnbanParams {
+    pub rule_id: Option<i32>,
+    /// ISO code or part of the country name.
+    pub country: Option<String>,
+    /// IPs and ranges inside this network.
+    pub cidr: Option<String>,
+    /// Lift every ban; required when no filter is given.
+    pub all: Option<bool>,
+    /// Also reset the escalation level of the subjects.
+    pub reset_escalation: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryParams {
+    pub ip_address: String,
 }
 
-/// GET /api/v1/bans — List all active bans.
+#[derive(Debug, Serialize)]
+pub struct HistoryResponse {
+    pub ip_address: String,
+    pub kind: &'static str,
+    /// Current escalation level (0 if it decayed).
+    pub escalation_level: u32,
+    pub bans: Vec<BanHistoryEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OffendersParams {
+    /// Only count bans issued in the last `days` days.
+    pub days: Option<i32>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    /// `text` (default), `nftables`, `ipset`, `nginx`, `traefik` or `csv`.
+    pub format: Option<String>,
+}
+
+/// Maximum offenders returned by one report.
+const MAX_OFFENDERS: u32 = 1000;
+
+/// GET /api/v1/bans — Active bans, filtered, sorted and paginated.
 pub async fn list_handler(
     State(app_state): State<Arc<AppState>>,
+    Query(params): Query<ListBansParams>,
 ) -> Result<impl IntoResponse, AppError> {
-    let bans = {
-        let ban_manager = app_state
-            .ban_manager
-            .lock()
-            .map_err(|_| AppError::CachePoisoned)?;
-        ban_manager
-            .active_bans()
-            .into_iter()
-            .map(|(ip, ban)| BanResponse {
-                ip_address: ip.to_string(),
-                rule_id: ban.rule_id,
-                reason: ban.reason.clone(),
-                ban_duration_seconds: ban.ban_duration_seconds,
-                escalation_level: ban.escalation_level,
-                time_remaining_seconds: ban.time_remaining().as_secs(),
-            })
-            .collect::<Vec<_>>()
+    let sort: BanSort = params
+        .sort_by
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .map_err(AppError::InvalidInput)?
+        .unwrap_or_default();
+    let filter = BanFilter {
+        rule_id: params.rule_id,
+        country: wildcard_free(params.country),
+        reason: wildcard_free(params.reason),
+        cidr: params
+            .cidr
+            .as_deref()
+            .map(parse_address)
+            .transpose()
+            .map_err(AppError::InvalidInput)?,
     };
-    Ok(ApiResponse::new(
+    let mut bans = listings(&app_state, &filter).await?;
+    sort.sort(&mut bans, params.asc.unwrap_or(false));
+
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).max(1);
+    let offset = params.page.unwrap_or(DEFAULT_PAGE).saturating_sub(1);
+    let records = bans.len();
+    let total_pages = u32::try_from(records.div_ceil(limit as usize)).unwrap_or(u32::MAX);
+    let page = bans
+        .into_iter()
+        .skip((offset as usize).saturating_mul(limit as usize))
+        .take(limit as usize)
+        .collect::<Vec<_>>();
+    let pagination = Pagination {
+        page: offset + 1,
+        limit,
+        pages: total_pages,
+        records: i64::try_from(records).unwrap_or(i64::MAX),
+        prev: if offset > 0 {
+            Some(format!("/bans?page={offset}&limit={limit}"))
+        } else {
+            None
+        },
+        next: if (offset + 1) < total_pages {
+            Some(format!("/bans?page={}&limit={}", offset + 2, limit))
+        } else {
+            None
+        },
+    };
+    Ok(PagedResponse::new(
         StatusCode::OK,
         "Active bans",
-        Data::Some(serde_json::to_value(bans)?),
+        Data::Some(serde_json::to_value(page)?),
+        pagination,
     ))
 }
 
-/// POST /api/v1/bans — Manually ban an IP.
+/// Active bans matching `filter`, with their geolocation.
+async fn listings(app_state: &AppState, filter: &BanFilter) -> Result<Vec<BanListing>, AppError> {
+    Ok(app_state
+        .store
+        .active_bans()
+        .await?
+        .iter()
+        .map(|(subject, ban)| {
+            BanListing::new(subject, ban).with_geo(&app_state.maxmind_db, app_state.asn_db.as_ref())
+        })
+        .filter(|listing| filter.matches(listing))
+        .collect())
+}
+
+/// Drops the `%` wildcards the table filters send; matching is by substring.
+fn wildcard_free(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.replace('%', ""))
+        .filter(|value| !value.is_empty())
+}
+
+/// POST /api/v1/bans — Manually ban an IP, range, ASN or rate-limit key.
 pub async fn ban_handler(
     State(app_state): State<Arc<AppState>>,
     Json(params): Json<BanRequest>,
 ) -> Result<impl IntoResponse, AppError> {
-    let ip: IpAddr = params
-        .ip_address
-        .parse()
-        .map_err(|_| AppError::InvalidInput("Invalid IP address".to_string()))?;
+    let subject: Subject = params.ip_address.parse().map_err(AppError::InvalidInput)?;
 
     let reason = params.reason.unwrap_or_else(|| "Manual ban".to_string());
+    let duration = ban_duration(params.ban_duration_seconds, params.permanent)?;
 
-    let ban_info = {
-        let mut ban_manager = app_state
-            .ban_manager
-            .lock()
-            .map_err(|_| AppError::CachePoisoned)?;
-        ban_manager.ban(ip, params.rule_id, reason, params.ban_duration_seconds).clone()
-    };
+    let ban_info = app_state
+        .store
+        .ban(
+            subject.clone(),
+            params.rule_id,
+            reason,
+            duration,
+            Vec::new(),
+        )
+        .await?;
 
     Ok(ApiResponse::new(
         StatusCode::CREATED,
         "IP banned",
-        Data::Some(serde_json::to_value(BanResponse {
-            ip_address: ip.to_string(),
-            rule_id: params.rule_id,
-            reason: ban_info.reason.clone(),
-            ban_duration_seconds: ban_info.ban_duration_seconds,
-            escalation_level: ban_info.escalation_level,
-            time_remaining_seconds: ban_info.time_remaining().as_secs(),
-        })?),
+        Data::Some(serde_json::to_value(
+            BanListing::new(&subject, &ban_info)
+                .with_geo(&app_state.maxmind_db, app_state.asn_db.as_ref()),
+        )?),
     ))
 }
 
-/// DELETE /api/v1/bans — Unban an IP.
+/// Duration of a manual ban: permanent, the given positive number of
+/// seconds, or `None` to follow the escalation policy.
+fn ban_duration(seconds: Option<i64>, permanent: Option<bool>) -> Result<Option<i64>, AppError> {
+    if permanent.unwrap_or(false) {
+        return Ok(Some(BanInfo::PERMANENT));
+    }
+    match seconds {
+        Some(seconds) if seconds <= 0 => Err(AppError::InvalidInput(
+            "ban_duration_seconds must be positive".to_string(),
+        )),
+        seconds => Ok(seconds),
+    }
+}
+
+/// DELETE /api/v1/bans — Unban an IP, range, ASN or rate-limit key.
 pub async fn unban_handler(
     State(app_state): State<Arc<AppState>>,
     Query(params): Query<UnbanParams>,
 ) -> Result<impl IntoResponse, AppError> {
-    let ip: IpAddr = params
-        .ip_address
-        .parse()
-        .map_err(|_| AppError::InvalidInput("Invalid IP address".to_string()))?;
-
-    let removed = {
-        let mut ban_manager = app_state
-            .ban_manager
-            .lock()
-            .map_err(|_| AppError::CachePoisoned)?;
-        ban_manager.unban(&ip, params.rule_id)
-    };
+    let subject: Subject = params.ip_address.parse().map_err(AppError::InvalidInput)?;
+
+    let removed = lift_ban(
+        &app_state,
+        &subject,
+        params.rule_id,
+        params.reset_escalation.unwrap_or(false),
+    )
+    .await?;
 
+    if !removed
+        && app_state
+            .crowdsec
+            .lock()
+            .map_err(|_| AppError::CachePoisoned)?
+            .for_subject(&subject, Utc::now())
+            .is_some()
+    {
+        return Ok(ApiResponse::new(
+            StatusCode::CONFLICT,
+            "Banned by a CrowdSec decision, remove it in CrowdSec",
+            Data::None,
+        ));
+    }
     if removed {
         Ok(ApiResponse::new(
             StatusCode::OK,
@@ -142,20 +340,269 @@ pub async fn unban_handler(
     }
 }
 
+/// Lifts the bans of `subject` issued by `rule_id` and forgets its counters
+/// in that rule (in every rule for manual bans), so its next request doesn't
+/// ban it again. Returns `true` if any ban was lifted.
+async fn lift_ban(
+    app_state: &AppState,
+    subject: &Subject,
+    rule_id: Option<i32>,
+    reset_escalation: bool,
+) -> Result<bool, AppError> {
+    let removed = app_state.store.unban(subject, rule_id).await?;
+    if removed {
+        app_state.store.reset_counters(rule_id, Some(subject)).await?;
+        if reset_escalation {
+            app_state.store.reset_escalation(subject).await?;
+        }
+    }
+    Ok(removed)
+}
+
+/// PATCH /api/v1/bans — Edit the reason or remaining time of an active ban.
+pub async fn update_handler(
+    State(app_state): State<Arc<AppState>>,
+    Json(params): Json<UpdateBanRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let subject: Subject = params.ip_address.parse().map_err(AppError::InvalidInput)?;
+    let remaining = match (
+        params.permanent.unwrap_or(false),
+        params.time_remaining_seconds,
+        params.extend_seconds,
+    ) {
+        (true, _, _) => Some(BanRemaining::Permanent),
+        (false, Some(_), Some(_)) => {
+            return Err(AppError::InvalidInput(
+                "Give either time_remaining_seconds or extend_seconds".to_string(),
+            ));
+        },
+        (false, Some(seconds), None) if seconds <= 0 => {
+            return Err(AppError::InvalidInput(
+                "time_remaining_seconds must be positive".to_string(),
+            ));
+        },
+        (false, Some(seconds), None) => Some(BanRemaining::Set(seconds)),
+        (false, None, Some(seconds)) => Some(BanRemaining::Extend(seconds)),
+        (false, None, None) => None,
+    };
+    let update = BanUpdate {
+        reason: params.reason.filter(|reason| !reason.trim().is_empty()),
+        remaining,
+    };
+    match app_state
+        .store
+        .update_ban(&subject, params.rule_id, update)
+        .await?
+    {
+        Some(ban) => Ok(ApiResponse::new(
+            StatusCode::OK,
+            "Ban updated",
+            Data::Some(serde_json::to_value(
+                BanListing::new(&subject, &ban)
+                    .with_geo(&app_state.maxmind_db, app_state.asn_db.as_ref()),
+            )?),
+        )),
+        None => Ok(ApiResponse::new(
+            StatusCode::NOT_FOUND,
+            "IP not found or not banned",
+            Data::None,
+        )),
+    }
+}
+
+/// POST /api/v1/bans/bulk — Ban every subject of a pasted list.
+pub async fn bulk_ban_handler(
+    State(app_state): State<Arc<AppState>>,
+    Json(params): Json<BulkBanRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let (subjects, invalid) = parse_subjects(&params.addresses);
+    if subjects.is_empty() {
+        return Err(AppError::InvalidInput("No valid address to ban".to_string()));
+    }
+    let reason = params.reason.unwrap_or_else(|| "Manual ban".to_string());
+    let duration = ban_duration(params.ban_duration_seconds, params.permanent)?;
+    for subject in &subjects {
+        app_state
+            .store
+            .ban(
+                subject.clone(),
+                params.rule_id,
+                reason.clone(),
+                duration,
+                Vec::new(),
+            )
+            .await?;
+    }
+    Ok(ApiResponse::new(
+        StatusCode::CREATED,
+        "IPs banned",
+        Data::Some(serde_json::to_value(BulkBanResponse {
+            banned: subjects.len(),
+            invalid,
+        })?),
+    ))
+}
+
+/// DELETE /api/v1/bans/bulk — Unban every active ban of a rule, country or
+/// CIDR, or all of them with `all=true`. Returns how many were lifted.
+pub async fn bulk_unban_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<BulkUnbanParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let filter = BanFilter {
+        rule_id: params.rule_id,
+        country: wildcard_free(params.country),
+        reason: None,
+        cidr: params
+            .cidr
+            .as_deref()
+            .map(parse_address)
+            .transpose()
+            .map_err(AppError::InvalidInput)?,
+    };
+    if filter.rule_id.is_none()
+        && filter.country.is_none()
+        && filter.cidr.is_none()
+        && !params.all.unwrap_or(false)
+    {
+        return Err(AppError::InvalidInput(
+            "Give rule_id, country or cidr, or all=true".to_string(),
+        ));
+    }
+    let reset_escalation = params.reset_escalation.unwrap_or(false);
+    let mut unbanned = 0_usize;
+    for listing in listings(&app_state, &filter).await? {
+        if lift_ban(&app_state, &listing.subject, listing.rule_id, reset_escalation).await? {
+            unbanned += 1;
+        }
+    }
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Bans lifted",
+        Data::Some(serde_json::to_value(unbanned)?),
+    ))
+}
+
+/// GET /api/v1/bans/crowdsec — Decisions pulled from the `CrowdSec` LAPI. They
+/// are read-only here: only `CrowdSec` can remove them.
+pub async fn crowdsec_handler(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let decisions = app_state
+        .crowdsec
+        .lock()
+        .map_err(|_| AppError::CachePoisoned)?
+        .list();
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "CrowdSec decisions",
+        Data::Some(serde_json::to_value(decisions)?),
+    ))
+}
+
 /// GET /api/v1/bans/info — Count of active bans.
 pub async fn info_handler(
     State(app_state): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, AppError> {
-    let count = {
-        let ban_manager = app_state
-            .ban_manager
-            .lock()
-            .map_err(|_| AppError::CachePoisoned)?;
-        ban_manager.active_count()
-    };
+    let count = app_state.store.active_bans().await?.len();
     Ok(ApiResponse::new(
         StatusCode::OK,
         "Active bans count",
         Data::Some(serde_json::to_value(count)?),
     ))
-}
\ No newline at end of file
+}
+
+/// GET /api/v1/bans/history — Every ban of the `ip_address` subject, newest
+/// first, with its current escalation level.
+pub async fn history_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<HistoryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let subject: Subject = params.ip_address.parse().map_err(AppError::InvalidInput)?;
+    let decay_days = app_state.store.ban_count_decay_days();
+    let history = HistoryResponse {
+        ip_address: subject.to_string(),
+        kind: subject.kind(),
+        escalation_level: BanEscalation::level(&app_state.pool, &subject, decay_days).await?,
+        bans: BanRecord::history(&app_state.pool, &subject).await?,
+    };
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Ban history",
+        Data::Some(serde_json::to_value(history)?),
+    ))
+}
+
+/// GET /api/v1/bans/offenders — Subjects ranked by ban count, optionally
+/// counting only the last `days` days.
+pub async fn offenders_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<OffendersParams>,
+) -> Result<impl IntoResponse, AppError> {
+    if params.days.is_some_and(|days| days <= 0) {
+        return Err(AppError::InvalidInput("days must be positive".to_string()));
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_OFFENDERS);
+    let offenders = BanRecord::offenders(
+        &app_state.pool,
+        params.days.map(i64::from),
+        app_state.store.ban_count_decay_days(),
+        i64::from(limit),
+    )
+    .await?;
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Offenders",
+        Data::Some(serde_json::to_value(offenders)?),
+    ))
+}
+
+/// GET /api/v1/bans/export — Active IP and range bans in the given `format`.
+/// Answers 304 if `If-None-Match` matches the `ETag` of the output.
+pub async fn export_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<ExportParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let format: ExportFormat = params
+        .format
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .map_err(AppError::InvalidInput)?
+        .unwrap_or_default();
+    let bans = app_state.store.active_bans().await?;
+    let body = render_bans(format, &bans);
+    let digest = sha256(body.as_bytes());
+    let etag = format!(
+        "\"{}\"",
+        digest[..16].iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+    );
+    let etag_header = HeaderValue::from_str(&etag).map_err(|e| AppError::Other(e.to_string()))?;
+    if etag_matches(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag_header)]).into_response());
+    }
+    Ok((
+        StatusCode::OK,
+        [
+            (CONTENT_TYPE, HeaderValue::from_static(format.content_type())),
+            (ETAG, etag_header),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// `true` if `If-None-Match` lists `etag` (weak or strong) or is `*`.
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
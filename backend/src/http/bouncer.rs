@@ -0,0 +1,90 @@
+//! # Endpoints de bouncers
+//!
+//! Alta, listado y baja de los bouncers que consumen `/v1/decisions`. La API
+//! key sólo se devuelve al crear el bouncer.
+
+use crate::models::error::AppError;
+use crate::models::{ApiResponse, AppState, Bouncer, Data, NewBouncer};
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub fn bouncer_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", routing::get(read_handler))
+        .route("/", routing::post(create_handler))
+        .route("/", routing::delete(delete_handler))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteParams {
+    pub id: Option<i32>,
+}
+
+/// Lists the bouncers and when they last pulled the stream.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool).
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the bouncers.
+pub async fn read_handler(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let bouncers = Bouncer::read_all(&app_state.pool).await?;
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Bouncers",
+        Data::Some(serde_json::to_value(bouncers)?),
+    ))
+}
+
+/// Registers a bouncer and generates its API key.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool).
+///   - `bouncer`: Bouncer name.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the bouncer and its
+///     `api_key`, which can't be read again.
+pub async fn create_handler(
+    State(app_state): State<Arc<AppState>>,
+    Json(bouncer): Json<NewBouncer>,
+) -> Result<impl IntoResponse, AppError> {
+    if bouncer.name.trim().is_empty() {
+        return Err(AppError::InvalidInput("name is required".to_string()));
+    }
+    let created = Bouncer::create(&app_state.pool, bouncer).await?;
+    Ok(ApiResponse::new(
+        StatusCode::CREATED,
+        "Bouncer created",
+        Data::Some(serde_json::to_value(created)?),
+    ))
+}
+
+/// Removes a bouncer, revoking its API key.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool).
+///   - `params`: Query parameter with the bouncer `id`.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the removed bouncer.
+pub async fn delete_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<DeleteParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = params
+        .id
+        .ok_or_else(|| AppError::InvalidInput("id parameter is required".to_string()))?;
+    let bouncer = Bouncer::delete(&app_state.pool, id).await?;
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Bouncer deleted",
+        Data::Some(serde_json::to_value(bouncer)?),
+    ))
+}
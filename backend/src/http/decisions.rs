@@ -0,0 +1,190 @@
+//! # Stream de decisiones compatible con `CrowdSec`
+//!
+//! Publica los bans de shuul con la API de la LAPI de `CrowdSec`, para que
+//! bouncers existentes (firewall, Cloudflare...) los apliquen sin una
+//! integración propia. Se monta en `/v1/decisions`, fuera de `/api/v1`:
+//!
+//! - `GET /v1/decisions/stream?startup=true&scopes=ip,range`: modo stream.
+//!   Con `startup` (o en la primera consulta del bouncer) `new` trae todos los
+//!   bans activos; después sólo los emitidos desde la consulta anterior, y
+//!   `deleted` los que han expirado o se han levantado desde entonces.
+//! - `GET /v1/decisions?ip=1.2.3.4`: modo live, los bans que cubren una IP,
+//!   un rango (`range`) o un valor de un ámbito (`scope` y `value`).
+//!
+//! Los bans se leen de la tabla `bans`, que refleja los de
+//! [`BanManager`](crate::models::BanManager). Los bouncers se autentican con
+//! la cabecera `X-Api-Key` (ver `/api/v1/bouncers`).
+
+use crate::models::error::AppError;
+use crate::models::{AppState, BanRecord, Bouncer, DecisionStream, LapiDecision, Subject};
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::debug;
+
+/// Bans stored or ended this long before the previous pull are sent again,
+/// so one committed while that pull ran isn't missed. Bouncers ignore
+/// duplicates.
+const PULL_OVERLAP: chrono::Duration = chrono::Duration::seconds(5);
+
+pub fn decisions_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", routing::get(live_handler))
+        .route("/stream", routing::get(stream_handler))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamParams {
+    #[serde(default)]
+    pub startup: bool,
+    /// Comma separated scopes; `ip` by default, as in `CrowdSec`.
+    pub scopes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LiveParams {
+    pub ip: Option<String>,
+    pub range: Option<String>,
+    pub scope: Option<String>,
+    pub value: Option<String>,
+}
+
+/// The bouncer owning the `X-Api-Key` header, or the 403 `CrowdSec` answers.
+async fn authenticate(app_state: &AppState, headers: &HeaderMap) -> Result<Bouncer, Response> {
+    let forbidden = || {
+        (
+            StatusCode::FORBIDDEN,
+            Json(json!({ "message": "access forbidden" })),
+        )
+            .into_response()
+    };
+    let api_key = headers
+        .get("X-Api-Key")
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(forbidden)?;
+    match Bouncer::authenticate(&app_state.pool, api_key).await {
+        Ok(Some(bouncer)) => Ok(bouncer),
+        Ok(None) => Err(forbidden()),
+        Err(e) => Err(AppError::from(e).into_response()),
+    }
+}
+
+/// `true` if `decision` has one of the comma separated `scopes`.
+fn in_scopes(decision: &LapiDecision, scopes: &str) -> bool {
+    scopes
+        .split(',')
+        .any(|scope| scope.trim().eq_ignore_ascii_case(&decision.scope))
+}
+
+/// GET /v1/decisions/stream — Bans issued and lifted since the bouncer's
+/// previous pull.
+pub async fn stream_handler(
+    State(app_state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<StreamParams>,
+) -> Result<Response, AppError> {
+    let bouncer = match authenticate(&app_state, &headers).await {
+        Ok(bouncer) => bouncer,
+        Err(response) => return Ok(response),
+    };
+    // The pull only moves forward once both lists were read.
+    let mut transaction = app_state.pool.begin().await?;
+    let previous_pull = Bouncer::record_pull(&mut transaction, bouncer.id).await?;
+    let startup = params.startup || previous_pull.is_none();
+    let since = previous_pull
+        .filter(|_| !startup)
+        .map(|previous| previous - PULL_OVERLAP);
+    let new = BanRecord::started_since(&mut transaction, since).await?;
+    let deleted = match previous_pull {
+        Some(previous) => BanRecord::ended_since(&mut transaction, previous - PULL_OVERLAP).await?,
+        None => Vec::new(),
+    };
+    transaction.commit().await?;
+
+    let now = Utc::now();
+    let scopes = params.scopes.as_deref().unwrap_or("ip");
+    let publish = |bans: Vec<_>| {
+        bans.iter()
+            .filter_map(|ban| LapiDecision::from_ban(ban, now))
+            .filter(|decision| in_scopes(decision, scopes))
+            .collect::<Vec<_>>()
+    };
+    let stream = DecisionStream {
+        new: Some(publish(new)),
+        deleted: Some(publish(deleted)),
+    };
+    debug!(
+        "Bouncer {} pulled {} new and {} deleted decisions",
+        bouncer.name,
+        stream.new.as_ref().map_or(0, Vec::len),
+        stream.deleted.as_ref().map_or(0, Vec::len)
+    );
+    Ok(Json(stream).into_response())
+}
+
+/// GET /v1/decisions — Active bans covering an IP, a range or a scope value;
+/// `null` if there are none.
+pub async fn live_handler(
+    State(app_state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<LiveParams>,
+) -> Result<Response, AppError> {
+    if let Err(response) = authenticate(&app_state, &headers).await {
+        return Ok(response);
+    }
+    let ip = params
+        .ip
+        .as_deref()
+        .and_then(|ip| ip.parse::<IpAddr>().ok());
+    let range = params
+        .range
+        .as_deref()
+        .and_then(|range| range.parse::<Subject>().ok());
+    // The stored forms a value may have; its scope is checked below.
+    let mut subjects: Vec<String> = ip.iter().map(ToString::to_string).collect();
+    subjects.extend(range.iter().map(ToString::to_string));
+    if let Some(value) = &params.value {
+        subjects.extend([
+            value.clone(),
+            format!("AS{value}"),
+            format!("country:{value}"),
+        ]);
+    }
+    let now = Utc::now();
+    let decisions: Vec<LapiDecision> = BanRecord::covering(&app_state.pool, &subjects, ip)
+        .await?
+        .iter()
+        .filter_map(|ban| {
+            let decision = LapiDecision::from_ban(ban, now)?;
+            let covered = match (&params.value, &ban.subject) {
+                (Some(value), _) => {
+                    decision.value == *value
+                        && params
+                            .scope
+                            .as_ref()
+                            .is_none_or(|scope| scope.eq_ignore_ascii_case(&decision.scope))
+                },
+                (None, Subject::Ip(banned)) => ip.as_ref() == Some(banned),
+                (None, Subject::Range(banned)) => {
+                    ip.as_ref().is_some_and(|ip| banned.contains(ip))
+                        || range.as_ref() == Some(&ban.subject)
+                },
+                _ => false,
+            };
+            covered.then_some(decision)
+        })
+        .collect();
+    if decisions.is_empty() {
+        return Ok(Json(serde_json::Value::Null).into_response());
+    }
+    Ok(Json(decisions).into_response())
+}
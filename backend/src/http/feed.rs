@@ -0,0 +1,186 @@
+//! # Endpoints de feeds
+//!
+//! CRUD de los feeds de listas de bloqueo y refresco manual. Al crear un feed,
+//! o al cambiar su origen o formato, se descarga en segundo plano; el resto de
+//! refrescos los hace la tarea de [`crate::feeds`].
+
+use crate::feeds;
+use crate::models::error::AppError;
+use crate::models::{ApiResponse, AppState, Data, Feed, NewFeed, UpdateFeed};
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::debug;
+
+pub fn feed_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", routing::get(read_handler))
+        .route("/", routing::post(create_handler))
+        .route("/", routing::patch(update_handler))
+        .route("/", routing::delete(delete_handler))
+        .route("/refresh", routing::post(refresh_handler))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdParams {
+    pub id: Option<i32>,
+}
+
+impl IdParams {
+    fn id(&self) -> Result<i32, AppError> {
+        self.id
+            .ok_or_else(|| AppError::InvalidInput("id parameter is required".to_string()))
+    }
+}
+
+/// Fails with 400 if the source or the refresh interval are unusable.
+fn validate(source: &str, refresh_seconds: Option<i32>) -> Result<(), AppError> {
+    if source.trim().is_empty() {
+        return Err(AppError::InvalidInput("source is required".to_string()));
+    }
+    if refresh_seconds.is_some_and(|seconds| seconds < 60) {
+        return Err(AppError::InvalidInput(
+            "refresh_seconds must be at least 60".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fetches a feed without blocking the request.
+fn spawn_refresh(app_state: &Arc<AppState>, feed: Feed) {
+    let app_state = Arc::clone(app_state);
+    tokio::spawn(async move {
+        // The outcome is logged and recorded in the feed
+        let _ = feeds::refresh(&app_state, &feed).await;
+    });
+}
+
+/// Lists the feeds with the status of their last fetch.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool).
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the feeds.
+pub async fn read_handler(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let feeds = Feed::read_all(&app_state.pool).await?;
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Feeds",
+        Data::Some(serde_json::to_value(feeds)?),
+    ))
+}
+
+/// Creates a feed and fetches it in the background if it is active.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool, feeds).
+///   - `feed`: Name, source, format, refresh interval and flags.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the created feed, or
+///     400 if the source or interval are invalid.
+pub async fn create_handler(
+    State(app_state): State<Arc<AppState>>,
+    Json(feed): Json<NewFeed>,
+) -> Result<impl IntoResponse, AppError> {
+    validate(&feed.source, feed.refresh_seconds)?;
+    let feed = Feed::create(&app_state.pool, feed).await?;
+    debug!("Feed created: {:?}", feed);
+    if feed.active {
+        spawn_refresh(&app_state, feed.clone());
+    }
+    Ok(ApiResponse::new(
+        StatusCode::CREATED,
+        "Feed created",
+        Data::Some(serde_json::to_value(feed)?),
+    ))
+}
+
+/// Replaces the configuration of a feed. A new source or format is fetched
+/// in the background; a deactivated feed stops matching at once.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool, feeds).
+///   - `update`: Feed id and configuration.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the updated feed.
+pub async fn update_handler(
+    State(app_state): State<Arc<AppState>>,
+    Json(update): Json<UpdateFeed>,
+) -> Result<impl IntoResponse, AppError> {
+    validate(&update.source, Some(update.refresh_seconds))?;
+    let feed = Feed::update(&app_state.pool, update).await?;
+    {
+        let mut feeds = app_state
+            .feeds
+            .lock()
+            .map_err(|_| AppError::CachePoisoned)?;
+        if feed.active {
+            feeds.set_deny(feed.id, feed.deny);
+        } else {
+            feeds.remove(feed.id);
+        }
+    }
+    if feed.active && feed.last_fetch_at.is_none() {
+        spawn_refresh(&app_state, feed.clone());
+    }
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Feed updated",
+        Data::Some(serde_json::to_value(feed)?),
+    ))
+}
+
+/// Removes a feed. Rules using it lose their feed condition.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool, feeds).
+///   - `params`: Query parameter with the feed `id`.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the removed feed.
+pub async fn delete_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<IdParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let feed = Feed::delete(&app_state.pool, params.id()?).await?;
+    app_state
+        .feeds
+        .lock()
+        .map_err(|_| AppError::CachePoisoned)?
+        .remove(feed.id);
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Feed deleted",
+        Data::Some(serde_json::to_value(feed)?),
+    ))
+}
+
+/// Fetches a feed now, whatever its refresh interval.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool, feeds).
+///   - `params`: Query parameter with the feed `id`.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the feed and its new
+///     fetch status; a failed fetch is reported in `last_status`.
+pub async fn refresh_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<IdParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let feed = Feed::read(&app_state.pool, params.id()?).await?;
+    // The outcome is recorded in the feed, read back below
+    let _ = feeds::refresh(&app_state, &feed).await;
+    let feed = Feed::read(&app_state.pool, feed.id).await?;
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Feed refreshed",
+        Data::Some(serde_json::to_value(feed)?),
+    ))
+}
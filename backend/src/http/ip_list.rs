@@ -0,0 +1,210 @@
+//! # Endpoints de listas globales
+//!
+//! CRUD de la lista de permitidos y la de bloqueados, más la importación en
+//! bloque de un texto pegado (una IP o rango CIDR por línea, con comentario
+//! opcional tras `#`).
+//!
+//! Tras cada cambio se recarga la copia en memoria que consulta `shuul()`.
+
+use crate::models::error::AppError;
+use crate::models::{
+    ApiResponse, AppState, Data, IpListEntry, IpListKind, IpLists, NewIpListEntry,
+    UpdateIpListEntry, parse_address,
+};
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::debug;
+
+pub fn ip_list_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", routing::get(read_handler))
+        .route("/", routing::post(create_handler))
+        .route("/", routing::patch(update_handler))
+        .route("/", routing::delete(delete_handler))
+        .route("/import", routing::post(import_handler))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadParams {
+    pub list: Option<IpListKind>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteParams {
+    pub id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportRequest {
+    pub list: IpListKind,
+    /// One IP or CIDR range per line; `#` starts a comment.
+    pub text: String,
+    /// Comment for the lines without one.
+    pub comment: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    pub imported: usize,
+    /// Lines that aren't an IP or CIDR range.
+    pub invalid: Vec<String>,
+}
+
+/// Reloads the in-memory lists checked by `shuul()`.
+async fn reload_lists(app_state: &AppState) -> Result<(), AppError> {
+    let lists = IpLists::load(&app_state.pool).await?;
+    *app_state
+        .ip_lists
+        .lock()
+        .map_err(|_| AppError::CachePoisoned)? = lists;
+    Ok(())
+}
+
+/// Lists the entries of both lists, or of one.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool).
+///   - `params`: Optional `list` (`allow` or `deny`).
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the entries, newest
+///     first.
+pub async fn read_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<ReadParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let entries = IpListEntry::read_all(&app_state.pool, params.list).await?;
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "IP list entries",
+        Data::Some(serde_json::to_value(entries)?),
+    ))
+}
+
+/// Adds an IP or CIDR range to a list; an existing entry is updated.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool, lists).
+///   - `entry`: List, address, optional comment and expiry.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the stored entry, or
+///     400 if the address is invalid.
+pub async fn create_handler(
+    State(app_state): State<Arc<AppState>>,
+    Json(entry): Json<NewIpListEntry>,
+) -> Result<impl IntoResponse, AppError> {
+    let range = parse_address(&entry.address).map_err(AppError::InvalidInput)?;
+    let entry = IpListEntry::create(
+        &app_state.pool,
+        entry.list,
+        &range,
+        entry.comment.as_deref(),
+        entry.expires_at,
+    )
+    .await?;
+    debug!("IP list entry stored: {:?}", entry);
+    reload_lists(&app_state).await?;
+    Ok(ApiResponse::new(
+        StatusCode::CREATED,
+        "IP list entry created",
+        Data::Some(serde_json::to_value(entry)?),
+    ))
+}
+
+/// Replaces the comment and expiry of an entry.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool, lists).
+///   - `update`: Entry id, comment and expiry.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the updated entry.
+pub async fn update_handler(
+    State(app_state): State<Arc<AppState>>,
+    Json(update): Json<UpdateIpListEntry>,
+) -> Result<impl IntoResponse, AppError> {
+    let entry = IpListEntry::update(&app_state.pool, &update).await?;
+    reload_lists(&app_state).await?;
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "IP list entry updated",
+        Data::Some(serde_json::to_value(entry)?),
+    ))
+}
+
+/// Removes an entry.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool, lists).
+///   - `params`: Query parameter with the entry `id`.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the removed entry.
+pub async fn delete_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<DeleteParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = params
+        .id
+        .ok_or_else(|| AppError::InvalidInput("id parameter is required".to_string()))?;
+    let entry = IpListEntry::delete(&app_state.pool, id).await?;
+    reload_lists(&app_state).await?;
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "IP list entry deleted",
+        Data::Some(serde_json::to_value(entry)?),
+    ))
+}
+
+/// Adds every IP or CIDR range of a pasted text to a list. Valid lines are
+/// stored even if others are not.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool, lists).
+///   - `import`: List, text, default comment and expiry.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the number of stored
+///     entries and the invalid lines.
+pub async fn import_handler(
+    State(app_state): State<Arc<AppState>>,
+    Json(import): Json<ImportRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut entries = Vec::new();
+    let mut invalid = Vec::new();
+    for line in import.text.lines() {
+        let (address, comment) = line
+            .split_once('#')
+            .map_or((line, None), |(address, comment)| {
+                (address, Some(comment.trim()).filter(|c| !c.is_empty()))
+            });
+        if address.trim().is_empty() {
+            continue;
+        }
+        match parse_address(address) {
+            Ok(range) => entries.push((
+                range,
+                comment
+                    .map(str::to_string)
+                    .or_else(|| import.comment.clone()),
+            )),
+            Err(_) => invalid.push(line.trim().to_string()),
+        }
+    }
+    let stored =
+        IpListEntry::import(&app_state.pool, import.list, &entries, import.expires_at).await?;
+    reload_lists(&app_state).await?;
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "IP list imported",
+        Data::Some(serde_json::to_value(ImportResponse {
+            imported: stored.len(),
+            invalid,
+        })?),
+    ))
+}
@@ -6,13 +6,21 @@
 //! - [`user`] — Autenticación (login, logout, registro) y gestión de usuarios
 //! - [`auth`] — SSO / OIDC (Single Sign-On)
 //! - [`rule`] — CRUD de reglas de filtrado
+//! - [`ip_list`] — Listas globales de IPs permitidas y bloqueadas
+//! - [`feed`] — Feeds externos de listas de bloqueo
+//! - [`bouncer`] — Bouncers que consumen el stream de decisiones
+//! - [`decisions`] — Stream de decisiones compatible con `CrowdSec`
 //! - [`request`] — Consulta de peticiones HTTP capturadas
 //! - [`shuul`] — Endpoint principal de captura y filtrado
 //! - [`util`] — Utilidades (geolocalización, etc.)
 
 mod auth;
 mod ban;
+mod bouncer;
+mod decisions;
+mod feed;
 mod health;
+mod ip_list;
 mod middleware;
 mod request;
 mod rule;
@@ -24,11 +32,16 @@ mod util;
 
 pub use auth::auth_router;
 pub use ban::ban_router;
+pub use bouncer::bouncer_router;
+pub use decisions::decisions_router;
+pub use feed::feed_router;
 pub use health::health_router;
+pub use ip_list::ip_list_router;
 pub use middleware::require_auth;
 pub use request::request_router;
 pub use rule::rule_router;
 pub use settings::settings_router;
+pub use shuul::{matching_rules, record_attempt};
 pub use shuul::shuul_router;
 pub use template::template_router;
 pub use user::{api_user_router, user_router};
@@ -2,12 +2,18 @@
 //!
 //! Consulta de peticiones HTTP capturadas: listado con paginación,
 //! estadísticas (top países, top reglas, evolución temporal) y eliminación.
+//!
+//! El listado se filtra por `decision` y `reason` como por cualquier otra
+//! columna. Las estadísticas aceptan `decision` para contar sólo las
+//! peticiones con esa decisión, y la evolución puede agrupar por país o por
+//! decisión (`series`).
 
 use crate::constants::DEFAULT_LIMIT;
 use crate::constants::DEFAULT_PAGE;
 use crate::models::error::AppError;
 use crate::models::{
     ApiResponse, AppState, Data, NewRequest, PagedResponse, Pagination, ReadRequestParams, Request,
+    RequestDecision,
 };
 use axum::{
     Json, Router,
@@ -35,13 +41,31 @@ pub fn request_router() -> Router<Arc<AppState>> {
 pub struct EvolutionParams {
     pub unit: Option<String>,
     pub last: Option<i32>,
+    /// `country` (default) or `decision`.
+    pub series: Option<String>,
+    pub decision: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsParams {
+    pub decision: Option<String>,
+}
+
+/// Parses the optional `decision` filter of the stats endpoints.
+fn decision_filter(decision: Option<&str>) -> Result<Option<RequestDecision>, AppError> {
+    decision
+        .filter(|decision| !decision.is_empty())
+        .map(str::parse)
+        .transpose()
+        .map_err(AppError::InvalidInput)
 }
 
 /// Returns time‑series evolution data for requests.
 ///
 /// * **Parameters**
 ///   - `app_state`: Shared state (DB pool, caches, etc.).
-///   - `params`: Query parameters containing `unit` (`day|hour|minute`) and `last` (how many periods).
+///   - `params`: Query parameters containing `unit` (`day|hour|minute`), `last` (how many periods),
+///     `series` (`country|decision`) and an optional `decision` filter.
 /// * **Returns**
 ///   - `Result<impl IntoResponse, AppError>` – JSON with the evolution data or an error message.
 pub async fn read_evolution(
@@ -51,7 +75,14 @@ pub async fn read_evolution(
     debug!("Evolution params: {:?}", params);
     let unit = params.unit.clone().unwrap_or_else(|| "day".to_string());
     let last = params.last.unwrap_or(7);
-    let evolution = Request::evolution(&app_state.pool, &unit, last).await?;
+    let series = params.series.as_deref().unwrap_or("country");
+    if series != "country" && series != "decision" {
+        return Err(AppError::InvalidInput(
+            "Parameter series must be 'country' or 'decision'".to_string(),
+        ));
+    }
+    let decision = decision_filter(params.decision.as_deref())?;
+    let evolution = Request::evolution(&app_state.pool, &unit, last, series, decision).await?;
     debug!("Request evolution: {:?}", evolution);
     Ok(ApiResponse::new(
         StatusCode::OK,
@@ -64,12 +95,15 @@ pub async fn read_evolution(
 ///
 /// * **Parameters**
 ///   - `app_state`: Shared application state (DB pool, cache, etc.).
+///   - `params`: Query parameters with an optional `decision` filter.
 /// * **Returns**
 ///   - `Result<impl IntoResponse, AppError>` – JSON with the top rules or an error.
 pub async fn read_top_rules(
     State(app_state): State<Arc<AppState>>,
+    Query(params): Query<StatsParams>,
 ) -> Result<impl IntoResponse, AppError> {
-    let countries = Request::top_rules(&app_state.pool).await?;
+    let decision = decision_filter(params.decision.as_deref())?;
+    let countries = Request::top_rules(&app_state.pool, decision).await?;
     debug!("Top rules: {:?}", countries);
     Ok(ApiResponse::new(
         StatusCode::OK,
@@ -82,12 +116,15 @@ pub async fn read_top_rules(
 ///
 /// * **Parameters**
 ///   - `app_state`: Shared state (DB pool, cache, etc.).
+///   - `params`: Query parameters with an optional `decision` filter.
 /// * **Returns**
 ///   - `Result<impl IntoResponse, AppError>` – JSON with the top countries or an error.
 pub async fn read_top_countries(
     State(app_state): State<Arc<AppState>>,
+    Query(params): Query<StatsParams>,
 ) -> Result<impl IntoResponse, AppError> {
-    let countries = Request::top_countries(&app_state.pool).await?;
+    let decision = decision_filter(params.decision.as_deref())?;
+    let countries = Request::top_countries(&app_state.pool, decision).await?;
     debug!("Top countries: {:?}", countries);
     Ok(ApiResponse::new(
         StatusCode::OK,
@@ -126,7 +163,8 @@ pub struct ReadInfoParams {
 ///
 /// * **Parameters**
 ///   - `app_state`: Shared state with DB pool.
-///   - `params`: Query parameters containing an optional `option` (e.g., "total").
+///   - `params`: Query parameters containing an optional `option`: "total", "filtered"
+///     (rejected requests) or a decision name.
 /// * **Returns**
 ///   - `Result<impl IntoResponse, AppError>` – JSON with the requested info or an error.
 pub async fn read_info_handler(
@@ -136,10 +174,10 @@ pub async fn read_info_handler(
     debug!("Read info params: {:?}", params);
     match params.option {
         Some(ref opt) => {
-            if opt != "total" && opt != "filtered" {
+            if opt != "total" && opt != "filtered" && opt.parse::<RequestDecision>().is_err() {
                 return Ok(ApiResponse::new(
                     StatusCode::BAD_REQUEST,
-                    "Parameter option must be 'total' or 'filtered'",
+                    "Parameter option must be 'total', 'filtered' or a decision",
                     Data::None,
                 )
                 .into_response());
@@ -2,13 +2,18 @@
 //!
 //! CRUD completo para las reglas de filtrado HTTP:
 //! crear, leer (con paginación), actualizar, eliminar y consultar info agregada.
+//! Incluye el análisis de reglas ensombrecidas, duplicadas o contradictorias
+//! y la reordenación atómica de pesos, las estadísticas de los rate limiters
+//! (con sus claves y contadores, que se pueden resetear) y el estado de los
+//! límites agregados.
 
 use crate::constants::DEFAULT_LIMIT;
 use crate::constants::DEFAULT_PAGE;
 use crate::models::error::AppError;
 use crate::models::{
-    ApiResponse, AppState, Data, NewRule, PagedResponse, Pagination, ReadRuleParams, Rule,
-    UpdateRule,
+    ApiResponse, AppState, CacheRule, Data, Gcra, MAX_TARPIT_SECONDS, NewRule, PagedResponse,
+    Pagination, Placement, ReadRuleParams, Rule, RuleAction, Subject, UpdateRule, analyze_rules,
+    place,
 };
 use axum::{
     Json, Router,
@@ -17,7 +22,7 @@ use axum::{
     response::IntoResponse,
     routing,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::debug;
 
@@ -26,6 +31,14 @@ pub fn rule_router() -> Router<Arc<AppState>> {
         .route("/", routing::post(create_handler))
         .route("/", routing::get(read_handler))
         .route("/info", routing::get(read_info_handler))
+        .route("/analysis", routing::get(analysis_handler))
+        .route("/limiters", routing::get(limiters_handler))
+        .route("/limiters", routing::delete(reset_counters_handler))
+        .route("/limiters/keys", routing::get(keys_handler))
+        .route("/limiters/subject", routing::get(subject_counters_handler))
+        .route("/aggregates", routing::get(aggregates_handler))
+        .route("/reorder", routing::put(reorder_handler))
+        .route("/move", routing::post(move_handler))
         .route("/", routing::patch(update_handler))
         .route("/", routing::delete(delete_handler))
 }
@@ -42,6 +55,10 @@ pub async fn create_handler(
     Json(rule): Json<NewRule>,
 ) -> Result<impl IntoResponse, AppError> {
     debug!("Rule: {:?}", rule);
+    check_aggregate_action(rule.aggregate_action)?;
+    check_gcra(rule.rate_limit_rate, rule.rate_limit_burst)?;
+    check_max_retry(rule.max_retry)?;
+    check_tarpit(rule.tarpit_seconds)?;
     let rule = Rule::create(&app_state.pool, rule).await?;
     debug!("Rule created: {:?}", &rule);
     {
@@ -159,6 +176,282 @@ pub async fn read_info_handler(
     }
 }
 
+/// Analyzes the active rule set in evaluation order.
+///
+/// Reports rules shadowed by earlier ones, duplicates, contradictory
+/// allow/deny pairs and rate limits that can never trigger.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state containing the cached active rules.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the list of issues or an error.
+pub async fn analysis_handler(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let issues = {
+        let rules_guard = app_state
+            .rules
+            .lock()
+            .map_err(|_| AppError::CachePoisoned)?;
+        analyze_rules(&rules_guard)
+    };
+    debug!("Rule analysis: {:?}", issues);
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Rule analysis",
+        Data::Some(serde_json::to_value(issues)?),
+    ))
+}
+
+/// Lists the rate limiters with their size and LRU eviction count.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (state store).
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with one entry per rule limiter.
+pub async fn limiters_handler(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut stats = app_state.store.limiter_stats().await?;
+    stats.sort_by_key(|s| s.rule_id);
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Rate limiters",
+        Data::Some(serde_json::to_value(stats)?),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CountersParams {
+    pub rule_id: Option<i32>,
+    /// IP, range, ASN or rate-limit key.
+    pub ip_address: Option<String>,
+}
+
+/// Lists the keys a rule's rate limiter tracks, with hit count, window
+/// start and time to reset, busiest first.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (rule cache, state store).
+///   - `params`: `rule_id` of the rule.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the tracked keys, or 404 if the rule is not rate limited.
+pub async fn keys_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<CountersParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let rule_id = params
+        .rule_id
+        .ok_or_else(|| AppError::InvalidInput("rule_id is required".to_string()))?;
+    let config = {
+        let rules_guard = app_state
+            .rules
+            .lock()
+            .map_err(|_| AppError::CachePoisoned)?;
+        rules_guard
+            .iter()
+            .find(|r| r.rule.id == rule_id && r.rule.rate_limit_enabled)
+            .map(|r| r.rule.limiter_config())
+    };
+    let Some(config) = config else {
+        return Ok(ApiResponse::new(
+            StatusCode::NOT_FOUND,
+            "Rule not found or not rate limited",
+            Data::None,
+        ));
+    };
+    let mut keys = app_state.store.counters(rule_id, config, None).await?;
+    keys.sort_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.subject.cmp(&b.subject)));
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Tracked keys",
+        Data::Some(serde_json::to_value(keys)?),
+    ))
+}
+
+/// Shows the counters of one IP (or other rate-limit key) across all rules.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (rule cache, state store).
+///   - `params`: `ip_address` to look up.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with one entry per rule tracking the subject.
+pub async fn subject_counters_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<CountersParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let subject: Subject = params
+        .ip_address
+        .ok_or_else(|| AppError::InvalidInput("ip_address is required".to_string()))?
+        .parse()
+        .map_err(AppError::InvalidInput)?;
+    let configs = {
+        let rules_guard = app_state
+            .rules
+            .lock()
+            .map_err(|_| AppError::CachePoisoned)?;
+        rules_guard
+            .iter()
+            .filter(|r| r.rule.rate_limit_enabled)
+            .map(|r| (r.rule.id, r.rule.limiter_config()))
+            .collect::<Vec<_>>()
+    };
+    let mut counters = Vec::new();
+    for (rule_id, config) in configs {
+        counters.extend(app_state.store.counters(rule_id, config, Some(&subject)).await?);
+    }
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Counters",
+        Data::Some(serde_json::to_value(counters)?),
+    ))
+}
+
+/// Resets rate-limit counters: of an IP (or other key) in every rule, of a
+/// whole rule, or of an IP in one rule.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (state store).
+///   - `params`: `rule_id` and/or `ip_address`; at least one is required.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the number of keys reset.
+pub async fn reset_counters_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<CountersParams>,
+) -> Result<impl IntoResponse, AppError> {
+    if params.rule_id.is_none() && params.ip_address.is_none() {
+        return Err(AppError::InvalidInput(
+            "rule_id or ip_address is required".to_string(),
+        ));
+    }
+    let subject: Option<Subject> = params
+        .ip_address
+        .map(|subject| subject.parse())
+        .transpose()
+        .map_err(AppError::InvalidInput)?;
+    let reset = app_state
+        .store
+        .reset_counters(params.rule_id, subject.as_ref())
+        .await?;
+    debug!(
+        "Reset {} rate limit keys (rule {:?}, subject {:?})",
+        reset, params.rule_id, subject
+    );
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Counters reset",
+        Data::Some(serde_json::to_value(reset)?),
+    ))
+}
+
+/// Aggregate limit of a rule and its current state.
+#[derive(Debug, Serialize)]
+pub struct AggregateState {
+    pub rule_id: i32,
+    pub limit: u32,
+    pub window_seconds: u64,
+    pub cooldown_seconds: u64,
+    pub action: RuleAction,
+    /// Matches within the window.
+    pub hits: u32,
+    pub cooling_down: bool,
+    pub cooldown_remaining_seconds: u64,
+}
+
+/// Lists the rules with an aggregate limit and whether they are cooling down.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (rule cache, state store).
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with one entry per rule with an aggregate limit.
+pub async fn aggregates_handler(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let aggregate_rules = {
+        let rules_guard = app_state
+            .rules
+            .lock()
+            .map_err(|_| AppError::CachePoisoned)?;
+        rules_guard
+            .iter()
+            .filter_map(|r| {
+                r.rule
+                    .aggregate_limit()
+                    .map(|limit| (r.rule.id, r.rule.aggregate_action, limit))
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut aggregates = Vec::with_capacity(aggregate_rules.len());
+    for (rule_id, action, limit) in aggregate_rules {
+        let status = app_state.store.aggregate_status(rule_id, limit).await?;
+        aggregates.push(AggregateState {
+            rule_id,
+            limit: limit.limit,
+            window_seconds: limit.window.as_secs(),
+            cooldown_seconds: limit.cooldown.as_secs(),
+            action,
+            hits: status.hits,
+            cooling_down: status.cooldown_remaining.is_some(),
+            cooldown_remaining_seconds: status
+                .cooldown_remaining
+                .map_or(0, |remaining| remaining.as_secs()),
+        });
+    }
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Aggregate limits",
+        Data::Some(serde_json::to_value(aggregates)?),
+    ))
+}
+
+/// Rejects aggregate actions other than deny and challenge.
+fn check_aggregate_action(action: Option<RuleAction>) -> Result<(), AppError> {
+    match action {
+        None | Some(RuleAction::Deny | RuleAction::Challenge) => Ok(()),
+        Some(action) => Err(AppError::InvalidInput(format!(
+            "aggregate_action must be deny or challenge, not {}",
+            action.as_str()
+        ))),
+    }
+}
+
+/// Rejects a GCRA rate outside the accepted range and a burst below 1.
+fn check_gcra(rate: Option<f64>, burst: Option<i32>) -> Result<(), AppError> {
+    if rate.is_some_and(|rate| Gcra::new(rate, 1).is_none()) {
+        return Err(AppError::InvalidInput(format!(
+            "rate_limit_rate must be between {} and {} requests per second",
+            Gcra::MIN_RATE,
+            Gcra::MAX_RATE
+        )));
+    }
+    if burst.is_some_and(|burst| burst < 1) {
+        return Err(AppError::InvalidInput(
+            "rate_limit_burst must be at least 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Rejects a `max_retry` below 1.
+fn check_max_retry(max_retry: Option<i32>) -> Result<(), AppError> {
+    if max_retry.is_some_and(|max_retry| max_retry < 1) {
+        return Err(AppError::InvalidInput(
+            "max_retry must be at least 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Rejects a tarpit delay below 0 or above [`MAX_TARPIT_SECONDS`].
+fn check_tarpit(tarpit_seconds: Option<i32>) -> Result<(), AppError> {
+    if tarpit_seconds.is_some_and(|seconds| !(0..=MAX_TARPIT_SECONDS).contains(&seconds)) {
+        return Err(AppError::InvalidInput(format!(
+            "tarpit_seconds must be between 0 and {MAX_TARPIT_SECONDS}"
+        )));
+    }
+    Ok(())
+}
+
 /// Updates an existing rule in the database and refreshes the in‑memory cache.
 ///
 /// * **Parameters**
@@ -171,6 +464,10 @@ pub async fn update_handler(
     Json(rule): Json<UpdateRule>,
 ) -> Result<impl IntoResponse, AppError> {
     debug!("Rule: {:?}", rule);
+    check_aggregate_action(rule.aggregate_action)?;
+    check_gcra(rule.rate_limit_rate, rule.rate_limit_burst)?;
+    check_max_retry(rule.max_retry)?;
+    check_tarpit(rule.tarpit_seconds)?;
     let rule = Rule::update(&app_state.pool, rule).await?;
     {
         let mut rules_guard = app_state
@@ -182,6 +479,11 @@ pub async fn update_handler(
         rules_guard.sort_by_key(|r| r.rule.weight);
         debug!("Rule updated: {:?}", rules_guard);
     }
+    // Apply the new limits right away, keeping the tracked history.
+    app_state
+        .store
+        .reconfigure(rule.id, rule.limiter_config())
+        .await?;
     Ok(ApiResponse::new(
         StatusCode::OK,
         "Rule updated",
@@ -189,6 +491,87 @@ pub async fn update_handler(
     ))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ReorderParams {
+    pub ids: Vec<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveParams {
+    pub id: i32,
+    pub anchor: i32,
+    pub placement: Placement,
+}
+
+/// Rewrites the weights of all rules following the given order.
+///
+/// The weights are updated in a single transaction and the in‑memory cache
+/// is replaced in one step, so `shuul()` never sees a half‑sorted rule set.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool, cache).
+///   - `params`: Every rule id, in the desired evaluation order.
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the reordered rules or an error.
+pub async fn reorder_handler(
+    State(app_state): State<Arc<AppState>>,
+    Json(params): Json<ReorderParams>,
+) -> Result<impl IntoResponse, AppError> {
+    debug!("Reorder params: {:?}", params);
+    let rules = apply_order(&app_state, &params.ids).await?;
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Rules reordered",
+        Data::Some(serde_json::to_value(rules)?),
+    ))
+}
+
+/// Moves a rule right before or after another one, renumbering all weights.
+///
+/// * **Parameters**
+///   - `app_state`: Shared state (DB pool, cache).
+///   - `params`: Rule to move, the `anchor` rule and the `placement` (`before` or `after`).
+/// * **Returns**
+///   - `Result<impl IntoResponse, AppError>` – JSON with the reordered rules or an error.
+pub async fn move_handler(
+    State(app_state): State<Arc<AppState>>,
+    Json(params): Json<MoveParams>,
+) -> Result<impl IntoResponse, AppError> {
+    debug!("Move params: {:?}", params);
+    let order = Rule::read_order(&app_state.pool).await?;
+    let order = place(&order, params.id, params.anchor, params.placement).ok_or_else(|| {
+        AppError::InvalidInput("id and anchor must be two different existing rules".to_string())
+    })?;
+    let rules = apply_order(&app_state, &order).await?;
+    Ok(ApiResponse::new(
+        StatusCode::OK,
+        "Rule moved",
+        Data::Some(serde_json::to_value(rules)?),
+    ))
+}
+
+/// Persists a new rule order and swaps in a freshly sorted cache.
+async fn apply_order(app_state: &AppState, ids: &[i32]) -> Result<Vec<Rule>, AppError> {
+    let rules = Rule::reorder(&app_state.pool, ids).await?.ok_or_else(|| {
+        AppError::InvalidInput("ids must contain every rule exactly once".to_string())
+    })?;
+    let active: Vec<CacheRule> = rules
+        .iter()
+        .filter(|r| r.active)
+        .cloned()
+        .map(CacheRule::from)
+        .collect();
+    {
+        let mut rules_guard = app_state
+            .rules
+            .lock()
+            .map_err(|_| AppError::CachePoisoned)?;
+        *rules_guard = active;
+    }
+    debug!("Rules reordered: {:?}", ids);
+    Ok(rules)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct DeleteParams {
     id: Option<i32>,
@@ -1,6 +1,7 @@
 //! # Endpoints de configuración
 //!
-//! Permite leer y actualizar la configuración de retención de datos.
+//! Permite leer y actualizar la configuración de retención de datos y la
+//! política del jail de reincidentes ([`RecidivePolicy`]).
 //! La configuración se almacena en la tabla `settings` de PostgreSQL.
 
 use axum::{Json, Router, extract::State, http::StatusCode, response::IntoResponse, routing};
@@ -8,16 +9,18 @@ use serde::{Deserialize, Serialize};
 use sqlx::Row;
 use std::sync::Arc;
 
-use crate::models::{ApiResponse, AppState, Data, error::AppError};
+use crate::models::{ApiResponse, AppState, Data, RecidivePolicy, error::AppError};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Settings {
     pub log_retention_days: i32,
+    pub recidive: RecidivePolicy,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateSettings {
     pub log_retention_days: Option<i32>,
+    pub recidive: Option<RecidivePolicy>,
 }
 
 pub fn settings_router() -> Router<Arc<AppState>> {
@@ -40,7 +43,9 @@ pub async fn get_settings(
         .and_then(|v| v.parse().ok())
         .unwrap_or(30);
 
-    let settings = Settings { log_retention_days: days };
+    let recidive = RecidivePolicy::load(&app_state.pool).await?;
+
+    let settings = Settings { log_retention_days: days, recidive };
     Ok(ApiResponse::new(StatusCode::OK, "Settings", Data::Some(serde_json::to_value(settings).map_err(AppError::from)?)))
 }
 
@@ -49,6 +54,9 @@ pub async fn update_settings(
     State(app_state): State<Arc<AppState>>,
     Json(update): Json<UpdateSettings>,
 ) -> Result<impl IntoResponse, AppError> {
+    if let Some(recidive) = &update.recidive {
+        recidive.validate().map_err(AppError::InvalidInput)?;
+    }
     if let Some(days) = update.log_retention_days {
         if days < 1 || days > 365 {
             return Err(AppError::InvalidInput("log_retention_days must be between 1 and 365".to_string()));
@@ -63,6 +71,10 @@ pub async fn update_settings(
         .map_err(AppError::from)?;
     }
 
+    if let Some(recidive) = update.recidive {
+        recidive.save(&app_state.pool).await?;
+    }
+
     let settings = get_settings(State(app_state)).await?;
     Ok(settings)
 }
\ No newline at end of file
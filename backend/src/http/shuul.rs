@@ -2,131 +2,606 @@
 //!
 //! Pipeline extendido:
 //! 1. Extraer request de headers
-//! 2. Check: ¿IP baneada? → 403
-//! 3. Rate limiter: ¿IP excede threshold? → Ban + 403
-//! 4. Reglas estáticas (allow/deny)
-//! 5. Persistir si la regla lo indica
+//! 2. Listas globales: IP en la lista de permitidos → 200 sin mirar bans,
+//!    rate limits ni reglas; en la de bloqueados o en un feed de bloqueo → 403
+//! 3. Decisiones de `CrowdSec`: `ban` → 403, `captcha` → challenge
+//! 4. Check: ¿IP, rango, ASN, país o clave de rate limit baneados? → 403
+//!
+//!    Las peticiones rechazadas en los pasos 2 a 4 se guardan como `deny` o
+//!    `banned` si la regla que baneó guarda peticiones (o no hay regla), y
+//!    como mucho una por cliente y minuto.
+//! 5. Reglas en orden de peso (una regla con feed sólo coincide si la IP está
+//!    en él); para cada regla que coincide:
+//!    - Rate limiter: ¿la clave de la regla excede threshold? → Ban + 403
+//!      (o 429 sin ban si la regla usa GCRA; `over_limit_status` lo cambia).
+//!      Si el sujeto baneado acumula bastantes bans, el jail de reincidentes
+//!      lo banea globalmente.
+//!    - Límite agregado: si la regla está en enfriamiento → deny o challenge
+//!      (sin banear a nadie)
+//!    - `log_only` / `rate_limit_only`: se anota y se sigue evaluando
+//!    - Resto de acciones: deciden la respuesta y paran la evaluación
+//! 6. Persistir si la regla lo indica, con la decisión tomada (`allow`,
+//!    `deny`, `rate_limited` o `monitor`) y su motivo
+//! 7. Responder según la acción (allow, deny, tarpit, challenge) o 429
+//!
+//! Las respuestas de reglas con rate limit llevan las cabeceras
+//! `RateLimit-Limit`, `RateLimit-Remaining` y `RateLimit-Reset`
+//! (draft-ietf-httpapi-ratelimit-headers) de la cuota más ajustada, y las
+//! 429 además `Retry-After`.
 
-use crate::models::{AppState, EmptyResponse, NewRequest, RateLimiter, Request};
+use crate::models::{
+    AbuseReport, AppState, CacheRule, EmptyResponse, IpListKind, MAX_TARPIT_SECONDS, NewRequest,
+    RateLimitAlgorithm, RateLimitStatus, RecidivePolicy, Request, RequestDecision, Rule,
+    RuleAction, Subject,
+};
 use axum::{
     Router,
+    body::Body,
     extract::State,
-    http::StatusCode,
-    response::IntoResponse,
+    http::{
+        HeaderName, HeaderValue, StatusCode,
+        header::{LOCATION, RETRY_AFTER},
+    },
+    response::{IntoResponse, Response},
     routing,
 };
+use chrono::Utc;
 use std::mem;
 use std::net::IpAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::{debug, error};
 
 pub fn shuul_router() -> Router<Arc<AppState>> {
     Router::new().route("/", routing::any(shuul))
 }
 
+/// Outcome of evaluating the rules for a request.
+#[derive(Debug, Default)]
+struct Decision {
+    action: RuleAction,
+    /// Status to answer because a rate limit was exceeded.
+    over_limit: Option<StatusCode>,
+    tarpit_seconds: u64,
+    challenge_url: Option<String>,
+}
+
 /// Main entry point for the shuul service.
 pub async fn shuul(
     State(app_state): State<Arc<AppState>>,
     headers: axum::http::HeaderMap,
 ) -> impl IntoResponse {
-    let mut request = NewRequest::from_request(&headers, &app_state.maxmind_db);
+    let mut request =
+        NewRequest::from_request(&headers, &app_state.maxmind_db, app_state.asn_db.as_ref());
     debug!("Captured request: {:?}", request);
 
-    // ── Step 1: Check if IP is actively banned ──
-    if let Some(ip_str) = &request.ip_address {
-        if let Ok(ip) = ip_str.parse::<IpAddr>() {
-            if let Ok(ban_manager) = app_state.ban_manager.lock() {
-                if let Some(ban) = ban_manager.is_banned(&ip) {
-                    debug!("IP {} is banned (reason: {})", ip, ban.reason);
-                    return EmptyResponse::create(
-                        StatusCode::FORBIDDEN,
-                        &format!("Banned: {}", ban.reason),
-                    );
-                }
-            } else {
-                error!("Ban manager mutex poisoned");
-            }
-        }
+    // ── Step 1: Global allowlist and denylist ──
+    if let Some(response) = listed_response(&app_state, &request).await {
+        return response;
+    }
+
+    // ── Step 2: CrowdSec decisions ──
+    if let Some(response) = crowdsec_response(&app_state, &request).await {
+        return response;
     }
 
-    // ── Step 2: Match against cached rules ──
-    let mut allow = true;
+    // ── Step 3: Check if the client is actively banned ──
+    if let Some(response) = banned_response(&app_state, &request, &headers).await {
+        return response;
+    }
+
+    // ── Step 4: Match against cached rules ──
+    let mut decision = Decision::default();
     let mut save = true;
+    let mut force_save = false;
+    let mut quota: Option<RateLimitStatus> = None;
 
-    if let Ok(rules) = app_state.rules.lock() {
-        for cache_rule in rules.iter() {
-            if cache_rule.matches(&request) {
-                request.rule_id = Some(cache_rule.rule.id);
-                debug!("Selected rule: {:?}", cache_rule.rule);
-                save = cache_rule.rule.store;
-                allow = cache_rule.rule.allow;
-
-                // ── Step 3: Rate limiter check ──
-                if cache_rule.rule.rate_limit_enabled {
-                    if let Some(ip_str) = &request.ip_address {
-                        if let Ok(ip) = ip_str.parse::<IpAddr>() {
-let should_ban = {
-                                    if let Ok(mut rate_limiters) = app_state.rate_limiter.lock() {
-                                        let rl = rate_limiters
-                                            .entry(cache_rule.rule.id)
-                                            .or_insert_with(|| RateLimiter::new(
-                                                cache_rule.rule.max_retry as u32,
-                                                cache_rule.rule.find_time_seconds,
-                                            ));
-                                        rl.record(ip)
-                                    } else {
-                                        error!("Rate limiter mutex poisoned");
-                                        false
-                                    }
-                                };
-
-                            if should_ban {
-                                debug!(
-                                    "IP {} exceeded rate limit for rule {}, banning",
-                                    ip, cache_rule.rule.id
-                                );
-                                if let Ok(mut ban_manager) = app_state.ban_manager.lock() {
-                                    ban_manager.ban(
-                                        ip,
-                                        Some(cache_rule.rule.id),
-                                        format!(
-                                            "Rate limit: {} requests in {}s",
-                                            cache_rule.rule.max_retry,
-                                            cache_rule.rule.find_time_seconds
-                                        ),
-                                        None,
-                                    );
-                                } else {
-                                    error!("Ban manager mutex poisoned during rate limit ban");
-                                }
-                                allow = false;
-                            }
-                        }
-                    }
-                }
+    for rule in matching_rules(&app_state, &request, |_| true) {
+        debug!("Selected rule: {:?}", rule);
 
+        // ── Step 5: Rate limiter check ──
+        let status = if rule.rate_limit_enabled {
+            record_attempt(&app_state, &request, &headers, &rule).await
+        } else {
+            None
+        };
+        if let Some(status) = status {
+            // Report the quota closest to running out.
+            if quota.is_none_or(|quota| status.remaining < quota.remaining) {
+                quota = Some(status);
+            }
+            if status.exceeded {
+                quota = Some(status);
+                request.rule_id = Some(rule.id);
+                request.decision = RequestDecision::RateLimited;
+                request.reason = Some("Rate limit exceeded".to_string());
+                save = rule.store;
+                decision.over_limit = Some(rule.over_limit_status());
                 break;
             }
         }
+
+        if let Some(action) = aggregate_cooldown(&app_state, &rule).await {
+            request.rule_id = Some(rule.id);
+            request.decision = RequestDecision::Deny;
+            request.reason = Some(format!("Aggregate limit exceeded ({})", action.as_str()));
+            save = rule.store;
+            decision.action = action;
+            decision.challenge_url.clone_from(&rule.challenge_url);
+            break;
+        }
+
+        match rule.action {
+            RuleAction::LogOnly => {
+                let tag = rule
+                    .tag
+                    .clone()
+                    .unwrap_or_else(|| format!("rule:{}", rule.id));
+                debug!("Log-only rule {} tagged request as {}", rule.id, tag);
+                request.tags.push(tag);
+                request.decision = RequestDecision::Monitor;
+                request.reason = Some(rule.action.as_str().to_string());
+                force_save |= rule.store;
+            },
+            RuleAction::RateLimitOnly => {
+                debug!("Rate-limit-only rule {} counted request", rule.id);
+                request.decision = RequestDecision::Monitor;
+                request.reason = Some(rule.action.as_str().to_string());
+            },
+            action => {
+                request.rule_id = Some(rule.id);
+                request.decision = RequestDecision::from_action(action);
+                request.reason = Some(action.as_str().to_string());
+                save = rule.store;
+                decision.action = action;
+                decision.tarpit_seconds =
+                    u64::try_from(rule.tarpit_seconds.min(MAX_TARPIT_SECONDS)).unwrap_or(0);
+                decision.challenge_url.clone_from(&rule.challenge_url);
+                break;
+            },
+        }
     }
 
     if request.rule_id.is_none() {
         debug!("No matching rule found for request: {:?}", &request);
     }
 
-    // ── Step 4: Persist the request if the rule says so ──
-    if save {
+    // ── Step 6: Persist the request if the rule says so ──
+    let original_url = original_url(&request);
+    if save || force_save {
         debug!("Saving request as per rule configuration");
         save_on_cache_or_db(&app_state, request).await;
     } else {
         debug!("Not saving request as per rule configuration");
     }
 
-    if allow {
-        EmptyResponse::create(StatusCode::OK, "Ok")
-    } else {
-        EmptyResponse::create(StatusCode::FORBIDDEN, "Ko")
+    // ── Step 7: Respond according to the action ──
+    respond(decision, quota, original_url.as_deref()).await
+}
+
+/// Response for a client on the global allowlist or denylist, or listed in a
+/// deny feed, if it is. The allowlist also wins over the feeds.
+async fn listed_response(app_state: &AppState, request: &NewRequest) -> Option<Response<Body>> {
+    let ip = client_ip(request)?;
+    let listed = app_state
+        .ip_lists
+        .lock()
+        .map_err(|_| error!("IP lists mutex poisoned"))
+        .ok()?
+        .check(&ip);
+    if let Some(listed) = listed {
+        debug!("Client {} is on the {} list", ip, listed);
+        return Some(match listed {
+            IpListKind::Allow => EmptyResponse::create(StatusCode::OK, "Ok"),
+            IpListKind::Deny => {
+                let reason = "Denylisted".to_string();
+                save_rejection(app_state, request, None, RequestDecision::Deny, reason).await;
+                EmptyResponse::create(StatusCode::FORBIDDEN, "Denylisted")
+            },
+        });
+    }
+    let feed = app_state
+        .feeds
+        .lock()
+        .map_err(|_| error!("Feeds mutex poisoned"))
+        .ok()?
+        .denying(&ip)?;
+    debug!("Client {} is listed in feed {}", ip, feed);
+    let reason = format!("Blocklisted by feed {feed}");
+    save_rejection(app_state, request, None, RequestDecision::Deny, reason).await;
+    Some(EmptyResponse::create(StatusCode::FORBIDDEN, "Blocklisted"))
+}
+
+/// Response for a banned client, if it is.
+async fn banned_response(
+    app_state: &AppState,
+    request: &NewRequest,
+    headers: &axum::http::HeaderMap,
+) -> Option<Response<Body>> {
+    let subjects = ban_subjects(app_state, request, headers);
+    let ban = app_state
+        .store
+        .find_ban(&subjects)
+        .await
+        .inspect_err(|e| error!("Cannot check bans: {e}"))
+        .ok()??;
+    debug!("Client {:?} is banned (reason: {})", subjects, ban.reason);
+    save_rejection(
+        app_state,
+        request,
+        ban.rule_id,
+        RequestDecision::Banned,
+        ban.reason.clone(),
+    )
+    .await;
+    let message = format!("Banned: {}", ban.reason);
+    Some(
+        if ban_status(app_state, ban.rule_id) == StatusCode::TOO_MANY_REQUESTS {
+            let response = EmptyResponse::create(StatusCode::TOO_MANY_REQUESTS, &message);
+            with_rate_limit_headers(response, None, ban.time_remaining())
+        } else {
+            EmptyResponse::create(StatusCode::FORBIDDEN, &message)
+        },
+    )
+}
+
+/// Response for a client covered by a `CrowdSec` decision, if it is.
+async fn crowdsec_response(app_state: &AppState, request: &NewRequest) -> Option<Response<Body>> {
+    let (response, decision, reason) = {
+        let decisions = app_state
+            .crowdsec
+            .lock()
+            .map_err(|_| error!("CrowdSec decisions mutex poisoned"))
+            .ok()?;
+        let ip = client_ip(request);
+        let decision = decisions.find(
+            ip.as_ref(),
+            request.asn,
+            request.country_code.as_deref(),
+            Utc::now(),
+        )?;
+        debug!("Client {:?} has CrowdSec decision {:?}", ip, decision);
+        match (decision.action, decisions.captcha_url()) {
+            (RuleAction::Challenge, Some(url)) => (
+                challenge_redirect(url, original_url(request).as_deref()),
+                RequestDecision::Deny,
+                format!("Challenged: {}", decision.reason()),
+            ),
+            _ => (
+                EmptyResponse::create(
+                    StatusCode::FORBIDDEN,
+                    &format!("Banned: {}", decision.reason()),
+                ),
+                RequestDecision::Banned,
+                decision.reason(),
+            ),
+        }
+    };
+    save_rejection(app_state, request, None, decision, reason).await;
+    Some(response)
+}
+
+/// Saves a request rejected before the rules, with its `decision` and
+/// `reason`. It is skipped if the rule behind it (`rule_id`) doesn't store
+/// requests, and sampled to one per client and minute so a banned client
+/// costs few writes.
+async fn save_rejection(
+    app_state: &AppState,
+    request: &NewRequest,
+    rule_id: Option<i32>,
+    decision: RequestDecision,
+    reason: String,
+) {
+    let store = rule_id
+        .and_then(|rule_id| cached_rule(app_state, rule_id, |rule| rule.store))
+        .unwrap_or(true);
+    let sampled = store
+        && app_state.rejections.lock().is_ok_and(|mut rejections| {
+            rejections.allow(
+                request.ip_address.as_deref().unwrap_or_default(),
+                Instant::now(),
+            )
+        });
+    if !sampled {
+        debug!("Not saving rejected request");
+        return;
+    }
+    let mut rejected = request.clone();
+    rejected.rule_id = rule_id;
+    rejected.decision = decision;
+    rejected.reason = Some(reason);
+    save_on_cache_or_db(app_state, rejected).await;
+}
+
+/// Builds the response for the rules' decision, with the rate-limit headers
+/// of `quota`.
+async fn respond(
+    decision: Decision,
+    quota: Option<RateLimitStatus>,
+    original_url: Option<&str>,
+) -> Response<Body> {
+    if let Some(status) = decision.over_limit {
+        let response = if status == StatusCode::TOO_MANY_REQUESTS {
+            EmptyResponse::create(status, "Too many requests")
+        } else {
+            EmptyResponse::create(status, "Ko")
+        };
+        let retry_after = quota
+            .filter(|_| status == StatusCode::TOO_MANY_REQUESTS)
+            .map(|quota| quota.reset);
+        return with_rate_limit_headers(response, quota.as_ref(), retry_after);
+    }
+    let response = match decision.action {
+        RuleAction::Tarpit => {
+            debug!("Tarpitting request for {}s", decision.tarpit_seconds);
+            tokio::time::sleep(Duration::from_secs(decision.tarpit_seconds)).await;
+            EmptyResponse::create(StatusCode::FORBIDDEN, "Ko")
+        },
+        RuleAction::Challenge => decision.challenge_url.map_or_else(
+            || {
+                error!("Challenge rule without challenge_url, denying request");
+                EmptyResponse::create(StatusCode::FORBIDDEN, "Ko")
+            },
+            |url| challenge_redirect(&url, original_url),
+        ),
+        RuleAction::Deny => EmptyResponse::create(StatusCode::FORBIDDEN, "Ko"),
+        RuleAction::Allow | RuleAction::LogOnly | RuleAction::RateLimitOnly => {
+            EmptyResponse::create(StatusCode::OK, "Ok")
+        },
+    };
+    with_rate_limit_headers(response, quota.as_ref(), None)
+}
+
+/// Counts the match towards the rule's aggregate limit. Returns the action
+/// that replaces the rule's own while it cools down.
+async fn aggregate_cooldown(app_state: &AppState, rule: &Rule) -> Option<RuleAction> {
+    let limit = rule.aggregate_limit()?;
+    let status = app_state
+        .store
+        .record_aggregate(rule.id, limit)
+        .await
+        .inspect_err(|e| error!("Cannot record aggregate hit: {e}"))
+        .ok()?;
+    let remaining = status.cooldown_remaining?;
+    debug!(
+        "Rule {} over its aggregate limit, {:?} for another {}s",
+        rule.id,
+        rule.aggregate_action,
+        remaining.as_secs()
+    );
+    Some(rule.aggregate_action)
+}
+
+/// Status answered to a client banned by `rule_id`: the rule's
+/// `over_limit_status`, or 403 for manual bans and deleted rules.
+fn ban_status(app_state: &AppState, rule_id: Option<i32>) -> StatusCode {
+    rule_id
+        .and_then(|rule_id| cached_rule(app_state, rule_id, Rule::over_limit_status))
+        .unwrap_or(StatusCode::FORBIDDEN)
+}
+
+/// Applies `f` to the cached rule `rule_id`; `None` if it was deleted.
+fn cached_rule<T>(app_state: &AppState, rule_id: i32, f: impl FnOnce(&Rule) -> T) -> Option<T> {
+    let rules = app_state.rules.lock().ok()?;
+    rules
+        .iter()
+        .find(|cache_rule| cache_rule.rule.id == rule_id)
+        .map(|cache_rule| f(&cache_rule.rule))
+}
+
+/// Adds the `RateLimit-*` headers for `quota` and, if given, `Retry-After`.
+fn with_rate_limit_headers(
+    mut response: Response<Body>,
+    quota: Option<&RateLimitStatus>,
+    retry_after: Option<Duration>,
+) -> Response<Body> {
+    let headers = response.headers_mut();
+    if let Some(quota) = quota {
+        headers.insert(
+            HeaderName::from_static("ratelimit-limit"),
+            HeaderValue::from(quota.limit),
+        );
+        headers.insert(
+            HeaderName::from_static("ratelimit-remaining"),
+            HeaderValue::from(quota.remaining),
+        );
+        headers.insert(
+            HeaderName::from_static("ratelimit-reset"),
+            HeaderValue::from(ceil_seconds(quota.reset)),
+        );
+    }
+    if let Some(retry_after) = retry_after {
+        headers.insert(RETRY_AFTER, HeaderValue::from(ceil_seconds(retry_after)));
+    }
+    response
+}
+
+/// Whole seconds, rounded up so clients don't retry too early.
+fn ceil_seconds(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+/// Client address of `request`, if it has a valid one.
+fn client_ip(request: &NewRequest) -> Option<IpAddr> {
+    request
+        .ip_address
+        .as_ref()
+        .and_then(|ip_str| ip_str.parse::<IpAddr>().ok())
+}
+
+/// Subjects a ban may target for this request: the client IP (which also
+/// covers banned ranges), its ASN, its country and the rate-limit key of every rule that
+/// bans by something other than the IP.
+fn ban_subjects(
+    app_state: &AppState,
+    request: &NewRequest,
+    headers: &axum::http::HeaderMap,
+) -> Vec<Subject> {
+    let mut subjects = Vec::new();
+    if let Some(ip) = client_ip(request) {
+        subjects.push(Subject::Ip(ip));
+    }
+    if let Some(asn) = request.asn {
+        subjects.push(Subject::Asn(asn));
+    }
+    if let Some(code) = &request.country_code {
+        subjects.push(Subject::Country(code.to_ascii_uppercase()));
+    }
+    if let Ok(rules) = app_state.rules.lock() {
+        for cache_rule in rules.iter().filter(|r| {
+            r.rule.rate_limit_enabled
+                && r.rule.rate_limit_algorithm == RateLimitAlgorithm::SlidingWindow
+        }) {
+            let key_spec = cache_rule.rule.key_spec();
+            if key_spec.is_ip() {
+                continue;
+            }
+            if let Some(subject) = key_spec.subject(request, headers) {
+                if !subjects.contains(&subject) {
+                    subjects.push(subject);
+                }
+            }
+        }
     }
+    subjects
+}
+
+/// Rules matching `request`, in weight order, among those accepted by
+/// `filter`. The rules are cloned so the cache lock isn't held across awaits.
+pub fn matching_rules(
+    app_state: &AppState,
+    request: &NewRequest,
+    filter: impl Fn(&CacheRule) -> bool,
+) -> Vec<Rule> {
+    let Ok(rules) = app_state.rules.lock() else {
+        error!("Rules mutex poisoned");
+        return Vec::new();
+    };
+    let Ok(feeds) = app_state.feeds.lock() else {
+        error!("Feeds mutex poisoned");
+        return Vec::new();
+    };
+    let ip = client_ip(request);
+    rules
+        .iter()
+        .filter(|cache_rule| filter(cache_rule) && cache_rule.matches(request))
+        .filter(|cache_rule| {
+            cache_rule.rule.feed_id.is_none_or(|feed_id| {
+                ip.as_ref().is_some_and(|ip| feeds.contains(feed_id, ip))
+            })
+        })
+        .map(|cache_rule| cache_rule.rule.clone())
+        .collect()
+}
+
+/// Records a rate-limited hit under the rule's key and returns the key's
+/// quota, or `None` if the request has no such key. Keys over the limit are
+/// banned by sliding window rules (the quota then resets when the ban ends);
+/// GCRA rules only deny the request.
+pub async fn record_attempt(
+    app_state: &AppState,
+    request: &NewRequest,
+    headers: &axum::http::HeaderMap,
+    rule: &Rule,
+) -> Option<RateLimitStatus> {
+    let subject = rule.key_spec().subject(request, headers)?;
+    let mut status = app_state
+        .store
+        .record_hit(rule.id, rule.limiter_config(), &subject)
+        .await
+        .inspect_err(|e| error!("Cannot record rate limit hit: {e}"))
+        .ok()?;
+    if status.exceeded && rule.rate_limit_algorithm == RateLimitAlgorithm::Gcra {
+        debug!("{} throttled by rule {}", subject, rule.id);
+    } else if status.exceeded {
+        debug!(
+            "{} exceeded rate limit for rule {}, banning",
+            subject, rule.id
+        );
+        let reason = format!(
+            "Rate limit: {} requests in {}s",
+            rule.max_retry, rule.find_time_seconds
+        );
+        match app_state
+            .store
+            .ban(
+                subject.clone(),
+                Some(rule.id),
+                reason.clone(),
+                None,
+                Vec::new(),
+            )
+            .await
+        {
+            Ok(ban) => {
+                // A permanent ban keeps the limiter's reset.
+                if let Some(remaining) = ban.time_remaining() {
+                    status.reset = remaining;
+                }
+                report_abuse(app_state, request, &subject, rule, reason);
+                if let Err(e) =
+                    RecidivePolicy::apply(&app_state.pool, app_state.store.as_ref(), &subject).await
+                {
+                    error!("Cannot apply the recidive policy: {e}");
+                }
+            },
+            Err(e) => error!("Cannot store rate limit ban: {e}"),
+        }
+    }
+    Some(status)
+}
+
+/// Queues an `AbuseIPDB` report of the banned client if the rule has
+/// categories and a reporter is configured. Only a ban of the client's own
+/// IP is reported, never one of its range, ASN, country or key.
+fn report_abuse(
+    app_state: &AppState,
+    request: &NewRequest,
+    banned: &Subject,
+    rule: &Rule,
+    reason: String,
+) {
+    let Some(reporter) = &app_state.abuse_reporter else {
+        return;
+    };
+    let report = client_ip(request)
+        .filter(|ip| *banned == Subject::Ip(*ip))
+        .and_then(|ip| AbuseReport::new(ip, &rule.abuseipdb_categories, reason));
+    if let Some(report) = report {
+        reporter.report(report);
+    }
+}
+
+/// Rebuilds the URL the client originally asked for from the forwarded headers.
+fn original_url(request: &NewRequest) -> Option<String> {
+    let fqdn = request.fqdn.as_ref()?;
+    let protocol = request.protocol.as_deref().unwrap_or("https");
+    let path = request.path.as_deref().unwrap_or("/");
+    let mut url = format!("{protocol}://{fqdn}{path}");
+    if let Some(query) = &request.query {
+        url.push('?');
+        url.push_str(query);
+    }
+    Some(url)
+}
+
+/// Redirects the client to the challenge page, passing the original URL in `rd`.
+fn challenge_redirect(challenge_url: &str, original_url: Option<&str>) -> Response<Body> {
+    let separator = if challenge_url.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    let location = original_url.map_or_else(
+        || challenge_url.to_string(),
+        |url| format!("{challenge_url}{separator}rd={}", urlencoding::encode(url)),
+    );
+    debug!("Challenging request, redirecting to {}", location);
+    Response::builder()
+        .status(StatusCode::FOUND)
+        .header(LOCATION, location)
+        .body(Body::empty())
+        .unwrap_or_else(|_| EmptyResponse::create(StatusCode::FORBIDDEN, "Ko"))
 }
 
 /// Saves a request either to the in-memory cache or directly to the database.
@@ -160,4 +635,4 @@ async fn save_on_cache_or_db(app_state: &AppState, request: NewRequest) {
             Err(e) => error!("Error saving request to database: {:?}", e),
         }
     }
-}
\ No newline at end of file
+}
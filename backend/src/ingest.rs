@@ -0,0 +1,212 @@
+//! # Ingesta de access logs
+//!
+//! Sigue (`tail -F`) ficheros de access log locales y pasa cada línea por las
+//! reglas que tienen condición de `status_code`. Los aciertos alimentan el
+//! [`StateStore`](crate::models::StateStore) igual que en forward auth, así
+//! que el ban por fallos reales se aplica en la siguiente llamada a `shuul`.
+//!
+//! Se configura con `ACCESS_LOGS`, una lista separada por comas de
+//! `formato:ruta`, por ejemplo
+//! `nginx:/var/log/nginx/access.log,traefik:/var/log/traefik/access.json`.
+//!
+//! La lectura empieza al final del fichero; si el fichero se rota (cambia el
+//! inode) o se trunca, se vuelve a leer desde el principio.
+
+use crate::http::{matching_rules, record_attempt};
+use crate::models::{AccessLogEntry, AppState, LogFormat, NewRequest};
+use axum::http::HeaderMap;
+use std::io::SeekFrom;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::{debug, error, info};
+
+/// How often the log files are checked for new lines.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Maximum bytes read from a file per poll.
+const MAX_READ_BYTES: u64 = 1024 * 1024;
+
+/// An access log file and its format.
+#[derive(Debug, Clone)]
+pub struct LogSource {
+    format: LogFormat,
+    path: PathBuf,
+}
+
+impl FromStr for LogSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid access log source (expected format:path): {s}"))?;
+        Ok(Self {
+            format: format.parse()?,
+            path: PathBuf::from(path.trim()),
+        })
+    }
+}
+
+/// Parses the `ACCESS_LOGS` value, skipping (and logging) invalid entries.
+pub fn parse_sources(value: &str) -> Vec<LogSource> {
+    value
+        .split(',')
+        .filter(|source| !source.trim().is_empty())
+        .filter_map(|source| {
+            source
+                .parse()
+                .inspect_err(|e| error!("Ignoring access log source: {e}"))
+                .ok()
+        })
+        .collect()
+}
+
+/// Spawns one tailing task per log source.
+pub fn spawn(app_state: &Arc<AppState>, sources: Vec<LogSource>) {
+    for source in sources {
+        info!(
+            "Ingesting {:?} access log from {}",
+            source.format,
+            source.path.display()
+        );
+        let app_state = Arc::clone(app_state);
+        tokio::spawn(async move { tail(&app_state, source).await });
+    }
+}
+
+/// Read position in a followed file.
+#[derive(Debug, Default)]
+struct TailState {
+    inode: Option<u64>,
+    offset: u64,
+    /// Trailing bytes of the last read that didn't end in a newline yet.
+    partial: Vec<u8>,
+}
+
+async fn tail(app_state: &AppState, source: LogSource) {
+    let mut state = TailState::default();
+    // Skip existing content: only new lines count.
+    if let Ok(metadata) = tokio::fs::metadata(&source.path).await {
+        state.inode = Some(metadata.ino());
+        state.offset = metadata.len();
+    }
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        match read_new_lines(&source, &mut state).await {
+            Ok(lines) => {
+                for line in lines {
+                    process_line(app_state, source.format, &line).await;
+                }
+            },
+            Err(e) => debug!("Cannot read access log {}: {e}", source.path.display()),
+        }
+    }
+}
+
+/// Reads the complete lines appended since the last call.
+async fn read_new_lines(source: &LogSource, state: &mut TailState) -> std::io::Result<Vec<String>> {
+    let metadata = tokio::fs::metadata(&source.path).await?;
+    if state.inode != Some(metadata.ino()) || metadata.len() < state.offset {
+        if state.inode.is_some() {
+            info!(
+                "Access log {} rotated, reading from the start",
+                source.path.display()
+            );
+        }
+        state.inode = Some(metadata.ino());
+        state.offset = 0;
+        state.partial.clear();
+    }
+    if metadata.len() == state.offset {
+        return Ok(Vec::new());
+    }
+    let mut file = File::open(&source.path).await?;
+    file.seek(SeekFrom::Start(state.offset)).await?;
+    let mut buffer = std::mem::take(&mut state.partial);
+    let read = file.take(MAX_READ_BYTES).read_to_end(&mut buffer).await?;
+    state.offset += read as u64;
+
+    let complete = buffer
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |i| i + 1);
+    state.partial = buffer.split_off(complete);
+    Ok(String::from_utf8_lossy(&buffer)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Matches a log line against the status rules, in weight order, and counts
+/// it in the rate limiter of the rules that match.
+async fn process_line(app_state: &AppState, format: LogFormat, line: &str) {
+    let Some(entry) = AccessLogEntry::parse(format, line) else {
+        debug!("Unparseable {:?} access log line: {}", format, line);
+        return;
+    };
+    let request =
+        NewRequest::from_access_log(entry, &app_state.maxmind_db, app_state.asn_db.as_ref());
+    let headers = HeaderMap::new();
+    let rules = matching_rules(app_state, &request, |cache_rule| {
+        cache_rule.status_code.is_some()
+    });
+    for rule in rules {
+        debug!("Access log line matched rule {}: {:?}", rule.id, request);
+        if rule.rate_limit_enabled
+            && record_attempt(app_state, &request, &headers, &rule)
+                .await
+                .is_some_and(|status| status.exceeded)
+        {
+            break;
+        }
+        if rule.action.is_terminal() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sources() {
+        let sources = parse_sources("nginx:/var/log/nginx/access.log, caddy:/logs/caddy.json,bad");
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].format, LogFormat::Nginx);
+        assert_eq!(sources[1].path, PathBuf::from("/logs/caddy.json"));
+    }
+
+    #[tokio::test]
+    async fn test_read_new_lines_handles_partial_lines_and_truncation() {
+        let path = std::env::temp_dir().join(format!("shuul-ingest-{}.log", std::process::id()));
+        std::fs::write(&path, "one\ntw").unwrap();
+        let source = LogSource {
+            format: LogFormat::Nginx,
+            path: path.clone(),
+        };
+        let mut state = TailState::default();
+
+        assert_eq!(
+            read_new_lines(&source, &mut state).await.unwrap(),
+            vec!["one"]
+        );
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        assert_eq!(
+            read_new_lines(&source, &mut state).await.unwrap(),
+            vec!["two", "three"]
+        );
+        std::fs::write(&path, "four\n").unwrap();
+        assert_eq!(
+            read_new_lines(&source, &mut state).await.unwrap(),
+            vec!["four"]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +1,7 @@
 //! # shuul — Backend Library
 //!
 //! Core types and models used by the shuul backend.
-//! Exported for integration tests and external consumers.
+//! Shared by the server binary, integration tests and external consumers.
 
 pub mod constants;
 pub mod models;
\ No newline at end of file
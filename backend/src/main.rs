@@ -12,11 +12,15 @@
 //! 5. Carga las reglas activas en memoria
 //! 6. Arranca el servidor Axum en `0.0.0.0:3000`
 
-mod constants;
+mod abuseipdb;
+mod crowdsec;
+mod feeds;
 mod http;
-mod models;
+mod ingest;
 mod templates;
 
+use backend::{constants, models};
+
 use axum::{
     Router,
     http::{
@@ -27,12 +31,17 @@ use axum::{
 };
 use dotenv::dotenv;
 use http::{
-    api_user_router, auth_router, ban_router, health_router, request_router, require_auth,
-    rule_router, settings_router, shuul_router, template_router, user_router, util_router,
+    api_user_router, auth_router, ban_router, bouncer_router, decisions_router, feed_router,
+    health_router, ip_list_router, request_router, require_auth, rule_router, settings_router,
+    shuul_router, template_router, user_router, util_router,
 };
 use maxminddb::Reader;
 use models::CacheRule;
-use models::{AppState, BanManager, Error, JwtValidator, OidcMetadata, RateLimiter};
+use models::{
+    AppState, BanManager, CrowdSecDecisions, DEFAULT_MAX_KEYS, Error, FeedSets, IpLists,
+    JwtValidator, MemoryStore, OidcMetadata, PostgresStore, RangeAggregation, RejectionSampler,
+    StateStore, StoreKind,
+};
 use sqlx::{
     migrate::{MigrateDatabase, Migrator},
     postgres::PgPoolOptions,
@@ -40,7 +49,7 @@ use sqlx::{
 };
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use std::{env::var, path::Path, str::FromStr};
+use std::{env::var, path::Path, str::FromStr, time::Duration};
 use tower_http::services::{ServeDir, ServeFile};
 use tower_http::{
     cors::{Any, CorsLayer},
@@ -72,6 +81,12 @@ async fn main() -> Result<(), Error> {
     info!("Port: {}", port);
     let maxmind_db_path = var("MAXMIND_DB_PATH").unwrap_or("geo/GeoLite2-City.mmdb".to_string());
     info!("Maxmin DB Path: {}", maxmind_db_path);
+    let asn_db = var("MAXMIND_ASN_DB_PATH").ok().and_then(|path| {
+        info!("Maxmind ASN DB Path: {}", path);
+        Reader::open_readfile(&path)
+            .inspect_err(|e| error!("Failed to open MaxMind ASN DB: {e}"))
+            .ok()
+    });
     let secret = var("SECRET").expect("SECRET environment variable is mandatory");
     debug!("Secret: {}", secret);
     let cache_enabled = var("CACHE_ENABLED")
@@ -143,14 +158,56 @@ async fn main() -> Result<(), Error> {
 
     let rules = Mutex::new(CacheRule::read_all_active(&pool).await.unwrap_or_default());
     let cache = Mutex::new(Vec::new());
-    let ban_manager = Mutex::new(BanManager::new(
+    let ip_lists = Mutex::new(IpLists::load(&pool).await.unwrap_or_default());
+    let ban_manager = BanManager::new(
         3600,    // default_ban_duration (1h)
         false,   // bantime_increment (per-rule config)
         vec![1, 2, 4, 8],
         604800,  // bantime_maxtime (1w)
         30,      // ban_count_decay_days
-    ));
-    let rate_limiter: Mutex<HashMap<i32, RateLimiter>> = Mutex::new(HashMap::new());
+    );
+    // Ban a whole /24 or /64 once BAN_RANGE_THRESHOLD of its IPs are banned
+    // within BAN_RANGE_WINDOW_SECONDS (disabled by default).
+    let ban_range_threshold = var("BAN_RANGE_THRESHOLD")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let ban_manager = if ban_range_threshold > 0 {
+        let window = var("BAN_RANGE_WINDOW_SECONDS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(3600);
+        info!("Range bans after {} IPs within {}s", ban_range_threshold, window);
+        ban_manager.with_range_aggregation(RangeAggregation {
+            threshold: ban_range_threshold,
+            window: Duration::from_secs(window),
+        })
+    } else {
+        ban_manager
+    };
+    let store_kind: StoreKind = var("STATE_STORE")
+        .ok()
+        .map(|value| value.parse().map_err(Error::Other))
+        .transpose()?
+        .unwrap_or_default();
+    let store: Box<dyn StateStore> = match store_kind {
+        StoreKind::Memory => {
+            let rate_limit_max_keys = var("RATE_LIMIT_MAX_KEYS")
+                .ok()
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or(DEFAULT_MAX_KEYS);
+            info!("Rate limiter max keys per rule: {}", rate_limit_max_keys);
+            let memory_store = MemoryStore::new(ban_manager, rate_limit_max_keys)
+                .with_persistence(pool.clone());
+            match memory_store.restore().await {
+                Ok(restored) => info!("Restored {} active bans", restored),
+                Err(e) => error!("Cannot restore bans: {}", e),
+            }
+            Box::new(memory_store)
+        },
+        StoreKind::Postgres => Box::new(PostgresStore::new(pool.clone(), ban_manager)),
+    };
+    info!("State store: {}", store.name());
 
     // ── OIDC / SSO Configuration ──
     let oidc_issuer_url = var("OIDC_ISSUER_URL").ok();
@@ -196,13 +253,18 @@ async fn main() -> Result<(), Error> {
         secret,
         maxmind_db: Reader::open_readfile(&maxmind_db_path)
             .map_err(|e| Error::Other(format!("Failed to open MaxMind DB: {e}")))?,
+        asn_db,
         static_dir: STATIC_DIR.to_string(),
         rules,
+        ip_lists,
+        feeds: Mutex::new(FeedSets::default()),
+        crowdsec: Mutex::new(CrowdSecDecisions::new(crowdsec::LapiConfig::captcha_url())),
+        abuse_reporter: abuseipdb::start(),
         cache,
+        rejections: Mutex::new(RejectionSampler::default()),
         cache_enabled,
         cache_size,
-        ban_manager,
-        rate_limiter,
+        store,
         oidc_metadata,
         jwt_validator,
         oidc_states: tokio::sync::Mutex::new(HashMap::new()),
@@ -210,25 +272,24 @@ async fn main() -> Result<(), Error> {
         oidc_redirect_url: Some(oidc_redirect_url),
     });
 
-    // Background task: cleanup expired bans every 60 seconds
+    // Background tasks: blocklist feeds, CrowdSec decisions and access log
+    // ingestion
+    feeds::spawn(&app_state);
+    if let Some(config) = crowdsec::LapiConfig::from_env() {
+        crowdsec::spawn(&app_state, config);
+    }
+    if let Ok(access_logs) = var("ACCESS_LOGS") {
+        ingest::spawn(&app_state, ingest::parse_sources(&access_logs));
+    }
+
+    // Background task: cleanup expired bans and rate limit counters every 60 seconds
     let cleanup_state = Arc::clone(&app_state);
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60));
         loop {
             interval.tick().await;
-            if let Ok(mut ban_manager) = cleanup_state.ban_manager.lock() {
-                let before = ban_manager.active_count();
-                ban_manager.cleanup_expired();
-                let after = ban_manager.active_count();
-                if before != after {
-                    debug!("Ban cleanup: {} → {} active bans", before, after);
-                }
-            }
-            if let Ok(mut rate_limiters) = cleanup_state.rate_limiter.lock() {
-                rate_limiters.retain(|_, rl| {
-                    rl.cleanup_expired();
-                    !rl.is_empty()
-                });
+            if let Err(e) = cleanup_state.store.cleanup().await {
+                error!("State cleanup failed: {e}");
             }
         }
     });
@@ -272,13 +333,20 @@ async fn main() -> Result<(), Error> {
         .nest("/requests", request_router())
         .nest("/rules", rule_router())
         .nest("/bans", ban_router())
+        .nest("/ip-lists", ip_list_router())
+        .nest("/feeds", feed_router())
+        .nest("/bouncers", bouncer_router())
         .nest("/templates", template_router())
         .nest("/settings", settings_router())
         .route_layer(axum_middleware::from_fn_with_state(app_state.clone(), require_auth))
-        .with_state(app_state);
+        .with_state(app_state.clone());
+
+    // CrowdSec-compatible decisions stream (bouncer API key auth)
+    let decisions_routes = decisions_router().with_state(app_state);
 
     let app = Router::new()
         .nest("/api/v1", api_routes)
+        .nest("/v1/decisions", decisions_routes)
         .fallback_service(ServeDir::new(STATIC_DIR).fallback(ServeFile::new("static/index.html")))
         .layer(TraceLayer::new_for_http())
         .layer(cors);
@@ -0,0 +1,131 @@
+//! # Reportes a `AbuseIPDB`
+//!
+//! Cola de reportes de IPs baneadas. `shuul()` encola un [`AbuseReport`] al
+//! banear por una regla con categorías de `AbuseIPDB` y una tarea en segundo
+//! plano los envía. `AbuseIPDB` rechaza reportar la misma IP más de una vez
+//! cada 15 minutos, así que [`ReportDedup`] descarta las repeticiones.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Minimum time between two reports of the same IP.
+pub const REPORT_INTERVAL: Duration = Duration::from_mins(15);
+/// Reports waiting to be sent; more are dropped.
+pub const QUEUE_CAPACITY: usize = 1000;
+
+/// A report of an IP to `AbuseIPDB`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbuseReport {
+    pub ip: IpAddr,
+    /// `AbuseIPDB` category ids (1 to 23).
+    pub categories: Vec<i32>,
+    pub comment: String,
+}
+
+impl AbuseReport {
+    /// Builds a report with the valid categories; `None` if there are none.
+    #[must_use]
+    pub fn new(ip: IpAddr, categories: &[i32], comment: String) -> Option<Self> {
+        let mut categories: Vec<i32> = categories
+            .iter()
+            .copied()
+            .filter(|category| (1..=23).contains(category))
+            .collect();
+        categories.sort_unstable();
+        categories.dedup();
+        (!categories.is_empty()).then_some(Self {
+            ip,
+            categories,
+            comment,
+        })
+    }
+
+    /// Categories as the comma separated list the API expects.
+    #[must_use]
+    pub fn categories_param(&self) -> String {
+        self.categories
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Sending side of the report queue, kept in the application state.
+#[derive(Debug, Clone)]
+pub struct AbuseReporter {
+    sender: mpsc::Sender<AbuseReport>,
+}
+
+impl AbuseReporter {
+    /// Creates the queue; the receiver goes to the sending task.
+    #[must_use]
+    pub fn channel() -> (Self, mpsc::Receiver<AbuseReport>) {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        (Self { sender }, receiver)
+    }
+
+    /// Queues a report without waiting; it is dropped if the queue is full.
+    pub fn report(&self, report: AbuseReport) {
+        if let Err(e) = self.sender.try_send(report) {
+            warn!("AbuseIPDB report dropped: {e}");
+        }
+    }
+}
+
+/// Last report time of each IP.
+#[derive(Debug, Default)]
+pub struct ReportDedup {
+    reported: HashMap<IpAddr, Instant>,
+}
+
+impl ReportDedup {
+    /// `true` if `ip` may be reported at `now`, which then counts as its last
+    /// report.
+    pub fn allow(&mut self, ip: IpAddr, now: Instant) -> bool {
+        self.reported
+            .retain(|_, reported| now.duration_since(*reported) < REPORT_INTERVAL);
+        if self.reported.contains_key(&ip) {
+            return false;
+        }
+        self.reported.insert(ip, now);
+        true
+    }
+
+    /// Drops the last report of `ip`, so a failed one can be retried.
+    pub fn forget(&mut self, ip: IpAddr) {
+        self.reported.remove(&ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_categories() {
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let report = AbuseReport::new(ip, &[21, 18, 0, 21, 99], "test".to_string()).unwrap();
+        assert_eq!(report.categories_param(), "18,21");
+        assert!(AbuseReport::new(ip, &[], "test".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_dedup_per_ip() {
+        let mut dedup = ReportDedup::default();
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(dedup.allow(a, now));
+        assert!(!dedup.allow(a, now + Duration::from_mins(14)));
+        assert!(dedup.allow(b, now + Duration::from_mins(14)));
+        assert!(dedup.allow(a, now + REPORT_INTERVAL));
+
+        dedup.forget(b);
+        assert!(dedup.allow(b, now + Duration::from_mins(15)));
+    }
+}
@@ -0,0 +1,178 @@
+//! # Access logs
+//!
+//! Parseo de líneas de access log para contar intentos fallidos como hace
+//! fail2ban. En forward auth no se conoce la respuesta del upstream, así que
+//! un login fallido (401, o 200 con página de error) sólo se ve en el log.
+//!
+//! Formatos soportados:
+//! - `nginx`: formato `combined` de nginx.
+//! - `traefik`: access log de Traefik en JSON.
+//! - `caddy`: access log de Caddy en JSON.
+
+use regex::Regex;
+use serde_json::Value;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+/// `$remote_addr - $remote_user [$time_local] "$request" $status ...`
+static NGINX_COMBINED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^(\S+) \S+ \S+ \[[^\]]*\] "(?:[A-Z]+ )?(\S+)(?: [^"]*)?" (\d{3}) "#)
+        .expect("valid nginx combined regex")
+});
+
+/// Access log line format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// nginx `combined` format.
+    Nginx,
+    /// Traefik JSON access log.
+    Traefik,
+    /// Caddy JSON access log.
+    Caddy,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "nginx" => Ok(Self::Nginx),
+            "traefik" => Ok(Self::Traefik),
+            "caddy" => Ok(Self::Caddy),
+            other => Err(format!("Unknown access log format: {other}")),
+        }
+    }
+}
+
+/// The parts of an access log line that rules can match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessLogEntry {
+    pub ip_address: String,
+    pub protocol: Option<String>,
+    pub fqdn: Option<String>,
+    pub path: String,
+    pub query: Option<String>,
+    pub status: u16,
+}
+
+impl AccessLogEntry {
+    /// Parses one log line. Returns `None` for lines that don't match the
+    /// format or lack the client IP, the URI or the status.
+    #[must_use]
+    pub fn parse(format: LogFormat, line: &str) -> Option<Self> {
+        match format {
+            LogFormat::Nginx => Self::parse_nginx(line),
+            LogFormat::Traefik => Self::parse_traefik(line),
+            LogFormat::Caddy => Self::parse_caddy(line),
+        }
+    }
+
+    fn parse_nginx(line: &str) -> Option<Self> {
+        let captures = NGINX_COMBINED.captures(line)?;
+        Some(Self::new(
+            captures[1].to_string(),
+            None,
+            None,
+            &captures[2],
+            captures[3].parse().ok()?,
+        ))
+    }
+
+    fn parse_traefik(line: &str) -> Option<Self> {
+        let json: Value = serde_json::from_str(line).ok()?;
+        let status = json
+            .get("DownstreamStatus")
+            .or_else(|| json.get("OriginStatus"))?
+            .as_u64()?;
+        Some(Self::new(
+            json.get("ClientHost")?.as_str()?.to_string(),
+            json.get("RequestScheme")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            json.get("RequestHost")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            json.get("RequestPath")?.as_str()?,
+            u16::try_from(status).ok()?,
+        ))
+    }
+
+    fn parse_caddy(line: &str) -> Option<Self> {
+        let json: Value = serde_json::from_str(line).ok()?;
+        let request = json.get("request")?;
+        let ip = request
+            .get("client_ip")
+            .or_else(|| request.get("remote_ip"))?
+            .as_str()?;
+        let protocol = if request.get("tls").is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        Some(Self::new(
+            ip.to_string(),
+            Some(protocol.to_string()),
+            request
+                .get("host")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            request.get("uri")?.as_str()?,
+            u16::try_from(json.get("status")?.as_u64()?).ok()?,
+        ))
+    }
+
+    fn new(
+        ip_address: String,
+        protocol: Option<String>,
+        fqdn: Option<String>,
+        uri: &str,
+        status: u16,
+    ) -> Self {
+        let (path, query) = uri
+            .split_once('?')
+            .map_or((uri, None), |(path, query)| (path, Some(query)));
+        Self {
+            ip_address,
+            protocol,
+            fqdn: fqdn.filter(|fqdn| !fqdn.is_empty()),
+            path: path.to_string(),
+            query: query.filter(|q| !q.is_empty()).map(str::to_string),
+            status,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nginx_combined() {
+        let line = r#"203.0.113.7 - - [19/Oct/2026:10:00:00 +0000] "POST /wp-login.php?redirect=1 HTTP/1.1" 401 512 "-" "curl/8.0""#;
+        let entry = AccessLogEntry::parse(LogFormat::Nginx, line).unwrap();
+        assert_eq!(entry.ip_address, "203.0.113.7");
+        assert_eq!(entry.path, "/wp-login.php");
+        assert_eq!(entry.query.as_deref(), Some("redirect=1"));
+        assert_eq!(entry.status, 401);
+        assert!(AccessLogEntry::parse(LogFormat::Nginx, "garbage").is_none());
+    }
+
+    #[test]
+    fn test_parse_traefik_json() {
+        let line = r#"{"ClientHost":"203.0.113.7","RequestHost":"blog.example.com","RequestPath":"/wp-login.php","RequestScheme":"https","DownstreamStatus":200}"#;
+        let entry = AccessLogEntry::parse(LogFormat::Traefik, line).unwrap();
+        assert_eq!(entry.fqdn.as_deref(), Some("blog.example.com"));
+        assert_eq!(entry.protocol.as_deref(), Some("https"));
+        assert_eq!(entry.status, 200);
+    }
+
+    #[test]
+    fn test_parse_caddy_json() {
+        let line = r#"{"level":"info","request":{"remote_ip":"10.0.0.1","client_ip":"203.0.113.7","host":"blog.example.com","uri":"/login?next=/","tls":{"version":772}},"status":403}"#;
+        let entry = AccessLogEntry::parse(LogFormat::Caddy, line).unwrap();
+        assert_eq!(entry.ip_address, "203.0.113.7");
+        assert_eq!(entry.path, "/login");
+        assert_eq!(entry.query.as_deref(), Some("next=/"));
+        assert_eq!(entry.status, 403);
+    }
+}
@@ -0,0 +1,150 @@
+//! # Escalado persistente
+//!
+//! Nivel de escalado de cada sujeto en la tabla `ban_escalations`, con la
+//! fecha del último ban en hora de reloj. Así `ban_count_decay_days` se
+//! respeta aunque el proceso se reinicie.
+
+use crate::models::subject::Subject;
+use chrono::{DateTime, Utc};
+use sqlx::{
+    Error, Row,
+    postgres::{PgPool, PgRow},
+    query,
+};
+use tracing::warn;
+
+/// A row of the `ban_escalations` table.
+#[derive(Debug, Clone)]
+pub struct BanEscalation {
+    pub subject: Subject,
+    /// Bans issued to the subject since its counter last decayed.
+    pub level: u32,
+    pub last_ban_at: DateTime<Utc>,
+}
+
+impl BanEscalation {
+    /// Reads a row; rows whose subject doesn't parse are skipped.
+    fn from_row(row: &PgRow) -> Option<Self> {
+        let subject: String = row.get("subject");
+        let subject = subject
+            .parse()
+            .inspect_err(|e| warn!("Ignoring escalation with invalid subject: {e}"))
+            .ok()?;
+        Some(Self {
+            subject,
+            level: u32::try_from(row.get::<i32, _>("level")).unwrap_or_default(),
+            last_ban_at: row.get("last_ban_at"),
+        })
+    }
+
+    /// Every counter banned within the last `decay_days` days.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn read_current(pool: &PgPool, decay_days: i64) -> Result<Vec<Self>, Error> {
+        let rows = query(
+            "SELECT subject, level, last_ban_at FROM ban_escalations
+            WHERE last_ban_at > NOW() - make_interval(days => $1)",
+        )
+        .bind(i32::try_from(decay_days).unwrap_or(i32::MAX))
+        .fetch_all(pool)
+        .await?;
+        Ok(rows.iter().filter_map(Self::from_row).collect())
+    }
+
+    /// Current level of `subject`, or 0 if it decayed or was never banned.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn level(pool: &PgPool, subject: &Subject, decay_days: i64) -> Result<u32, Error> {
+        let level: Option<i32> = query(
+            "SELECT level FROM ban_escalations
+            WHERE subject = $1 AND last_ban_at > NOW() - make_interval(days => $2)",
+        )
+        .bind(subject.to_string())
+        .bind(i32::try_from(decay_days).unwrap_or(i32::MAX))
+        .map(|row: PgRow| row.get(0))
+        .fetch_optional(pool)
+        .await?;
+        Ok(level.map_or(0, |level| u32::try_from(level).unwrap_or_default()))
+    }
+
+    /// Counts a new ban of `subject` and returns the level it was issued at:
+    /// the previous level, or 0 if it had decayed.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn escalate(pool: &PgPool, subject: &Subject, decay_days: i64) -> Result<u32, Error> {
+        let level: i32 = query(
+            "INSERT INTO ban_escalations (subject, level, last_ban_at)
+            VALUES ($1, 1, NOW())
+            ON CONFLICT (subject) DO UPDATE SET
+                level = CASE
+                    WHEN ban_escalations.last_ban_at > NOW() - make_interval(days => $2)
+                    THEN ban_escalations.level + 1
+                    ELSE 1
+                END,
+                last_ban_at = NOW()
+            RETURNING level - 1",
+        )
+        .bind(subject.to_string())
+        .bind(i32::try_from(decay_days).unwrap_or(i32::MAX))
+        .map(|row: PgRow| row.get(0))
+        .fetch_one(pool)
+        .await?;
+        Ok(u32::try_from(level).unwrap_or_default())
+    }
+
+    /// Stores the counter of `subject` as kept in memory.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn save(
+        pool: &PgPool,
+        subject: &Subject,
+        level: u32,
+        last_ban_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        query(
+            "INSERT INTO ban_escalations (subject, level, last_ban_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (subject) DO UPDATE SET
+                level = EXCLUDED.level,
+                last_ban_at = EXCLUDED.last_ban_at",
+        )
+        .bind(subject.to_string())
+        .bind(i32::try_from(level).unwrap_or(i32::MAX))
+        .bind(last_ban_at)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes the counter of `subject`. Returns how many rows were deleted.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn delete(pool: &PgPool, subject: &Subject) -> Result<u64, Error> {
+        let result = query("DELETE FROM ban_escalations WHERE subject = $1")
+            .bind(subject.to_string())
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Deletes the counters not banned within the last `decay_days` days.
+    /// Returns how many.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn delete_decayed(pool: &PgPool, decay_days: i64) -> Result<u64, Error> {
+        let result = query(
+            "DELETE FROM ban_escalations
+            WHERE last_ban_at <= NOW() - make_interval(days => $1)",
+        )
+        .bind(i32::try_from(decay_days).unwrap_or(i32::MAX))
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
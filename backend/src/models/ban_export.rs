@@ -0,0 +1,279 @@
+//! # Exportación de bans
+//!
+//! Convierte los bans activos en la configuración de un cortafuegos o proxy,
+//! para descartar a los clientes baneados en el borde sin pasar por
+//! forward-auth. Solo se exportan IPs y rangos; los bans de ASN, de país y de
+//! claves de rate limit no tienen equivalente.
+//!
+//! La salida está ordenada y no depende del momento en que se genera (las
+//! fechas van en segundos), así que su hash sirve como `ETag`.
+
+use crate::models::ban_manager::BanInfo;
+use crate::models::subject::{IpRange, Subject};
+use chrono::{DateTime, Utc};
+use std::fmt::Write;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Name of the nftables sets and ipset sets.
+const SET_NAME: &str = "shuul_banned";
+
+/// Output format of `GET /api/v1/bans/export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    /// One address or range per line.
+    #[default]
+    Text,
+    /// `shuul_banned_v4` and `shuul_banned_v6` sets, for `include` in a table.
+    Nftables,
+    /// Input for `ipset restore`.
+    Ipset,
+    /// `deny` directives, for `include` in nginx.
+    Nginx,
+    /// Traefik dynamic configuration. Traefik only ships `ipAllowList`, so
+    /// the deny list uses the `denyip` plugin's `ipDenyList`.
+    Traefik,
+    /// One row per ban, with its rule, reason and expiry.
+    Csv,
+}
+
+impl ExportFormat {
+    /// `Content-Type` of the output.
+    #[must_use]
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::Text | Self::Nftables | Self::Ipset | Self::Nginx => "text/plain; charset=utf-8",
+            Self::Traefik => "application/yaml",
+            Self::Csv => "text/csv; charset=utf-8",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" | "txt" => Ok(Self::Text),
+            "nftables" | "nft" => Ok(Self::Nftables),
+            "ipset" => Ok(Self::Ipset),
+            "nginx" => Ok(Self::Nginx),
+            "traefik" => Ok(Self::Traefik),
+            "csv" => Ok(Self::Csv),
+            other => Err(format!(
+                "Unknown export format: {other} (text, nftables, ipset, nginx, traefik or csv)"
+            )),
+        }
+    }
+}
+
+/// A banned IP or range, written without the prefix for single addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Banned(IpRange);
+
+impl Banned {
+    fn new(subject: &Subject) -> Option<Self> {
+        match subject {
+            Subject::Ip(ip) => IpRange::new(*ip, if ip.is_ipv4() { 32 } else { 128 }).map(Self),
+            Subject::Range(range) => Some(Self(*range)),
+            Subject::Asn(_) | Subject::Country(_) | Subject::Key(_) => None,
+        }
+    }
+
+    const fn is_ipv4(self) -> bool {
+        self.0.network().is_ipv4()
+    }
+
+    fn address(self) -> String {
+        let single = match self.0.network() {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if self.0.prefix_len() == single {
+            self.0.network().to_string()
+        } else {
+            self.0.to_string()
+        }
+    }
+}
+
+/// Renders the IP and range bans of `bans` in `format`.
+#[must_use]
+pub fn render(format: ExportFormat, bans: &[(Subject, BanInfo)]) -> String {
+    let mut rows: Vec<(Banned, &BanInfo)> = bans
+        .iter()
+        .filter_map(|(subject, ban)| Banned::new(subject).map(|banned| (banned, ban)))
+        .collect();
+    rows.sort_by(|(a, a_ban), (b, b_ban)| a.cmp(b).then(a_ban.rule_id.cmp(&b_ban.rule_id)));
+    let addresses = || {
+        let mut addresses: Vec<Banned> = rows.iter().map(|(banned, _)| *banned).collect();
+        addresses.dedup();
+        addresses
+    };
+    match format {
+        ExportFormat::Text => lines(&addresses(), |address| address),
+        ExportFormat::Nftables => nftables(&addresses()),
+        ExportFormat::Ipset => ipset(&addresses()),
+        ExportFormat::Nginx => lines(&addresses(), |address| format!("deny {address};")),
+        ExportFormat::Traefik => traefik(&addresses()),
+        ExportFormat::Csv => csv(&rows),
+    }
+}
+
+/// One line per address, formatted by `line`.
+fn lines(addresses: &[Banned], line: impl Fn(String) -> String) -> String {
+    addresses.iter().fold(String::new(), |mut out, banned| {
+        let _ = writeln!(out, "{}", line(banned.address()));
+        out
+    })
+}
+
+/// Addresses of one family, comma separated.
+fn family(addresses: &[Banned], ipv4: bool) -> Vec<String> {
+    addresses
+        .iter()
+        .filter(|banned| banned.is_ipv4() == ipv4)
+        .map(|banned| banned.address())
+        .collect()
+}
+
+fn nftables(addresses: &[Banned]) -> String {
+    let mut out = String::new();
+    for (suffix, ipv4, kind) in [("v4", true, "ipv4_addr"), ("v6", false, "ipv6_addr")] {
+        let _ = writeln!(out, "set {SET_NAME}_{suffix} {{");
+        let _ = writeln!(out, "    type {kind}");
+        let _ = writeln!(out, "    flags interval");
+        let elements = family(addresses, ipv4);
+        // nft rejects an empty element list
+        if !elements.is_empty() {
+            let _ = writeln!(out, "    elements = {{ {} }}", elements.join(", "));
+        }
+        let _ = writeln!(out, "}}");
+    }
+    out
+}
+
+fn ipset(addresses: &[Banned]) -> String {
+    let mut out = String::new();
+    for (suffix, ipv4, family_name) in [("v4", true, "inet"), ("v6", false, "inet6")] {
+        let set = format!("{SET_NAME}_{suffix}");
+        let _ = writeln!(out, "create {set} hash:net family {family_name} -exist");
+        let _ = writeln!(out, "flush {set}");
+        for address in family(addresses, ipv4) {
+            let _ = writeln!(out, "add {set} {address}");
+        }
+    }
+    out
+}
+
+fn traefik(addresses: &[Banned]) -> String {
+    let mut out = String::from(
+        "http:\n  middlewares:\n    shuul-denylist:\n      plugin:\n        denyip:\n",
+    );
+    if addresses.is_empty() {
+        out.push_str("          ipDenyList: []\n");
+    } else {
+        out.push_str("          ipDenyList:\n");
+        for banned in addresses {
+            let _ = writeln!(out, "            - \"{}\"", banned.address());
+        }
+    }
+    out
+}
+
+fn csv(rows: &[(Banned, &BanInfo)]) -> String {
+    let mut out =
+        String::from("ip_address,rule_id,reason,escalation_level,banned_at,expires_at\n");
+    for (banned, ban) in rows {
+        let banned_at = ban.issued_at().timestamp();
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            banned.address(),
+            ban.rule_id.map(|id| id.to_string()).unwrap_or_default(),
+            csv_field(&ban.reason),
+            ban.escalation_level,
+            timestamp(banned_at),
+            ban.ends_at()
+                .map(|ends_at| timestamp(ends_at.timestamp()))
+                .unwrap_or_default(),
+        );
+    }
+    out
+}
+
+/// RFC 3339 time, to the second, of a Unix timestamp.
+fn timestamp(seconds: i64) -> String {
+    DateTime::<Utc>::from_timestamp(seconds, 0)
+        .map(|at| at.to_rfc3339())
+        .unwrap_or_default()
+}
+
+/// Quotes a CSV field if it needs it.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn ban(rule_id: Option<i32>, reason: &str) -> BanInfo {
+        BanInfo {
+            banned_at: Instant::now(),
+            ban_duration_seconds: 3600,
+            escalation_level: 0,
+            rule_id,
+            reason: reason.to_string(),
+        }
+    }
+
+    fn bans() -> Vec<(Subject, BanInfo)> {
+        vec![
+            ("10.0.0.2".parse().unwrap(), ban(Some(2), "scan")),
+            ("2001:db8::/48".parse().unwrap(), ban(None, "manual")),
+            ("10.0.0.2".parse().unwrap(), ban(Some(1), "brute, force")),
+            ("AS64500".parse().unwrap(), ban(None, "asn")),
+            ("10.0.1.0/24".parse().unwrap(), ban(None, "range")),
+        ]
+    }
+
+    #[test]
+    fn test_render_text_and_nginx() {
+        assert_eq!(
+            render(ExportFormat::Text, &bans()),
+            "10.0.0.2\n10.0.1.0/24\n2001:db8::/48\n"
+        );
+        assert_eq!(
+            render(ExportFormat::Nginx, &bans()),
+            "deny 10.0.0.2;\ndeny 10.0.1.0/24;\ndeny 2001:db8::/48;\n"
+        );
+    }
+
+    #[test]
+    fn test_render_sets() {
+        let nftables = render(ExportFormat::Nftables, &bans());
+        assert!(nftables.contains("elements = { 10.0.0.2, 10.0.1.0/24 }"));
+        assert!(nftables.contains("elements = { 2001:db8::/48 }"));
+        let empty = render(ExportFormat::Nftables, &[]);
+        assert!(!empty.contains("elements"));
+
+        let ipset = render(ExportFormat::Ipset, &bans());
+        assert!(ipset.contains("add shuul_banned_v4 10.0.1.0/24\n"));
+        assert!(ipset.contains("create shuul_banned_v6 hash:net family inet6 -exist\n"));
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let csv = render(ExportFormat::Csv, &bans());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].starts_with("10.0.0.2,1,\"brute, force\",0,"));
+        assert!(lines[2].starts_with("10.0.0.2,2,scan,0,"));
+    }
+}
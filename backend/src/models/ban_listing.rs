@@ -0,0 +1,311 @@
+//! # Listado de bans activos
+//!
+//! [`BanListing`] es la vista de un ban activo que devuelve la API, con la
+//! geolocalización (país, ciudad) y el sistema autónomo del sujeto.
+//!
+//! [`BanFilter`] y [`BanSort`] implementan el filtrado (regla, país, motivo,
+//! CIDR) y la ordenación (tiempo restante, escalado, fecha) del listado
+//! paginado.
+
+use crate::models::IPData;
+use crate::models::ban_manager::BanInfo;
+use crate::models::subject::{IpRange, Subject};
+use chrono::{DateTime, Utc};
+use maxminddb::Reader;
+use serde::Serialize;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An active ban as returned by the API.
+#[derive(Debug, Clone, Serialize)]
+pub struct BanListing {
+    #[serde(skip)]
+    pub subject: Subject,
+    /// Row key: the subject and the rule that banned it.
+    pub id: String,
+    pub ip_address: String,
+    pub kind: &'static str,
+    pub rule_id: Option<i32>,
+    pub reason: String,
+    pub ban_duration_seconds: i64,
+    pub escalation_level: u32,
+    /// `None` for permanent bans.
+    pub time_remaining_seconds: Option<u64>,
+    pub permanent: bool,
+    pub banned_at: DateTime<Utc>,
+    pub country_code: Option<String>,
+    pub country_name: Option<String>,
+    pub city_name: Option<String>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+impl BanListing {
+    /// Listing of `ban`, without geolocation.
+    #[must_use]
+    pub fn new(subject: &Subject, ban: &BanInfo) -> Self {
+        Self {
+            subject: subject.clone(),
+            id: ban.rule_id.map_or_else(
+                || format!("{subject} manual"),
+                |rule_id| format!("{subject} rule:{rule_id}"),
+            ),
+            ip_address: subject.to_string(),
+            kind: subject.kind(),
+            rule_id: ban.rule_id,
+            reason: ban.reason.clone(),
+            ban_duration_seconds: ban.ban_duration_seconds,
+            escalation_level: ban.escalation_level,
+            time_remaining_seconds: ban.time_remaining().map(|remaining| remaining.as_secs()),
+            permanent: ban.is_permanent(),
+            banned_at: ban.issued_at(),
+            country_code: match subject {
+                Subject::Country(code) => Some(code.clone()),
+                _ => None,
+            },
+            country_name: None,
+            city_name: None,
+            asn: match subject {
+                Subject::Asn(asn) => Some(*asn),
+                _ => None,
+            },
+            as_org: None,
+        }
+    }
+
+    /// Fills in the country, city and autonomous system of the address (or
+    /// network address of the range) being banned. ASN, country and key bans
+    /// are left as they are.
+    #[must_use]
+    pub fn with_geo(
+        mut self,
+        maxmind_db: &Reader<Vec<u8>>,
+        asn_db: Option<&Reader<Vec<u8>>>,
+    ) -> Self {
+        let Some(ip) = self.address() else {
+            return self;
+        };
+        let ip = ip.to_string();
+        let data = IPData::complete(maxmind_db, &ip);
+        self.country_code = data.country_code;
+        self.country_name = data.country_name;
+        self.city_name = data.city_name;
+        if let Some((asn, as_org)) = asn_db.and_then(|db| IPData::lookup_asn(db, &ip)) {
+            self.asn = Some(asn);
+            self.as_org = as_org;
+        }
+        self
+    }
+
+    /// Address used for the geolocation: the IP itself, or the network
+    /// address of a range.
+    const fn address(&self) -> Option<IpAddr> {
+        match &self.subject {
+            Subject::Ip(ip) => Some(*ip),
+            Subject::Range(range) => Some(range.network()),
+            Subject::Asn(_) | Subject::Country(_) | Subject::Key(_) => None,
+        }
+    }
+}
+
+/// Filters of the ban listing. Every filter set must match.
+#[derive(Debug, Clone, Default)]
+pub struct BanFilter {
+    pub rule_id: Option<i32>,
+    /// ISO code (exact) or part of the English name, case-insensitive.
+    pub country: Option<String>,
+    /// Part of the reason, case-insensitive.
+    pub reason: Option<String>,
+    /// IPs inside the range and ranges contained in it.
+    pub cidr: Option<IpRange>,
+}
+
+impl BanFilter {
+    #[must_use]
+    pub fn matches(&self, listing: &BanListing) -> bool {
+        self.rule_id
+            .is_none_or(|rule_id| listing.rule_id == Some(rule_id))
+            && self.reason.as_deref().is_none_or(|reason| {
+                listing
+                    .reason
+                    .to_lowercase()
+                    .contains(&reason.to_lowercase())
+            })
+            && self
+                .country
+                .as_deref()
+                .is_none_or(|country| Self::country_matches(listing, country))
+            && self
+                .cidr
+                .is_none_or(|cidr| Self::cidr_matches(&listing.subject, &cidr))
+    }
+
+    fn country_matches(listing: &BanListing, country: &str) -> bool {
+        listing
+            .country_code
+            .as_deref()
+            .is_some_and(|code| code.eq_ignore_ascii_case(country))
+            || listing
+                .country_name
+                .as_deref()
+                .is_some_and(|name| name.to_lowercase().contains(&country.to_lowercase()))
+    }
+
+    fn cidr_matches(subject: &Subject, cidr: &IpRange) -> bool {
+        match subject {
+            Subject::Ip(ip) => cidr.contains(ip),
+            Subject::Range(range) => {
+                range.prefix_len() >= cidr.prefix_len() && cidr.contains(&range.network())
+            },
+            Subject::Asn(_) | Subject::Country(_) | Subject::Key(_) => false,
+        }
+    }
+}
+
+/// Sort key of the ban listing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BanSort {
+    TimeRemaining,
+    EscalationLevel,
+    #[default]
+    BannedAt,
+}
+
+impl FromStr for BanSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "time_remaining" | "time_remaining_seconds" => Ok(Self::TimeRemaining),
+            "escalation" | "escalation_level" => Ok(Self::EscalationLevel),
+            "banned_at" | "created_at" => Ok(Self::BannedAt),
+            _ => Err(format!("Invalid sort field: {s}")),
+        }
+    }
+}
+
+impl BanSort {
+    /// Sorts `listings` by this key, ties broken by subject.
+    pub fn sort(self, listings: &mut [BanListing], asc: bool) {
+        listings.sort_by(|a, b| {
+            let order = match self {
+                // Permanent bans last longest.
+                Self::TimeRemaining => a
+                    .time_remaining_seconds
+                    .unwrap_or(u64::MAX)
+                    .cmp(&b.time_remaining_seconds.unwrap_or(u64::MAX)),
+                Self::EscalationLevel => a.escalation_level.cmp(&b.escalation_level),
+                Self::BannedAt => a.banned_at.cmp(&b.banned_at),
+            };
+            let order = if asc { order } else { order.reverse() };
+            order.then_with(|| a.ip_address.cmp(&b.ip_address))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn listing(
+        subject: &str,
+        rule_id: Option<i32>,
+        reason: &str,
+        level: u32,
+        age: u64,
+    ) -> BanListing {
+        let ban = BanInfo {
+            banned_at: Instant::now()
+                .checked_sub(Duration::from_secs(age))
+                .unwrap(),
+            ban_duration_seconds: 3600,
+            escalation_level: level,
+            rule_id,
+            reason: reason.to_string(),
+        };
+        BanListing::new(&subject.parse().unwrap(), &ban)
+    }
+
+    #[test]
+    fn test_filters_by_rule_and_reason() {
+        let ban = listing("1.2.3.4", Some(7), "Rate limit exceeded", 0, 0);
+        let by_rule = BanFilter {
+            rule_id: Some(7),
+            ..BanFilter::default()
+        };
+        assert!(by_rule.matches(&ban));
+        let other_rule = BanFilter {
+            rule_id: Some(8),
+            ..BanFilter::default()
+        };
+        assert!(!other_rule.matches(&ban));
+        let by_reason = BanFilter {
+            reason: Some("RATE".to_string()),
+            ..BanFilter::default()
+        };
+        assert!(by_reason.matches(&ban));
+    }
+
+    #[test]
+    fn test_filters_by_country_code_or_name() {
+        let mut ban = listing("1.2.3.4", None, "Manual ban", 0, 0);
+        ban.country_code = Some("ES".to_string());
+        ban.country_name = Some("Spain".to_string());
+        for country in ["es", "spa"] {
+            let filter = BanFilter {
+                country: Some(country.to_string()),
+                ..BanFilter::default()
+            };
+            assert!(filter.matches(&ban), "{country}");
+        }
+        let filter = BanFilter {
+            country: Some("FR".to_string()),
+            ..BanFilter::default()
+        };
+        assert!(!filter.matches(&ban));
+    }
+
+    #[test]
+    fn test_filters_by_cidr() {
+        let filter = BanFilter {
+            cidr: Some("10.0.0.0/8".parse().unwrap()),
+            ..BanFilter::default()
+        };
+        assert!(filter.matches(&listing("10.1.2.3", None, "", 0, 0)));
+        assert!(filter.matches(&listing("10.1.0.0/16", None, "", 0, 0)));
+        assert!(!filter.matches(&listing("10.0.0.0/7", None, "", 0, 0)));
+        assert!(!filter.matches(&listing("11.0.0.1", None, "", 0, 0)));
+        assert!(!filter.matches(&listing("AS64500", None, "", 0, 0)));
+    }
+
+    #[test]
+    fn test_sorts_by_key_and_direction() {
+        let mut bans = vec![
+            listing("1.1.1.1", None, "", 2, 100),
+            listing("2.2.2.2", None, "", 0, 10),
+            listing("3.3.3.3", None, "", 1, 1000),
+        ];
+        BanSort::EscalationLevel.sort(&mut bans, true);
+        assert_eq!(bans[0].ip_address, "2.2.2.2");
+        BanSort::BannedAt.sort(&mut bans, false);
+        assert_eq!(bans[0].ip_address, "2.2.2.2");
+        assert_eq!(bans[2].ip_address, "3.3.3.3");
+        BanSort::TimeRemaining.sort(&mut bans, true);
+        assert_eq!(bans[0].ip_address, "3.3.3.3");
+        assert_eq!("created_at".parse(), Ok(BanSort::BannedAt));
+        assert!("ip_address".parse::<BanSort>().is_err());
+    }
+
+    #[test]
+    fn test_asn_and_country_bans_carry_their_value() {
+        assert_eq!(listing("AS64500", None, "", 0, 0).asn, Some(64500));
+        let country = listing("country:es", None, "", 0, 0);
+        assert_eq!(country.country_code.as_deref(), Some("ES"));
+        let filter = BanFilter {
+            country: Some("es".to_string()),
+            ..BanFilter::default()
+        };
+        assert!(filter.matches(&country));
+    }
+}
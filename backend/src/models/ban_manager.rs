@@ -1,9 +1,18 @@
 //! # Ban Manager
 //!
-//! Manages active IP bans with escalation and decay.
+//! Manages active bans with escalation and decay.
 //! Bans are enforced at the HTTP level — no firewall backend needed.
+//!
+//! A ban targets a [`Subject`]: an IP, a network range, an ASN or an opaque
+//! rate-limit key. Range bans are found through a per-prefix-length index,
+//! so checking an IP costs one hash lookup per prefix length in use.
+//!
+//! With [`RangeAggregation`], once enough distinct IPs of the same /24 (IPv4)
+//! or /64 (IPv6) are banned within a window, the whole prefix is banned.
 
-use std::collections::HashMap;
+use crate::models::subject::{IpRange, Subject};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeSet, HashMap};
 use std::net::IpAddr;
 use std::time::{Duration, Instant};
 
@@ -23,31 +32,120 @@ pub struct BanInfo {
 }
 
 impl BanInfo {
+    /// `ban_duration_seconds` of a ban that never expires, as in fail2ban.
+    pub const PERMANENT: i64 = -1;
+
+    /// Converts a wall-clock `banned_at` (e.g. read from the database) into
+    /// the monotonic clock used by [`BanInfo`].
+    #[must_use]
+    pub fn instant_from(banned_at: DateTime<Utc>) -> Instant {
+        let elapsed = (Utc::now() - banned_at).to_std().unwrap_or_default();
+        Instant::now().checked_sub(elapsed).unwrap_or_else(Instant::now)
+    }
+
+    /// Wall-clock time the ban was issued at.
+    #[must_use]
+    pub fn issued_at(&self) -> DateTime<Utc> {
+        chrono::Duration::from_std(self.banned_at.elapsed())
+            .map_or_else(|_| Utc::now(), |ago| Utc::now() - ago)
+    }
+
+    /// Returns true if this ban never expires.
+    #[must_use]
+    pub const fn is_permanent(&self) -> bool {
+        self.ban_duration_seconds < 0
+    }
+
     /// Returns true if this ban has expired.
+    #[must_use]
     pub fn is_expired(&self) -> bool {
-        let elapsed = Instant::now().duration_since(self.banned_at);
-        elapsed > Duration::from_secs(self.ban_duration_seconds as u64)
+        self.time_remaining().is_some_and(|remaining| remaining.is_zero())
     }
 
-    /// Returns the remaining duration as a human-friendly string.
-    pub fn time_remaining(&self) -> Duration {
-        let elapsed = Instant::now().duration_since(self.banned_at);
-        let total = Duration::from_secs(self.ban_duration_seconds as u64);
-        if elapsed > total {
-            Duration::from_secs(0)
-        } else {
-            total - elapsed
+    /// Returns the remaining duration, or `None` if the ban is permanent.
+    #[must_use]
+    pub fn time_remaining(&self) -> Option<Duration> {
+        let total = Duration::from_secs(u64::try_from(self.ban_duration_seconds).ok()?);
+        Some(total.saturating_sub(self.banned_at.elapsed()))
+    }
+
+    /// Wall-clock time the ban ends at, or `None` if it is permanent.
+    #[must_use]
+    pub fn ends_at(&self) -> Option<DateTime<Utc>> {
+        (!self.is_permanent())
+            .then(|| self.issued_at() + chrono::Duration::seconds(self.ban_duration_seconds))
+    }
+
+    /// Applies the changes of `update`.
+    pub fn apply(&mut self, update: &BanUpdate) {
+        if let Some(reason) = &update.reason {
+            self.reason.clone_from(reason);
+        }
+        match update.remaining {
+            Some(BanRemaining::Set(seconds)) => {
+                self.ban_duration_seconds = i64::try_from(self.banned_at.elapsed().as_secs())
+                    .unwrap_or(i64::MAX)
+                    .saturating_add(seconds);
+            },
+            Some(BanRemaining::Extend(seconds)) if !self.is_permanent() => {
+                self.ban_duration_seconds = self.ban_duration_seconds.saturating_add(seconds).max(0);
+            },
+            Some(BanRemaining::Permanent) => self.ban_duration_seconds = Self::PERMANENT,
+            Some(BanRemaining::Extend(_)) | None => {},
         }
     }
 }
 
+/// New remaining time of an edited ban.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BanRemaining {
+    /// Ends this many seconds from now.
+    Set(i64),
+    /// Adds seconds to the ban, or removes them if negative. Permanent bans
+    /// are left as they are.
+    Extend(i64),
+    /// Never ends.
+    Permanent,
+}
+
+/// Changes to an active ban.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BanUpdate {
+    pub reason: Option<String>,
+    pub remaining: Option<BanRemaining>,
+}
+
+/// Ban a whole /24 or /64 once `threshold` distinct IPs in it are banned
+/// within `window`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeAggregation {
+    pub threshold: usize,
+    pub window: Duration,
+}
+
+impl RangeAggregation {
+    /// Reason recorded on an aggregated ban of `range`.
+    #[must_use]
+    pub fn reason(&self, range: IpRange) -> String {
+        format!(
+            "{} IPs of {} banned within {}s",
+            self.threshold,
+            range,
+            self.window.as_secs()
+        )
+    }
+}
+
 /// Manages all active bans, with escalation and decay logic.
 #[derive(Debug, Clone)]
 pub struct BanManager {
-    /// Active bans keyed by IP address
-    bans: HashMap<IpAddr, Vec<BanInfo>>,
-    /// Per-IP escalation counters (decays over time)
-    escalation_counts: HashMap<IpAddr, (u32, Instant)>,
+    /// Active bans keyed by subject
+    bans: HashMap<Subject, Vec<BanInfo>>,
+    /// Prefix lengths of the banned ranges, as `(is_ipv4, prefix_len)`
+    range_lengths: BTreeSet<(bool, u8)>,
+    /// Per-subject escalation counters and wall-clock time of the last ban
+    /// (decays over time)
+    escalation_counts: HashMap<Subject, (u32, DateTime<Utc>)>,
     /// Default ban duration for new bans
     default_ban_duration: i64,
     /// Whether to escalate repeat offenses
@@ -58,6 +156,10 @@ pub struct BanManager {
     bantime_maxtime: i64,
     /// Days after which escalation counter resets
     ban_count_decay_days: i64,
+    /// Automatic range bans, if enabled
+    range_aggregation: Option<RangeAggregation>,
+    /// IPs recently banned in each /24 or /64, for range aggregation
+    recent_ip_bans: HashMap<IpRange, Vec<(IpAddr, Instant)>>,
 }
 
 impl BanManager {
@@ -71,30 +173,96 @@ impl BanManager {
     ) -> Self {
         Self {
             bans: HashMap::new(),
+            range_lengths: BTreeSet::new(),
             escalation_counts: HashMap::new(),
             default_ban_duration,
             bantime_increment,
             bantime_multipliers,
             bantime_maxtime,
             ban_count_decay_days,
+            range_aggregation: None,
+            recent_ip_bans: HashMap::new(),
         }
     }
 
-    /// Check if an IP is currently banned.
+    /// Enable automatic range bans.
+    #[must_use]
+    pub const fn with_range_aggregation(mut self, range_aggregation: RangeAggregation) -> Self {
+        self.range_aggregation = Some(range_aggregation);
+        self
+    }
+
+    /// Automatic range ban policy, if enabled.
+    #[must_use]
+    pub const fn range_aggregation(&self) -> Option<RangeAggregation> {
+        self.range_aggregation
+    }
+
+    /// Records a ban of `ip` for range aggregation. Returns the /24 or /64 of
+    /// `ip` if it now has enough distinct banned IPs and isn't banned yet.
+    pub fn aggregate_range(&mut self, ip: IpAddr) -> Option<IpRange> {
+        let aggregation = self.range_aggregation?;
+        let range = IpRange::default_prefix(ip);
+        if self.active_ban(&Subject::Range(range)).is_some() {
+            return None;
+        }
+        let now = Instant::now();
+        let recent = self.recent_ip_bans.entry(range).or_default();
+        recent.retain(|(other, banned_at)| {
+            *other != ip && now.duration_since(*banned_at) <= aggregation.window
+        });
+        recent.push((ip, now));
+        if recent.len() < aggregation.threshold {
+            return None;
+        }
+        self.recent_ip_bans.remove(&range);
+        Some(range)
+    }
+
+    /// Check if an IP is currently banned, directly or through a banned range.
     /// Returns the first active ban info, or None.
     pub fn is_banned(&self, ip: &IpAddr) -> Option<&BanInfo> {
-        self.bans.get(ip).and_then(|ban_list| {
-            ban_list.iter().find(|ban| !ban.is_expired())
+        self.active_ban(&Subject::Ip(*ip)).or_else(|| {
+            self.range_lengths
+                .iter()
+                .filter(|(is_ipv4, _)| *is_ipv4 == ip.is_ipv4())
+                .filter_map(|(_, len)| IpRange::new(*ip, *len))
+                .find_map(|range| self.active_ban(&Subject::Range(range)))
         })
     }
 
-    /// Ban an IP address.
+    /// Check a list of subjects (IP, ASN, rate-limit keys) for an active ban.
+    /// IP subjects also match the ranges that contain them.
+    #[must_use]
+    pub fn find_ban(&self, subjects: &[Subject]) -> Option<&BanInfo> {
+        subjects.iter().find_map(|subject| match subject {
+            Subject::Ip(ip) => self.is_banned(ip),
+            other => self.active_ban(other),
+        })
+    }
+
+    /// First non-expired ban of exactly `subject`.
+    fn active_ban(&self, subject: &Subject) -> Option<&BanInfo> {
+        self.bans
+            .get(subject)
+            .and_then(|ban_list| ban_list.iter().find(|ban| !ban.is_expired()))
+    }
+
+    /// Ban a subject (IP address, range, ASN or rate-limit key).
     ///
     /// If `ban_duration_override` is `Some`, it is used as the ban duration
     /// instead of the calculated escalation-based duration.
-    pub fn ban(&mut self, ip: IpAddr, rule_id: Option<i32>, reason: String, ban_duration_override: Option<i64>) -> &BanInfo {
-        let escalation_level = self.get_escalation_level(&ip);
-        let duration = ban_duration_override.unwrap_or_else(|| self.calculate_ban_duration(escalation_level));
+    pub fn ban(
+        &mut self,
+        subject: impl Into<Subject>,
+        rule_id: Option<i32>,
+        reason: String,
+        ban_duration_override: Option<i64>,
+    ) -> &BanInfo {
+        let subject = subject.into();
+        let escalation_level = self.get_escalation_level(&subject);
+        let duration =
+            ban_duration_override.unwrap_or_else(|| self.calculate_ban_duration(escalation_level));
 
         let ban_info = BanInfo {
             banned_at: Instant::now(),
@@ -104,32 +272,96 @@ impl BanManager {
             reason,
         };
 
-        self.bans.entry(ip).or_default().push(ban_info.clone());
-        self.increment_escalation(&ip);
+        self.increment_escalation(&subject);
+        if let Subject::Range(range) = &subject {
+            self.range_lengths
+                .insert((range.network().is_ipv4(), range.prefix_len()));
+        }
+        let ban_list = self.bans.entry(subject).or_default();
+        ban_list.push(ban_info);
 
         // Return reference to the last added ban
-        self.bans.get(&ip).unwrap().last().unwrap()
+        &ban_list[ban_list.len() - 1]
+    }
+
+    /// Re-adds a ban loaded from storage, keeping when it was issued and its
+    /// escalation level, so the next ban of the subject keeps escalating.
+    pub fn restore(&mut self, subject: Subject, ban: BanInfo) {
+        self.restore_escalation(subject.clone(), ban.escalation_level + 1, ban.issued_at());
+        if let Subject::Range(range) = &subject {
+            self.range_lengths
+                .insert((range.network().is_ipv4(), range.prefix_len()));
+        }
+        self.bans.entry(subject).or_default().push(ban);
+    }
+
+    /// Escalation level of `subject` and wall-clock time of its last ban.
+    #[must_use]
+    pub fn escalation(&self, subject: &Subject) -> Option<(u32, DateTime<Utc>)> {
+        self.escalation_counts.get(subject).copied()
     }
 
-    /// Unban an IP for a specific rule. Returns true if anything was removed.
-    pub fn unban(&mut self, ip: &IpAddr, rule_id: Option<i32>) -> bool {
-        if let Some(ban_list) = self.bans.get_mut(ip) {
+    /// Re-adds an escalation counter loaded from storage. Keeps the highest
+    /// level and the latest ban if the subject is already known.
+    pub fn restore_escalation(&mut self, subject: Subject, level: u32, last_ban: DateTime<Utc>) {
+        let escalation = self
+            .escalation_counts
+            .entry(subject)
+            .or_insert((0, last_ban));
+        *escalation = (escalation.0.max(level), escalation.1.max(last_ban));
+    }
+
+    /// Unban a subject for a specific rule. Returns true if anything was removed.
+    pub fn unban(&mut self, subject: impl Into<Subject>, rule_id: Option<i32>) -> bool {
+        let subject = subject.into();
+        let removed = if let Some(ban_list) = self.bans.get_mut(&subject) {
             let before = ban_list.len();
             ban_list.retain(|b| b.rule_id != rule_id && !b.is_expired());
             let removed = before - ban_list.len();
             if ban_list.is_empty() {
-                self.bans.remove(ip);
+                self.bans.remove(&subject);
             }
             removed > 0
         } else {
             false
+        };
+        self.refresh_range_lengths();
+        removed
+    }
+
+    /// Applies `update` to the active bans of `subject` issued by `rule_id`.
+    /// Returns the last ban updated, if any.
+    pub fn update(
+        &mut self,
+        subject: &Subject,
+        rule_id: Option<i32>,
+        update: &BanUpdate,
+    ) -> Option<BanInfo> {
+        let mut updated = None;
+        for ban in self
+            .bans
+            .get_mut(subject)?
+            .iter_mut()
+            .filter(|ban| ban.rule_id == rule_id && !ban.is_expired())
+        {
+            ban.apply(update);
+            updated = Some(ban.clone());
         }
+        updated
+    }
+
+    /// Forgets the escalation counter of `subject`, so its next ban starts
+    /// at level 0. Returns true if it had one.
+    pub fn reset_escalation(&mut self, subject: &Subject) -> bool {
+        self.escalation_counts.remove(subject).is_some()
     }
 
-    /// Unban all entries for an IP.
+    /// Unban all entries for a subject.
     #[allow(dead_code)]
-    pub fn unban_all(&mut self, ip: &IpAddr) -> bool {
-        self.bans.remove(ip).is_some()
+    pub fn unban_all(&mut self, subject: impl Into<Subject>) -> bool {
+        let removed = self.bans.remove(&subject.into()).is_some();
+        self.refresh_range_lengths();
+        removed
     }
 
     /// Remove all expired bans.
@@ -138,60 +370,97 @@ impl BanManager {
             ban_list.retain(|b| !b.is_expired());
             !ban_list.is_empty()
         });
+        self.refresh_range_lengths();
         // Decay escalation counters
-        let decay_duration = Duration::from_secs(self.ban_count_decay_days as u64 * 86400);
-        self.escalation_counts.retain(|_, (_, last_ban)| {
-            Instant::now().duration_since(*last_ban) <= decay_duration
-        });
+        let decay_duration = chrono::Duration::days(self.ban_count_decay_days);
+        self.escalation_counts
+            .retain(|_, (_, last_ban)| Utc::now() - *last_ban <= decay_duration);
+        if let Some(aggregation) = self.range_aggregation {
+            self.recent_ip_bans.retain(|_, recent| {
+                recent.retain(|(_, banned_at)| banned_at.elapsed() <= aggregation.window);
+                !recent.is_empty()
+            });
+        }
     }
 
     /// Get all active (non-expired) bans.
-    pub fn active_bans(&self) -> Vec<(IpAddr, &BanInfo)> {
+    pub fn active_bans(&self) -> Vec<(&Subject, &BanInfo)> {
         let mut result = Vec::new();
-        for (ip, ban_list) in &self.bans {
+        for (subject, ban_list) in &self.bans {
             for ban in ban_list {
                 if !ban.is_expired() {
-                    result.push((*ip, ban));
+                    result.push((subject, ban));
                 }
             }
         }
         result
     }
 
+    /// Rebuilds the index of banned range prefix lengths.
+    fn refresh_range_lengths(&mut self) {
+        self.range_lengths = self
+            .bans
+            .keys()
+            .filter_map(|subject| match subject {
+                Subject::Range(range) => Some((range.network().is_ipv4(), range.prefix_len())),
+                _ => None,
+            })
+            .collect();
+    }
+
     /// Number of active bans.
     pub fn active_count(&self) -> usize {
         self.active_bans().len()
     }
 
+    /// Days after which the escalation counter resets.
+    #[must_use]
+    pub const fn ban_count_decay_days(&self) -> i64 {
+        self.ban_count_decay_days
+    }
+
     /// Calculate ban duration based on escalation level.
-    fn calculate_ban_duration(&self, escalation_level: u32) -> i64 {
-        if !self.bantime_increment || escalation_level == 0 {
+    ///
+    /// A negative default duration makes every ban permanent. A negative
+    /// `bantime_maxtime` lifts the cap and makes the bans escalated past the
+    /// last multiplier permanent.
+    #[must_use]
+    pub fn calculate_ban_duration(&self, escalation_level: u32) -> i64 {
+        if !self.bantime_increment || escalation_level == 0 || self.default_ban_duration < 0 {
             return self.default_ban_duration;
         }
 
-        let multiplier_idx = (escalation_level as usize).min(self.bantime_multipliers.len() - 1);
-        let multiplier = self.bantime_multipliers[multiplier_idx] as i64;
-        let duration = self.default_ban_duration * multiplier;
+        let level = usize::try_from(escalation_level).unwrap_or(usize::MAX);
+        if self.bantime_maxtime < 0 && level >= self.bantime_multipliers.len() {
+            return BanInfo::PERMANENT;
+        }
+        let multiplier_idx = level.min(self.bantime_multipliers.len() - 1);
+        let multiplier = i64::from(self.bantime_multipliers[multiplier_idx]);
+        let duration = self.default_ban_duration.saturating_mul(multiplier);
 
-        duration.min(self.bantime_maxtime)
+        if self.bantime_maxtime < 0 {
+            duration
+        } else {
+            duration.min(self.bantime_maxtime)
+        }
     }
 
-    /// Get the current escalation level for an IP.
-    fn get_escalation_level(&self, ip: &IpAddr) -> u32 {
+    /// Get the current escalation level for a subject.
+    fn get_escalation_level(&self, subject: &Subject) -> u32 {
         self.escalation_counts
-            .get(ip)
+            .get(subject)
             .map(|(level, _)| *level)
             .unwrap_or(0)
     }
 
-    /// Increment the escalation counter for an IP.
-    fn increment_escalation(&mut self, ip: &IpAddr) {
+    /// Increment the escalation counter for a subject.
+    fn increment_escalation(&mut self, subject: &Subject) {
         let entry = self
             .escalation_counts
-            .entry(*ip)
-            .or_insert((0, Instant::now()));
+            .entry(subject.clone())
+            .or_insert_with(|| (0, Utc::now()));
         entry.0 += 1;
-        entry.1 = Instant::now();
+        entry.1 = Utc::now();
     }
 }
 
@@ -215,7 +484,7 @@ mod tests {
         let ip: IpAddr = "1.2.3.4".parse().unwrap();
 
         bm.ban(ip, Some(1), "test".to_string(), None);
-        assert!(bm.unban(&ip, Some(1)));
+        assert!(bm.unban(ip, Some(1)));
         assert!(bm.is_banned(&ip).is_none());
     }
 
@@ -237,6 +506,77 @@ mod tests {
         assert_eq!(ban3.ban_duration_seconds, 14400);
     }
 
+    #[test]
+    fn test_restore_keeps_escalation() {
+        let mut bm = BanManager::new(3600, true, vec![1, 2, 4, 8], 86400, 30);
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+        let stored = BanInfo {
+            banned_at: Instant::now(),
+            ban_duration_seconds: 7200,
+            escalation_level: 1,
+            rule_id: Some(1),
+            reason: "stored".to_string(),
+        };
+
+        bm.restore(Subject::Ip(ip), stored);
+        assert_eq!(bm.is_banned(&ip).unwrap().reason, "stored");
+        // Next offense continues from the restored level (multiplier 4)
+        let next = bm.ban(ip, Some(1), "next".to_string(), None);
+        assert_eq!(next.escalation_level, 2);
+        assert_eq!(next.ban_duration_seconds, 14400);
+    }
+
+    #[test]
+    fn test_restored_escalation_decays() {
+        let mut bm = BanManager::new(3600, true, vec![1, 2, 4, 8], 86400, 30);
+        let recent: Subject = "1.2.3.4".parse().unwrap();
+        let stale: Subject = "5.6.7.8".parse().unwrap();
+        bm.restore_escalation(recent.clone(), 2, Utc::now() - chrono::Duration::days(29));
+        bm.restore_escalation(stale.clone(), 3, Utc::now() - chrono::Duration::days(31));
+
+        bm.cleanup_expired();
+        assert_eq!(bm.escalation(&recent).map(|(level, _)| level), Some(2));
+        assert!(bm.escalation(&stale).is_none());
+    }
+
+    #[test]
+    fn test_range_aggregation() {
+        let mut bm = BanManager::new(3600, false, vec![1], 86400, 30).with_range_aggregation(
+            RangeAggregation {
+                threshold: 3,
+                window: Duration::from_mins(10),
+            },
+        );
+        let ip = |last: u8| IpAddr::from([10, 0, 0, last]);
+
+        assert!(bm.aggregate_range(ip(1)).is_none());
+        // The same IP twice counts once
+        assert!(bm.aggregate_range(ip(1)).is_none());
+        assert!(bm.aggregate_range(IpAddr::from([10, 0, 1, 1])).is_none());
+        assert!(bm.aggregate_range(ip(2)).is_none());
+        let range = bm.aggregate_range(ip(3)).unwrap();
+        assert_eq!(range.to_string(), "10.0.0.0/24");
+
+        bm.ban(range, None, "aggregated".to_string(), None);
+        assert!(bm.is_banned(&ip(200)).is_some());
+        // Already banned: not aggregated again
+        assert!(bm.aggregate_range(ip(4)).is_none());
+    }
+
+    #[test]
+    fn test_range_ban_covers_ips() {
+        let mut bm = BanManager::new(3600, false, vec![1], 86400, 30);
+        let ip: IpAddr = "10.20.30.40".parse().unwrap();
+        bm.ban(IpRange::default_prefix(ip), Some(1), "prefix".to_string(), None);
+
+        assert!(bm.is_banned(&"10.20.30.1".parse().unwrap()).is_some());
+        assert!(bm.is_banned(&"10.20.31.1".parse().unwrap()).is_none());
+        assert!(bm.find_ban(&[Subject::Key("other".to_string()), Subject::Ip(ip)]).is_some());
+
+        assert!(bm.unban(IpRange::default_prefix(ip), Some(1)));
+        assert!(bm.is_banned(&ip).is_none());
+    }
+
     #[test]
     fn test_cleanup_expired() {
         let mut bm = BanManager::new(1, false, vec![1], 86400, 30);
@@ -249,4 +589,69 @@ mod tests {
         bm.cleanup_expired();
         assert_eq!(bm.active_count(), 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_update_remaining_and_reason() {
+        let mut bm = BanManager::new(60, false, vec![1], 86400, 30);
+        let subject = Subject::Ip("1.2.3.4".parse().unwrap());
+        bm.ban(subject.clone(), Some(1), "test".to_string(), None);
+
+        let extend = BanUpdate {
+            reason: Some("edited".to_string()),
+            remaining: Some(BanRemaining::Extend(3600)),
+        };
+        let ban = bm.update(&subject, Some(1), &extend).unwrap();
+        assert_eq!((ban.ban_duration_seconds, ban.reason.as_str()), (3660, "edited"));
+        assert!(bm.update(&subject, None, &extend).is_none());
+
+        let permanent = BanUpdate {
+            remaining: Some(BanRemaining::Permanent),
+            ..BanUpdate::default()
+        };
+        let ban = bm.update(&subject, Some(1), &permanent).unwrap();
+        assert!(ban.is_permanent() && !ban.is_expired());
+        assert_eq!((ban.time_remaining(), ban.ends_at()), (None, None));
+        // Extending a permanent ban leaves it permanent.
+        assert!(bm.update(&subject, Some(1), &extend).unwrap().is_permanent());
+
+        let shorten = BanUpdate {
+            remaining: Some(BanRemaining::Set(10)),
+            ..BanUpdate::default()
+        };
+        let ban = bm.update(&subject, Some(1), &shorten).unwrap();
+        assert!(ban.time_remaining().unwrap() <= Duration::from_secs(10));
+
+        let expire = BanUpdate {
+            remaining: Some(BanRemaining::Extend(-3600)),
+            ..BanUpdate::default()
+        };
+        bm.update(&subject, Some(1), &expire);
+        assert!(bm.find_ban(&[subject]).is_none());
+    }
+
+    #[test]
+    fn test_escalation_to_permanent() {
+        let mut bm = BanManager::new(3600, true, vec![1, 2, 4], BanInfo::PERMANENT, 30);
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+
+        let durations: Vec<i64> = (0..4)
+            .map(|_| {
+                bm.ban(ip, None, "test".to_string(), None)
+                    .ban_duration_seconds
+            })
+            .collect();
+        assert_eq!(durations, [3600, 7200, 14400, BanInfo::PERMANENT]);
+    }
+
+    #[test]
+    fn test_reset_escalation() {
+        let mut bm = BanManager::new(3600, true, vec![1, 2, 4], 86400, 30);
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+
+        bm.ban(ip, None, "first".to_string(), None);
+        assert!(bm.unban(ip, None));
+        assert!(bm.reset_escalation(&Subject::Ip(ip)));
+        let ban = bm.ban(ip, None, "second".to_string(), None);
+        assert_eq!((ban.escalation_level, ban.ban_duration_seconds), (0, 3600));
+    }
+}
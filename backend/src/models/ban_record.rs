@@ -0,0 +1,467 @@
+//! # Persistencia de bans
+//!
+//! Operaciones sobre la tabla `bans`. [`MemoryStore`](super::MemoryStore)
+//! escribe en ella cada ban y desban para restaurarlos al arrancar;
+//! [`PostgresStore`](super::PostgresStore) la usa como única fuente de verdad.
+//!
+//! Un ban no se borra al expirar o levantarse: se marca `expired`. Un
+//! `ban_duration_seconds` negativo es un ban permanente.
+
+use crate::models::ban_manager::{BanInfo, BanRemaining, BanUpdate};
+use crate::models::subject::{IpRange, Subject};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{
+    Error, Row,
+    postgres::{PgConnection, PgPool, PgRow},
+    query,
+};
+use std::net::IpAddr;
+use std::time::Duration;
+use tracing::warn;
+
+/// Condition selecting the bans still in force.
+const ACTIVE_BAN: &str = "NOT expired
+    AND (ban_duration_seconds < 0
+        OR banned_at + make_interval(secs => ban_duration_seconds) > NOW())";
+
+/// Natural end of a ban, `NULL` if it is permanent.
+const ENDS_AT: &str = "CASE WHEN ban_duration_seconds >= 0
+    THEN banned_at + make_interval(secs => ban_duration_seconds) END";
+
+/// Columns read into a [`BanRecord`].
+const COLUMNS: &str = "ip_address, rule_id, banned_at, ban_duration_seconds,
+    escalation_level, reason";
+
+/// A row of the `bans` table.
+#[derive(Debug, Clone)]
+pub struct BanRecord {
+    pub subject: Subject,
+    pub ban: BanInfo,
+}
+
+/// A past or current ban of a subject, as listed in its history.
+#[derive(Debug, Clone, Serialize)]
+pub struct BanHistoryEntry {
+    pub id: i32,
+    pub rule_id: Option<i32>,
+    pub jail_name: String,
+    pub banned_at: DateTime<Utc>,
+    pub ban_duration_seconds: i32,
+    pub escalation_level: i32,
+    pub reason: Option<String>,
+    /// Whether the ban is still in force.
+    pub active: bool,
+    /// Bans that made the recidive jail issue this one.
+    pub triggered_by: Option<Vec<i32>>,
+}
+
+/// A ban as published in the decisions stream.
+#[derive(Debug, Clone)]
+pub struct BanDecision {
+    pub id: i32,
+    pub subject: Subject,
+    pub reason: Option<String>,
+    /// When the ban ends, or ended if it was lifted early. `None` if it is
+    /// permanent.
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+/// A subject ranked by the number of bans it received.
+#[derive(Debug, Clone, Serialize)]
+pub struct Offender {
+    pub ip_address: String,
+    pub ban_count: i64,
+    pub last_banned_at: DateTime<Utc>,
+    /// Rules that banned the subject.
+    pub rule_ids: Vec<i32>,
+    /// Current escalation level (0 if it decayed).
+    pub escalation_level: i32,
+    /// Whether the subject is banned right now.
+    pub banned: bool,
+}
+
+impl BanRecord {
+    /// Reads a row; rows whose subject doesn't parse are skipped.
+    fn from_row(row: &PgRow) -> Option<Self> {
+        let ip_address: String = row.get("ip_address");
+        let subject = ip_address
+            .parse()
+            .inspect_err(|e| warn!("Ignoring ban with invalid subject: {e}"))
+            .ok()?;
+        let banned_at: DateTime<Utc> = row.get("banned_at");
+        let ban = BanInfo {
+            banned_at: BanInfo::instant_from(banned_at),
+            ban_duration_seconds: i64::from(row.get::<i32, _>("ban_duration_seconds")),
+            escalation_level: u32::try_from(row.get::<i32, _>("escalation_level"))
+                .unwrap_or_default(),
+            rule_id: row.get("rule_id"),
+            reason: row.get::<Option<String>, _>("reason").unwrap_or_default(),
+        };
+        Some(Self { subject, ban })
+    }
+
+    /// Stores a ban issued at `ban.banned_at`. A recidive ban carries the
+    /// ids of the bans that `triggered_by` it; other bans pass none.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn create(
+        pool: &PgPool,
+        subject: &Subject,
+        ban: &BanInfo,
+        triggered_by: &[i32],
+    ) -> Result<(), Error> {
+        let sql = "INSERT INTO bans (ip_address, rule_id, jail_name, banned_at,
+                ban_duration_seconds, escalation_level, reason, triggered_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+        let recidive = !triggered_by.is_empty();
+        query(sql)
+            .bind(subject.to_string())
+            .bind(ban.rule_id)
+            .bind(if recidive {
+                "recidive".to_string()
+            } else {
+                jail_name(ban.rule_id)
+            })
+            .bind(ban.issued_at())
+            .bind(i32::try_from(ban.ban_duration_seconds).unwrap_or(i32::MAX))
+            .bind(i32::try_from(ban.escalation_level).unwrap_or(i32::MAX))
+            .bind(&ban.reason)
+            .bind(recidive.then_some(triggered_by))
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Every ban still in force, oldest first.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn read_active(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let sql = format!("SELECT {COLUMNS} FROM bans WHERE {ACTIVE_BAN} ORDER BY banned_at");
+        let rows = query(&sql).fetch_all(pool).await?;
+        Ok(rows.iter().filter_map(Self::from_row).collect())
+    }
+
+    /// Oldest ban in force among `subjects`; IP subjects also match banned
+    /// ranges.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn find_active(pool: &PgPool, subjects: &[Subject]) -> Result<Option<Self>, Error> {
+        let keys: Vec<String> = subjects.iter().map(ToString::to_string).collect();
+        let ips: Vec<String> = subjects
+            .iter()
+            .filter(|subject| matches!(subject, Subject::Ip(_)))
+            .map(ToString::to_string)
+            .collect();
+        let sql = format!(
+            "SELECT {COLUMNS}
+            FROM bans
+            WHERE {ACTIVE_BAN}
+                AND (ip_address = ANY($1)
+                    OR CASE WHEN ip_address ~ '^[0-9A-Fa-f:.]+/[0-9]{{1,3}}$'
+                        THEN ip_address::inet >>= ANY($2::text[]::inet[])
+                        ELSE FALSE END)
+            ORDER BY banned_at
+            LIMIT 1"
+        );
+        let row = query(&sql)
+            .bind(keys)
+            .bind(ips)
+            .fetch_optional(pool)
+            .await?;
+        Ok(row.as_ref().and_then(Self::from_row))
+    }
+
+    /// Number of distinct IPs within `range` banned in the last `window`.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn distinct_ips_banned(
+        pool: &PgPool,
+        range: &IpRange,
+        window: Duration,
+    ) -> Result<i64, Error> {
+        query(
+            "SELECT COUNT(DISTINCT ip_address) FROM bans
+            WHERE banned_at > NOW() - make_interval(secs => $2)
+                AND CASE WHEN ip_address ~ '^[0-9A-Fa-f:.]+$'
+                    THEN ip_address::inet <<= $1::inet
+                    ELSE FALSE END",
+        )
+        .bind(range.to_string())
+        .bind(window.as_secs_f64())
+        .map(|row: PgRow| row.get(0))
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Every ban of exactly `subject`, newest first.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn history(pool: &PgPool, subject: &Subject) -> Result<Vec<BanHistoryEntry>, Error> {
+        let sql = format!(
+            "SELECT id, rule_id, jail_name, banned_at, ban_duration_seconds, escalation_level,
+                reason, ({ACTIVE_BAN}) AS active, triggered_by
+            FROM bans
+            WHERE ip_address = $1
+            ORDER BY banned_at DESC"
+        );
+        query(&sql)
+            .bind(subject.to_string())
+            .map(|row: PgRow| BanHistoryEntry {
+                id: row.get("id"),
+                rule_id: row.get("rule_id"),
+                jail_name: row.get("jail_name"),
+                banned_at: row.get("banned_at"),
+                ban_duration_seconds: row.get("ban_duration_seconds"),
+                escalation_level: row.get("escalation_level"),
+                reason: row.get("reason"),
+                active: row.get("active"),
+                triggered_by: row.get("triggered_by"),
+            })
+            .fetch_all(pool)
+            .await
+    }
+
+    /// The `limit` subjects with most bans, counting only those issued in the
+    /// last `days` days if given. Escalation levels older than `decay_days`
+    /// count as 0.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn offenders(
+        pool: &PgPool,
+        days: Option<i64>,
+        decay_days: i64,
+        limit: i64,
+    ) -> Result<Vec<Offender>, Error> {
+        let sql = format!(
+            "SELECT b.ip_address,
+                COUNT(*) AS ban_count,
+                MAX(b.banned_at) AS last_banned_at,
+                COALESCE(ARRAY_AGG(DISTINCT b.rule_id) FILTER (WHERE b.rule_id IS NOT NULL),
+                    '{{}}') AS rule_ids,
+                COALESCE(MAX(e.level) FILTER (
+                    WHERE e.last_ban_at > NOW() - make_interval(days => $2)), 0) AS escalation_level,
+                BOOL_OR({ACTIVE_BAN}) AS banned
+            FROM bans b
+            LEFT JOIN ban_escalations e ON e.subject = b.ip_address
+            WHERE $1::int IS NULL OR b.banned_at > NOW() - make_interval(days => $1)
+            GROUP BY b.ip_address
+            ORDER BY ban_count DESC, last_banned_at DESC
+            LIMIT $3"
+        );
+        query(&sql)
+            .bind(days.map(|days| i32::try_from(days).unwrap_or(i32::MAX)))
+            .bind(i32::try_from(decay_days).unwrap_or(i32::MAX))
+            .bind(limit)
+            .map(|row: PgRow| Offender {
+                ip_address: row.get("ip_address"),
+                ban_count: row.get("ban_count"),
+                last_banned_at: row.get("last_banned_at"),
+                rule_ids: row.get("rule_ids"),
+                escalation_level: row.get("escalation_level"),
+                banned: row.get("banned"),
+            })
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Bans in force stored after `since`, or all of them without it.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn started_since(
+        connection: &mut PgConnection,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<BanDecision>, Error> {
+        let sql = format!(
+            "SELECT id, ip_address, reason, {ENDS_AT} AS ends_at
+            FROM bans
+            WHERE {ACTIVE_BAN} AND ($1::timestamptz IS NULL OR created_at > $1)
+            ORDER BY id"
+        );
+        let rows = query(&sql).bind(since).fetch_all(connection).await?;
+        Ok(rows.iter().filter_map(BanDecision::from_row).collect())
+    }
+
+    /// Bans in force whose subject is one of `subjects` or, with `ip`, an
+    /// address range containing it.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn covering(
+        pool: &PgPool,
+        subjects: &[String],
+        ip: Option<IpAddr>,
+    ) -> Result<Vec<BanDecision>, Error> {
+        let sql = format!(
+            "SELECT id, ip_address, reason, {ENDS_AT} AS ends_at
+            FROM bans
+            WHERE {ACTIVE_BAN}
+                AND (ip_address = ANY($1)
+                    OR CASE WHEN ip_address ~ '^[0-9A-Fa-f.:]+/[0-9]+$'
+                        THEN $2::inet <<= ip_address::inet ELSE FALSE END)
+            ORDER BY id"
+        );
+        let rows = query(&sql)
+            .bind(subjects)
+            .bind(ip.map(|ip| ip.to_string()))
+            .fetch_all(pool)
+            .await?;
+        Ok(rows.iter().filter_map(BanDecision::from_row).collect())
+    }
+
+    /// Bans that stopped being in force after `since`, leaving their subject
+    /// unbanned.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn ended_since(
+        connection: &mut PgConnection,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<BanDecision>, Error> {
+        let sql = format!(
+            "SELECT id, ip_address, reason, ends_at
+            FROM (
+                SELECT id, ip_address, reason, COALESCE(expired_at, {ENDS_AT}) AS ends_at
+                FROM bans
+            ) ended
+            WHERE ends_at > $1 AND ends_at <= NOW()
+                AND NOT EXISTS (
+                    SELECT 1 FROM bans WHERE bans.ip_address = ended.ip_address AND {ACTIVE_BAN})
+            ORDER BY id"
+        );
+        let rows = query(&sql).bind(since).fetch_all(connection).await?;
+        Ok(rows.iter().filter_map(BanDecision::from_row).collect())
+    }
+
+    /// Ids of the rule bans of `subject` issued in the last `days` days and
+    /// after its last recidive ban.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn recidive_triggers(
+        pool: &PgPool,
+        subject: &Subject,
+        days: i32,
+    ) -> Result<Vec<i32>, Error> {
+        query(
+            "SELECT id FROM bans
+            WHERE ip_address = $1 AND rule_id IS NOT NULL AND triggered_by IS NULL
+                AND banned_at > NOW() - make_interval(days => $2)
+                AND banned_at > COALESCE((
+                    SELECT MAX(banned_at) FROM bans
+                    WHERE ip_address = $1 AND triggered_by IS NOT NULL), '-infinity')
+            ORDER BY id",
+        )
+        .bind(subject.to_string())
+        .bind(days)
+        .map(|row: PgRow| row.get(0))
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Marks the bans of `subject` issued by `rule_id` as expired. Returns
+    /// the number of bans lifted.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn expire(
+        pool: &PgPool,
+        subject: &Subject,
+        rule_id: Option<i32>,
+    ) -> Result<u64, Error> {
+        let result = query(
+            "UPDATE bans SET expired = TRUE, expired_at = NOW()
+            WHERE ip_address = $1 AND rule_id IS NOT DISTINCT FROM $2 AND NOT expired",
+        )
+        .bind(subject.to_string())
+        .bind(rule_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Applies `update` to the bans in force of `subject` issued by
+    /// `rule_id`. Returns the updated bans.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn update(
+        pool: &PgPool,
+        subject: &Subject,
+        rule_id: Option<i32>,
+        update: &BanUpdate,
+    ) -> Result<Vec<Self>, Error> {
+        let (remaining, seconds) = match update.remaining {
+            Some(BanRemaining::Set(seconds)) => (Some("set"), seconds),
+            Some(BanRemaining::Extend(seconds)) => (Some("extend"), seconds),
+            Some(BanRemaining::Permanent) => (Some("permanent"), 0),
+            None => (None, 0),
+        };
+        let sql = format!(
+            "UPDATE bans SET
+                reason = COALESCE($3, reason),
+                ban_duration_seconds = CASE
+                    WHEN $4 = 'set' THEN LEAST(
+                        FLOOR(EXTRACT(EPOCH FROM NOW() - banned_at))::bigint + $5,
+                        2147483647)::int
+                    WHEN $4 = 'extend' AND ban_duration_seconds >= 0
+                        THEN LEAST(GREATEST(ban_duration_seconds + $5, 0), 2147483647)::int
+                    WHEN $4 = 'permanent' THEN {permanent}
+                    ELSE ban_duration_seconds
+                END
+            WHERE ip_address = $1 AND rule_id IS NOT DISTINCT FROM $2 AND {ACTIVE_BAN}
+            RETURNING {COLUMNS}",
+            permanent = BanInfo::PERMANENT,
+        );
+        let rows = query(&sql)
+            .bind(subject.to_string())
+            .bind(rule_id)
+            .bind(update.reason.as_deref())
+            .bind(remaining)
+            .bind(seconds)
+            .fetch_all(pool)
+            .await?;
+        Ok(rows.iter().filter_map(Self::from_row).collect())
+    }
+
+    /// Marks the bans whose time is up as expired. Returns how many.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn expire_elapsed(pool: &PgPool) -> Result<u64, Error> {
+        let result = query(
+            "UPDATE bans
+            SET expired = TRUE,
+                expired_at = banned_at + make_interval(secs => ban_duration_seconds)
+            WHERE NOT expired AND ban_duration_seconds >= 0
+                AND banned_at + make_interval(secs => ban_duration_seconds) <= NOW()",
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+impl BanDecision {
+    /// Reads a row; rows whose subject doesn't parse are skipped.
+    fn from_row(row: &PgRow) -> Option<Self> {
+        Some(Self {
+            id: row.get("id"),
+            subject: row.get::<String, _>("ip_address").parse().ok()?,
+            reason: row.get("reason"),
+            ends_at: row.get("ends_at"),
+        })
+    }
+}
+
+/// Name of the jail a ban belongs to, for the `bans.jail_name` column.
+fn jail_name(rule_id: Option<i32>) -> String {
+    rule_id.map_or_else(|| "manual".to_string(), |id| format!("rule:{id}"))
+}
@@ -0,0 +1,153 @@
+//! # Bouncers
+//!
+//! Clientes del stream de decisiones compatible con `CrowdSec`
+//! (`/v1/decisions/stream`). Cada bouncer se autentica con una API key en la
+//! cabecera `X-Api-Key`; en la base de datos sólo se guarda su SHA-256.
+//!
+//! `last_pull_at` marca la última consulta del stream: lo publicado o
+//! retirado desde entonces es lo que el bouncer recibe en la siguiente.
+
+use chrono::{DateTime, Utc};
+use openssl::sha::sha256;
+use rand::{Rng, distributions::Alphanumeric};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    Error, Row,
+    postgres::{PgConnection, PgPool, PgRow},
+    query,
+};
+use std::fmt::Write;
+
+/// Length of a generated API key.
+const API_KEY_LENGTH: usize = 40;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Bouncer {
+    pub id: i32,
+    pub name: String,
+    pub last_pull_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NewBouncer {
+    pub name: String,
+}
+
+/// A bouncer just created, with the only copy of its API key.
+#[derive(Debug, Serialize)]
+pub struct CreatedBouncer {
+    #[serde(flatten)]
+    pub bouncer: Bouncer,
+    pub api_key: String,
+}
+
+/// Hex SHA-256 of an API key, as stored.
+fn hash_key(api_key: &str) -> String {
+    sha256(api_key.as_bytes())
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+impl Bouncer {
+    fn from_row(row: &PgRow) -> Self {
+        Self {
+            id: row.get("id"),
+            name: row.get("name"),
+            last_pull_at: row.get("last_pull_at"),
+            created_at: row.get("created_at"),
+        }
+    }
+
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn read_all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        query("SELECT id, name, last_pull_at, created_at FROM bouncers ORDER BY name")
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Registers a bouncer with a new random API key.
+    ///
+    /// # Errors
+    /// Returns the underlying database error, e.g. if the name is taken.
+    pub async fn create(pool: &PgPool, bouncer: NewBouncer) -> Result<CreatedBouncer, Error> {
+        let api_key: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(API_KEY_LENGTH)
+            .map(char::from)
+            .collect();
+        let bouncer = query(
+            "INSERT INTO bouncers (name, api_key_hash) VALUES ($1, $2)
+            RETURNING id, name, last_pull_at, created_at",
+        )
+        .bind(bouncer.name)
+        .bind(hash_key(&api_key))
+        .map(|row: PgRow| Self::from_row(&row))
+        .fetch_one(pool)
+        .await?;
+        Ok(CreatedBouncer { bouncer, api_key })
+    }
+
+    /// # Errors
+    /// [`Error::RowNotFound`] if there is no such bouncer, or the underlying
+    /// database error.
+    pub async fn delete(pool: &PgPool, id: i32) -> Result<Self, Error> {
+        query("DELETE FROM bouncers WHERE id = $1 RETURNING id, name, last_pull_at, created_at")
+            .bind(id)
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_one(pool)
+            .await
+    }
+
+    /// The bouncer owning `api_key`, if any.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn authenticate(pool: &PgPool, api_key: &str) -> Result<Option<Self>, Error> {
+        query("SELECT id, name, last_pull_at, created_at FROM bouncers WHERE api_key_hash = $1")
+            .bind(hash_key(api_key))
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Records a pull of the stream at the start of the transaction of
+    /// `connection`, so it only counts once that commits. Returns the time of
+    /// the previous pull, `None` if this is the first one.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn record_pull(
+        connection: &mut PgConnection,
+        id: i32,
+    ) -> Result<Option<DateTime<Utc>>, Error> {
+        query(
+            "UPDATE bouncers SET last_pull_at = NOW()
+            FROM (SELECT last_pull_at FROM bouncers WHERE id = $1 FOR UPDATE) previous
+            WHERE id = $1
+            RETURNING previous.last_pull_at",
+        )
+        .bind(id)
+        .map(|row: PgRow| row.get("last_pull_at"))
+        .fetch_one(connection)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_key() {
+        assert_eq!(
+            hash_key("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,454 @@
+//! # Decisiones de `CrowdSec`
+//!
+//! Copia en memoria de las decisiones que publica una LAPI de `CrowdSec`
+//! (`/v1/decisions/stream`), consultada por `shuul()` antes de las reglas.
+//!
+//! Se guardan aparte de los bans de [`BanManager`](super::BanManager): no se
+//! persisten, no cuentan para la escalada y un desbaneo local no las quita.
+//! Sólo desaparecen cuando la LAPI las borra o cuando vence su duración.
+//!
+//! [`LapiDecision::from_ban`] hace el camino inverso: publica los bans locales
+//! en el mismo formato para otros bouncers.
+//!
+//! Ámbitos admitidos: `Ip`, `Range`, `AS` y `Country`. El tipo `captcha` se
+//! traduce en un challenge y cualquier otro (`ban`, `throttle`...) en un deny.
+
+use crate::models::RuleAction;
+use crate::models::ban_record::BanDecision;
+use crate::models::ip_list::parse_address;
+use crate::models::subject::{IpRange, IpSet, Subject};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// Duration published for a permanent ban, since decisions always have one.
+pub const PERMANENT_DURATION: &str = "87600h";
+
+/// A decision as sent by the LAPI.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LapiDecision {
+    pub id: i64,
+    #[serde(default)]
+    pub origin: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub scope: String,
+    pub value: String,
+    /// Remaining time, as a Go duration (`3h59m58.5s`).
+    #[serde(default)]
+    pub duration: String,
+    #[serde(default)]
+    pub scenario: String,
+}
+
+impl LapiDecision {
+    /// Publishes a local ban; `None` for rate-limit keys, which have no
+    /// `CrowdSec` scope. Bans that already ended get a negative duration and
+    /// permanent ones [`PERMANENT_DURATION`].
+    #[must_use]
+    pub fn from_ban(ban: &BanDecision, now: DateTime<Utc>) -> Option<Self> {
+        let (scope, value) = match &ban.subject {
+            Subject::Ip(ip) => ("Ip", ip.to_string()),
+            Subject::Range(range) => ("Range", range.to_string()),
+            Subject::Asn(asn) => ("AS", asn.to_string()),
+            Subject::Country(code) => ("Country", code.clone()),
+            Subject::Key(_) => return None,
+        };
+        Some(Self {
+            id: i64::from(ban.id),
+            origin: "shuul".to_string(),
+            kind: "ban".to_string(),
+            scope: scope.to_string(),
+            value,
+            duration: ban.ends_at.map_or_else(
+                || PERMANENT_DURATION.to_string(),
+                |ends_at| format!("{}s", (ends_at - now).num_seconds()),
+            ),
+            scenario: ban.reason.clone().unwrap_or_else(|| "shuul".to_string()),
+        })
+    }
+}
+
+/// Body of `GET /v1/decisions/stream`. The LAPI sends `null` for empty lists.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct DecisionStream {
+    #[serde(default)]
+    pub new: Option<Vec<LapiDecision>>,
+    #[serde(default)]
+    pub deleted: Option<Vec<LapiDecision>>,
+}
+
+/// An active `CrowdSec` decision.
+#[derive(Debug, Clone, Serialize)]
+pub struct CrowdSecDecision {
+    pub id: i64,
+    pub origin: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub scope: String,
+    pub value: String,
+    pub scenario: String,
+    /// What `shuul()` does with a matching request.
+    pub action: RuleAction,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl CrowdSecDecision {
+    /// Converts a LAPI decision; `None` if its duration is unparseable.
+    #[must_use]
+    pub fn from_lapi(decision: LapiDecision, now: DateTime<Utc>) -> Option<Self> {
+        let duration = parse_go_duration(&decision.duration)?;
+        let action = if decision.kind.eq_ignore_ascii_case("captcha") {
+            RuleAction::Challenge
+        } else {
+            RuleAction::Deny
+        };
+        Some(Self {
+            id: decision.id,
+            origin: decision.origin,
+            kind: decision.kind,
+            scope: decision.scope,
+            value: decision.value,
+            scenario: decision.scenario,
+            action,
+            expires_at: now + duration,
+        })
+    }
+
+    /// Reason shown to the client and in the logs.
+    #[must_use]
+    pub fn reason(&self) -> String {
+        format!("crowdsec {} ({})", self.scenario, self.origin)
+    }
+}
+
+/// Parses a Go duration (`1h2m3.5s`, `150ms`, `-2s`). Negative durations
+/// (already expired) come out as zero.
+#[must_use]
+pub fn parse_go_duration(s: &str) -> Option<chrono::Duration> {
+    let (negative, mut rest) = s
+        .strip_prefix('-')
+        .map_or_else(|| (false, s.trim_start_matches('+')), |rest| (true, rest));
+    if rest.is_empty() {
+        return None;
+    }
+    let mut total = 0.0_f64;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .filter(|split| *split > 0)?;
+        let (number, tail) = rest.split_at(split);
+        let number: f64 = number.parse().ok()?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let seconds = match unit {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 1e-3,
+            "us" | "µs" => 1e-6,
+            "ns" => 1e-9,
+            _ => return None,
+        };
+        total += number * seconds;
+        rest = tail;
+    }
+    if negative {
+        return Some(chrono::Duration::zero());
+    }
+    std::time::Duration::try_from_secs_f64(total)
+        .ok()
+        .and_then(|duration| chrono::Duration::from_std(duration).ok())
+}
+
+/// The active decisions, indexed by scope. A value may have several
+/// decisions (say a CAPI ban and a local captcha), so each maps to all of
+/// their ids.
+#[derive(Debug, Default)]
+pub struct CrowdSecDecisions {
+    decisions: HashMap<i64, CrowdSecDecision>,
+    ips: IpSet<Vec<i64>>,
+    asns: HashMap<u32, Vec<i64>>,
+    countries: HashMap<String, Vec<i64>>,
+    /// Where `captcha` decisions are redirected; without it they deny.
+    captcha_url: Option<String>,
+}
+
+impl CrowdSecDecisions {
+    #[must_use]
+    pub fn new(captcha_url: Option<String>) -> Self {
+        Self {
+            captcha_url,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn captcha_url(&self) -> Option<&str> {
+        self.captcha_url.as_deref()
+    }
+
+    /// Applies a stream response. With `startup` the `new` list is the full
+    /// set, so the previous decisions are dropped. Returns how many decisions
+    /// were added and deleted.
+    pub fn apply(
+        &mut self,
+        stream: DecisionStream,
+        startup: bool,
+        now: DateTime<Utc>,
+    ) -> (usize, usize) {
+        if startup {
+            self.decisions.clear();
+        }
+        let mut deleted = 0;
+        for decision in stream.deleted.unwrap_or_default() {
+            deleted += usize::from(self.decisions.remove(&decision.id).is_some());
+        }
+        let mut added = 0;
+        for decision in stream.new.unwrap_or_default() {
+            if let Some(decision) = CrowdSecDecision::from_lapi(decision, now) {
+                self.decisions.insert(decision.id, decision);
+                added += 1;
+            }
+        }
+        self.reindex();
+        (added, deleted)
+    }
+
+    /// Drops the decisions whose duration has passed.
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let before = self.decisions.len();
+        self.decisions
+            .retain(|_, decision| decision.expires_at > now);
+        if self.decisions.len() != before {
+            self.reindex();
+        }
+    }
+
+    fn reindex(&mut self) {
+        let mut ranges: HashMap<IpRange, Vec<i64>> = HashMap::new();
+        self.asns.clear();
+        self.countries.clear();
+        for decision in self.decisions.values() {
+            match decision.scope.to_ascii_lowercase().as_str() {
+                "ip" | "range" => {
+                    if let Ok(range) = parse_address(&decision.value) {
+                        ranges.entry(range).or_default().push(decision.id);
+                    }
+                },
+                "as" => {
+                    if let Ok(asn) = decision.value.trim_start_matches("AS").parse() {
+                        self.asns.entry(asn).or_default().push(decision.id);
+                    }
+                },
+                "country" => {
+                    self.countries
+                        .entry(decision.value.to_ascii_uppercase())
+                        .or_default()
+                        .push(decision.id);
+                },
+                _ => {},
+            }
+        }
+        self.ips = IpSet::default();
+        for (range, ids) in ranges {
+            self.ips.insert(range, ids);
+        }
+    }
+
+    /// The decision covering a client, if any. Deny decisions win over
+    /// captcha ones.
+    #[must_use]
+    pub fn find(
+        &self,
+        ip: Option<&IpAddr>,
+        asn: Option<u32>,
+        country_code: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Option<&CrowdSecDecision> {
+        let ids = ip
+            .into_iter()
+            .flat_map(|ip| self.ips.matches(ip))
+            .chain(asn.and_then(|asn| self.asns.get(&asn)))
+            .chain(country_code.and_then(|code| self.countries.get(&code.to_ascii_uppercase())))
+            .flatten();
+        ids.filter_map(|id| self.decisions.get(id))
+            .filter(|decision| decision.expires_at > now)
+            .min_by_key(|decision| decision.action != RuleAction::Deny)
+    }
+
+    /// The decision banning exactly `subject`, or covering it if it is an IP.
+    #[must_use]
+    pub fn for_subject(&self, subject: &Subject, now: DateTime<Utc>) -> Option<&CrowdSecDecision> {
+        match subject {
+            Subject::Ip(ip) => self.find(Some(ip), None, None, now),
+            Subject::Range(range) => self.decisions.values().find(|decision| {
+                decision.expires_at > now && parse_address(&decision.value).as_ref() == Ok(range)
+            }),
+            Subject::Asn(asn) => self.find(None, Some(*asn), None, now),
+            Subject::Country(code) => self.find(None, None, Some(code), now),
+            Subject::Key(_) => None,
+        }
+    }
+
+    /// Every active decision, soonest to expire first.
+    #[must_use]
+    pub fn list(&self) -> Vec<CrowdSecDecision> {
+        let mut decisions: Vec<_> = self.decisions.values().cloned().collect();
+        decisions.sort_by_key(|decision| (decision.expires_at, decision.id));
+        decisions
+    }
+
+    /// Number of active decisions.
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.decisions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(id: i64, kind: &str, scope: &str, value: &str, duration: &str) -> LapiDecision {
+        LapiDecision {
+            id,
+            origin: "crowdsec".to_string(),
+            kind: kind.to_string(),
+            scope: scope.to_string(),
+            value: value.to_string(),
+            duration: duration.to_string(),
+            scenario: "crowdsecurity/http-probing".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_from_ban() {
+        let now = Utc::now();
+        let ban = BanDecision {
+            id: 7,
+            subject: "10.0.0.0/24".parse().unwrap(),
+            reason: Some("Rate limit exceeded".to_string()),
+            ends_at: Some(now + chrono::Duration::seconds(90)),
+        };
+        let decision = LapiDecision::from_ban(&ban, now).unwrap();
+        assert_eq!(
+            (decision.scope.as_str(), decision.value.as_str()),
+            ("Range", "10.0.0.0/24")
+        );
+        assert_eq!(decision.duration, "90s");
+        // Round trip through the bouncer side
+        let decision = CrowdSecDecision::from_lapi(decision, now).unwrap();
+        assert_eq!(Some(decision.expires_at), ban.ends_at);
+
+        let permanent = BanDecision {
+            ends_at: None,
+            ..ban.clone()
+        };
+        let decision = LapiDecision::from_ban(&permanent, now).unwrap();
+        assert_eq!(decision.duration, PERMANENT_DURATION);
+
+        let key = BanDecision {
+            subject: "key:abc".parse().unwrap(),
+            ..ban
+        };
+        assert!(LapiDecision::from_ban(&key, now).is_none());
+    }
+
+    #[test]
+    fn test_parse_go_duration() {
+        assert_eq!(
+            parse_go_duration("3h59m58.5s"),
+            Some(chrono::Duration::milliseconds(14_398_500))
+        );
+        assert_eq!(
+            parse_go_duration("150ms"),
+            Some(chrono::Duration::milliseconds(150))
+        );
+        assert_eq!(parse_go_duration("-2s"), Some(chrono::Duration::zero()));
+        assert_eq!(parse_go_duration(""), None);
+        assert_eq!(parse_go_duration("10x"), None);
+    }
+
+    #[test]
+    fn test_stream_and_find() {
+        let now = Utc::now();
+        let mut decisions = CrowdSecDecisions::new(None);
+        let stream: DecisionStream = serde_json::from_value(serde_json::json!({
+            "new": [
+                {"id": 1, "origin": "CAPI", "type": "ban", "scope": "Ip",
+                 "value": "192.0.2.1", "duration": "4h", "scenario": "ssh-bf"},
+                {"id": 2, "origin": "crowdsec", "type": "captcha", "scope": "Range",
+                 "value": "198.51.100.0/24", "duration": "1h", "scenario": "http-crawl"},
+                {"id": 3, "origin": "cscli", "type": "ban", "scope": "Country",
+                 "value": "XX", "duration": "1h", "scenario": "manual"}
+            ],
+            "deleted": null
+        }))
+        .unwrap();
+        assert_eq!(decisions.apply(stream, true, now), (3, 0));
+
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        assert_eq!(decisions.find(Some(&ip), None, None, now).unwrap().id, 1);
+        let ip: IpAddr = "198.51.100.7".parse().unwrap();
+        let found = decisions.find(Some(&ip), None, None, now).unwrap();
+        assert_eq!(found.action, RuleAction::Challenge);
+        // A deny decision wins over the captcha one
+        assert_eq!(
+            decisions.find(Some(&ip), None, Some("xx"), now).unwrap().id,
+            3
+        );
+
+        let stream = DecisionStream {
+            new: Some(vec![decision(4, "ban", "AS", "64500", "10s")]),
+            deleted: Some(vec![decision(1, "ban", "Ip", "192.0.2.1", "0s")]),
+        };
+        assert_eq!(decisions.apply(stream, false, now), (1, 1));
+        assert!(
+            decisions
+                .find(Some(&"192.0.2.1".parse().unwrap()), None, None, now)
+                .is_none()
+        );
+        assert_eq!(decisions.find(None, Some(64500), None, now).unwrap().id, 4);
+        let range: Subject = "198.51.100.0/24".parse().unwrap();
+        assert_eq!(decisions.for_subject(&range, now).unwrap().id, 2);
+
+        decisions.prune(now + chrono::Duration::seconds(11));
+        assert_eq!(decisions.count(), 2);
+        assert!(decisions.find(None, Some(64500), None, now).is_none());
+    }
+
+    #[test]
+    fn test_ban_wins_over_captcha_on_same_value() {
+        let now = Utc::now();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        // Both orders, as the index is rebuilt from a hash map
+        for (ban, captcha) in [(1, 2), (2, 1)] {
+            let mut decisions = CrowdSecDecisions::new(Some("https://captcha".to_string()));
+            let stream = DecisionStream {
+                new: Some(vec![
+                    decision(ban, "ban", "Ip", "192.0.2.1", "4h"),
+                    decision(captcha, "captcha", "Ip", "192.0.2.1", "4h"),
+                    decision(ban + 10, "ban", "AS", "64500", "4h"),
+                    decision(captcha + 10, "captcha", "AS", "64500", "4h"),
+                    decision(ban + 20, "ban", "Country", "XX", "4h"),
+                    decision(captcha + 20, "captcha", "Country", "XX", "4h"),
+                ]),
+                deleted: None,
+            };
+            decisions.apply(stream, true, now);
+            assert_eq!(decisions.find(Some(&ip), None, None, now).unwrap().id, ban);
+            assert_eq!(
+                decisions.find(None, Some(64500), None, now).unwrap().id,
+                ban + 10
+            );
+            assert_eq!(
+                decisions.find(None, None, Some("XX"), now).unwrap().id,
+                ban + 20
+            );
+        }
+    }
+}
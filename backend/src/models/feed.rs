@@ -0,0 +1,427 @@
+//! # Feeds de listas de bloqueo
+//!
+//! Listas de IPs externas (una URL o un fichero local) que se descargan en
+//! segundo plano cada `refresh_seconds`. Cada feed se convierte en un
+//! [`IpSet`] que sirve como fuente global de bloqueo (`deny`) o como condición
+//! de una regla (`rules.feed_id`).
+//!
+//! Formatos admitidos:
+//!
+//! - `plain`: una IP o rango CIDR por línea; `#` o `;` inician un comentario.
+//! - `spamhaus_drop`: `1.10.16.0/20 ; SBL256894`, o las líneas JSON de los
+//!   ficheros `drop_v4.json` y `drop_v6.json` (campo `cidr`).
+//! - `firehol_netset`: como `plain`, con cabecera de comentarios `#`.
+//! - `csv`: la dirección está en la columna `csv_column` (desde 0); las filas
+//!   que no la tienen, como la cabecera, se ignoran.
+
+use crate::models::ip_list::parse_address;
+use crate::models::subject::{IpRange, IpSet};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    Error, Row,
+    postgres::{PgPool, PgRow},
+    query,
+};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// How the body of a feed is parsed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedFormat {
+    #[default]
+    Plain,
+    SpamhausDrop,
+    FireholNetset,
+    Csv,
+}
+
+impl FeedFormat {
+    /// Value stored in the database.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Plain => "plain",
+            Self::SpamhausDrop => "spamhaus_drop",
+            Self::FireholNetset => "firehol_netset",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for FeedFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "spamhaus_drop" => Ok(Self::SpamhausDrop),
+            "firehol_netset" => Ok(Self::FireholNetset),
+            "csv" => Ok(Self::Csv),
+            other => Err(format!("Unknown feed format: {other}")),
+        }
+    }
+}
+
+/// Addresses of a feed body, and the number of lines that looked like data
+/// but didn't parse.
+#[derive(Debug, Default)]
+pub struct ParsedFeed {
+    pub ranges: Vec<IpRange>,
+    pub invalid: usize,
+}
+
+/// Parses the body of a feed.
+#[must_use]
+pub fn parse_feed(format: FeedFormat, csv_column: usize, body: &str) -> ParsedFeed {
+    let mut parsed = ParsedFeed::default();
+    for line in body.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let address = match format {
+            FeedFormat::SpamhausDrop if line.starts_with('{') => {
+                // JSON lines; the last one is metadata without `cidr`
+                let Ok(value) = serde_json::from_str::<serde_json::Value>(line) else {
+                    parsed.invalid += 1;
+                    continue;
+                };
+                match value.get("cidr").and_then(serde_json::Value::as_str) {
+                    Some(cidr) => cidr.to_string(),
+                    None => continue,
+                }
+            },
+            FeedFormat::Plain | FeedFormat::FireholNetset | FeedFormat::SpamhausDrop => data(line),
+            FeedFormat::Csv => {
+                let Some(field) = line.split(',').nth(csv_column) else {
+                    continue;
+                };
+                let field = field.trim().trim_matches('"').to_string();
+                // Headers and other rows without an address
+                if !field.contains(|c: char| c.is_ascii_digit()) {
+                    continue;
+                }
+                field
+            },
+        };
+        match parse_address(&address) {
+            Ok(range) => parsed.ranges.push(range),
+            Err(_) => parsed.invalid += 1,
+        }
+    }
+    parsed
+}
+
+/// First token of a line, without a trailing `#` or `;` comment.
+fn data(line: &str) -> String {
+    line.split(['#', ';'])
+        .next()
+        .and_then(|data| data.split_whitespace().next())
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Feed {
+    pub id: i32,
+    pub name: String,
+    /// http(s) URL, file:// URL or local path.
+    pub source: String,
+    pub format: FeedFormat,
+    pub csv_column: i32,
+    pub refresh_seconds: i32,
+    /// Deny listed clients before bans and rules.
+    pub deny: bool,
+    pub active: bool,
+    pub last_fetch_at: Option<DateTime<Utc>>,
+    /// `ok` or the error of the last fetch.
+    pub last_status: Option<String>,
+    pub entry_count: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NewFeed {
+    pub name: String,
+    pub source: String,
+    #[serde(default)]
+    pub format: FeedFormat,
+    pub csv_column: Option<i32>,
+    pub refresh_seconds: Option<i32>,
+    #[serde(default)]
+    pub deny: bool,
+    pub active: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct UpdateFeed {
+    pub id: i32,
+    pub name: String,
+    pub source: String,
+    pub format: FeedFormat,
+    pub csv_column: i32,
+    pub refresh_seconds: i32,
+    pub deny: bool,
+    pub active: bool,
+}
+
+impl Feed {
+    fn from_row(row: &PgRow) -> Self {
+        Self {
+            id: row.get("id"),
+            name: row.get("name"),
+            source: row.get("source"),
+            format: row.get::<String, _>("format").parse().unwrap_or_default(),
+            csv_column: row.get("csv_column"),
+            refresh_seconds: row.get("refresh_seconds"),
+            deny: row.get("deny"),
+            active: row.get("active"),
+            last_fetch_at: row.get("last_fetch_at"),
+            last_status: row.get("last_status"),
+            entry_count: row.get("entry_count"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        }
+    }
+
+    /// Column holding the address, for the csv format.
+    #[must_use]
+    pub fn csv_column(&self) -> usize {
+        usize::try_from(self.csv_column).unwrap_or_default()
+    }
+
+    /// `true` if the feed is active and was never fetched or its refresh
+    /// interval has passed.
+    #[must_use]
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.active
+            && self.last_fetch_at.is_none_or(|at| {
+                now - at >= chrono::Duration::seconds(i64::from(self.refresh_seconds))
+            })
+    }
+
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn read_all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        query("SELECT * FROM feeds ORDER BY id")
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_all(pool)
+            .await
+    }
+
+    /// # Errors
+    /// [`Error::RowNotFound`] if there is no such feed, or the underlying
+    /// database error.
+    pub async fn read(pool: &PgPool, id: i32) -> Result<Self, Error> {
+        query("SELECT * FROM feeds WHERE id = $1")
+            .bind(id)
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_one(pool)
+            .await
+    }
+
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn create(pool: &PgPool, feed: NewFeed) -> Result<Self, Error> {
+        let sql = "INSERT INTO feeds (name, source, format, csv_column, refresh_seconds, deny,
+                active)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *";
+        query(sql)
+            .bind(feed.name)
+            .bind(feed.source)
+            .bind(feed.format.as_str())
+            .bind(feed.csv_column.unwrap_or(0))
+            .bind(feed.refresh_seconds.unwrap_or(3600))
+            .bind(feed.deny)
+            .bind(feed.active)
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Replaces the configuration of a feed. Changing its source or format
+    /// makes it due for a fetch.
+    ///
+    /// # Errors
+    /// [`Error::RowNotFound`] if there is no such feed, or the underlying
+    /// database error.
+    pub async fn update(pool: &PgPool, feed: UpdateFeed) -> Result<Self, Error> {
+        let sql = "UPDATE feeds SET
+                name = $2,
+                last_fetch_at = CASE
+                    WHEN source = $3 AND format = $4 AND csv_column = $5 THEN last_fetch_at
+                END,
+                source = $3,
+                format = $4,
+                csv_column = $5,
+                refresh_seconds = $6,
+                deny = $7,
+                active = $8,
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING *";
+        query(sql)
+            .bind(feed.id)
+            .bind(feed.name)
+            .bind(feed.source)
+            .bind(feed.format.as_str())
+            .bind(feed.csv_column)
+            .bind(feed.refresh_seconds)
+            .bind(feed.deny)
+            .bind(feed.active)
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_one(pool)
+            .await
+    }
+
+    /// # Errors
+    /// [`Error::RowNotFound`] if there is no such feed, or the underlying
+    /// database error.
+    pub async fn delete(pool: &PgPool, id: i32) -> Result<Self, Error> {
+        query("DELETE FROM feeds WHERE id = $1 RETURNING *")
+            .bind(id)
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Records the outcome of a fetch. `entry_count` is only updated on
+    /// success, so a failed fetch keeps the previous count.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn record_fetch(
+        pool: &PgPool,
+        id: i32,
+        status: &str,
+        entry_count: Option<usize>,
+    ) -> Result<(), Error> {
+        query(
+            "UPDATE feeds SET
+                last_fetch_at = NOW(),
+                last_status = $2,
+                entry_count = COALESCE($3, entry_count)
+            WHERE id = $1",
+        )
+        .bind(id)
+        .bind(status)
+        .bind(entry_count.map(|count| i32::try_from(count).unwrap_or(i32::MAX)))
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// A fetched feed.
+#[derive(Debug, Default)]
+struct FeedSet {
+    deny: bool,
+    set: IpSet<()>,
+}
+
+/// In-memory copy of the fetched feeds, by feed id.
+#[derive(Debug, Default)]
+pub struct FeedSets {
+    feeds: HashMap<i32, FeedSet>,
+}
+
+impl FeedSets {
+    /// Replaces the addresses of feed `id`.
+    pub fn replace(&mut self, id: i32, deny: bool, ranges: Vec<IpRange>) {
+        self.feeds.insert(
+            id,
+            FeedSet {
+                deny,
+                set: ranges.into_iter().collect(),
+            },
+        );
+    }
+
+    /// Updates the `deny` flag of a fetched feed.
+    pub fn set_deny(&mut self, id: i32, deny: bool) {
+        if let Some(feed) = self.feeds.get_mut(&id) {
+            feed.deny = deny;
+        }
+    }
+
+    pub fn remove(&mut self, id: i32) {
+        self.feeds.remove(&id);
+    }
+
+    /// `true` if feed `id` lists `ip`.
+    #[must_use]
+    pub fn contains(&self, id: i32, ip: &IpAddr) -> bool {
+        self.feeds
+            .get(&id)
+            .is_some_and(|feed| feed.set.contains(ip))
+    }
+
+    /// A `deny` feed listing `ip`, if any.
+    #[must_use]
+    pub fn denying(&self, ip: &IpAddr) -> Option<i32> {
+        self.feeds
+            .iter()
+            .find(|(_, feed)| feed.deny && feed.set.contains(ip))
+            .map(|(id, _)| *id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_formats() {
+        let plain = parse_feed(
+            FeedFormat::Plain,
+            0,
+            "# comment\n1.2.3.4\n10.0.0.0/8 # office\n\nnot-an-ip\n",
+        );
+        assert_eq!(plain.ranges.len(), 2);
+        assert_eq!(plain.invalid, 1);
+
+        let drop = parse_feed(
+            FeedFormat::SpamhausDrop,
+            0,
+            "; Spamhaus DROP List\n1.10.16.0/20 ; SBL256894\n\
+             {\"cidr\":\"2.56.192.0/22\",\"sblid\":\"SBL459831\",\"rir\":\"ripencc\"}\n\
+             {\"type\":\"metadata\",\"timestamp\":1700000000}\n",
+        );
+        assert_eq!(
+            drop.ranges
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["1.10.16.0/20", "2.56.192.0/22"]
+        );
+        assert_eq!(drop.invalid, 0);
+
+        let csv = parse_feed(
+            FeedFormat::Csv,
+            1,
+            "id,ip,reason\n1,192.0.2.1,scanner\n2,\"2001:db8::1\",spam\n",
+        );
+        assert_eq!(csv.ranges.len(), 2);
+        assert_eq!(csv.invalid, 0);
+    }
+
+    #[test]
+    fn test_feed_sets() {
+        let mut sets = FeedSets::default();
+        sets.replace(1, false, vec!["10.0.0.0/8".parse().unwrap()]);
+        sets.replace(2, true, vec!["192.0.2.0/24".parse().unwrap()]);
+        let ip: IpAddr = "10.1.2.3".parse().unwrap();
+
+        assert!(sets.contains(1, &ip));
+        assert!(!sets.contains(2, &ip));
+        assert_eq!(sets.denying(&ip), None);
+        assert_eq!(sets.denying(&"192.0.2.7".parse().unwrap()), Some(2));
+        sets.set_deny(1, true);
+        assert_eq!(sets.denying(&ip), Some(1));
+    }
+}
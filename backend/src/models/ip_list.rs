@@ -0,0 +1,332 @@
+//! # Listas globales de IPs
+//!
+//! Lista de permitidos y lista de bloqueados, independientes de las reglas.
+//! Cada entrada es una IP o un rango CIDR, con comentario y caducidad
+//! opcionales, y vive en la tabla `ip_lists`.
+//!
+//! `shuul()` las consulta antes que los bans y las reglas a través de
+//! [`IpLists`], una copia en memoria en dos [`IpSet`].
+
+use crate::models::subject::{IpRange, IpSet};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    Error, Row,
+    postgres::{PgPool, PgRow},
+    query,
+};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Which global list an entry belongs to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum IpListKind {
+    /// Bypasses bans, rate limiting and rules.
+    Allow,
+    /// Denied before any rule is evaluated.
+    Deny,
+}
+
+impl IpListKind {
+    /// Value stored in the database.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+        }
+    }
+}
+
+impl fmt::Display for IpListKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for IpListKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "deny" => Ok(Self::Deny),
+            other => Err(format!("Unknown IP list: {other}")),
+        }
+    }
+}
+
+/// Parses an IP (`1.2.3.4`) or a CIDR range (`1.2.3.0/24`); a single IP
+/// becomes a /32 or /128 range.
+///
+/// # Errors
+/// Returns a message naming the invalid address.
+pub fn parse_address(address: &str) -> Result<IpRange, String> {
+    let address = address.trim();
+    if address.contains('/') {
+        return address.parse();
+    }
+    let ip: IpAddr = address
+        .parse()
+        .map_err(|_| format!("Invalid IP address: {address}"))?;
+    let prefix_len = if ip.is_ipv4() { 32 } else { 128 };
+    IpRange::new(ip, prefix_len).ok_or_else(|| format!("Invalid IP address: {address}"))
+}
+
+/// An entry of a global list.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IpListEntry {
+    pub id: i32,
+    pub list: IpListKind,
+    /// CIDR range, e.g. `1.2.3.0/24` or `1.2.3.4/32`.
+    pub address: String,
+    pub comment: Option<String>,
+    /// The entry is ignored from this moment on.
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NewIpListEntry {
+    pub list: IpListKind,
+    pub address: String,
+    pub comment: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Replaces the comment and expiry of an entry.
+#[derive(Debug, Deserialize, Clone)]
+pub struct UpdateIpListEntry {
+    pub id: i32,
+    pub comment: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl IpListEntry {
+    fn from_row(row: &PgRow) -> Self {
+        Self {
+            id: row.get("id"),
+            list: row
+                .get::<String, _>("list")
+                .parse()
+                .unwrap_or(IpListKind::Deny),
+            address: row.get("address"),
+            comment: row.get("comment"),
+            expires_at: row.get("expires_at"),
+            created_at: row.get("created_at"),
+        }
+    }
+
+    /// Every entry, of `list` if given, newest first.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn read_all(pool: &PgPool, list: Option<IpListKind>) -> Result<Vec<Self>, Error> {
+        let sql = "SELECT * FROM ip_lists
+            WHERE $1::text IS NULL OR list = $1
+            ORDER BY created_at DESC, id DESC";
+        query(sql)
+            .bind(list.map(IpListKind::as_str))
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Every entry that hasn't expired.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn read_current(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let sql = "SELECT * FROM ip_lists WHERE expires_at IS NULL OR expires_at > NOW()";
+        query(sql).map(|row: PgRow| Self::from_row(&row)).fetch_all(pool).await
+    }
+
+    /// Adds `range` to `list`. An existing entry for the same range gets the
+    /// new comment and expiry.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn create(
+        pool: &PgPool,
+        list: IpListKind,
+        range: &IpRange,
+        comment: Option<&str>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<Self, Error> {
+        let sql = "INSERT INTO ip_lists (list, address, comment, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (list, address) DO UPDATE SET
+                comment = EXCLUDED.comment,
+                expires_at = EXCLUDED.expires_at
+            RETURNING *";
+        query(sql)
+            .bind(list.as_str())
+            .bind(range.to_string())
+            .bind(comment)
+            .bind(expires_at)
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Adds every range of `entries` (with its comment) to `list` in a single
+    /// transaction. Returns the stored entries.
+    ///
+    /// # Errors
+    /// Returns the underlying database error; nothing is stored then.
+    pub async fn import(
+        pool: &PgPool,
+        list: IpListKind,
+        entries: &[(IpRange, Option<String>)],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Self>, Error> {
+        let mut transaction = pool.begin().await?;
+        let mut stored = Vec::with_capacity(entries.len());
+        for (range, comment) in entries {
+            let entry = query(
+                "INSERT INTO ip_lists (list, address, comment, expires_at)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (list, address) DO UPDATE SET
+                    comment = COALESCE(EXCLUDED.comment, ip_lists.comment),
+                    expires_at = EXCLUDED.expires_at
+                RETURNING *",
+            )
+            .bind(list.as_str())
+            .bind(range.to_string())
+            .bind(comment)
+            .bind(expires_at)
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_one(&mut *transaction)
+            .await?;
+            stored.push(entry);
+        }
+        transaction.commit().await?;
+        Ok(stored)
+    }
+
+    /// Replaces the comment and expiry of an entry.
+    ///
+    /// # Errors
+    /// [`Error::RowNotFound`] if there is no such entry, or the underlying
+    /// database error.
+    pub async fn update(pool: &PgPool, update: &UpdateIpListEntry) -> Result<Self, Error> {
+        let sql = "UPDATE ip_lists SET comment = $2, expires_at = $3 WHERE id = $1 RETURNING *";
+        query(sql)
+            .bind(update.id)
+            .bind(&update.comment)
+            .bind(update.expires_at)
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Deletes an entry.
+    ///
+    /// # Errors
+    /// [`Error::RowNotFound`] if there is no such entry, or the underlying
+    /// database error.
+    pub async fn delete(pool: &PgPool, id: i32) -> Result<Self, Error> {
+        let sql = "DELETE FROM ip_lists WHERE id = $1 RETURNING *";
+        query(sql)
+            .bind(id)
+            .map(|row: PgRow| Self::from_row(&row))
+            .fetch_one(pool)
+            .await
+    }
+}
+
+/// In-memory copy of both global lists.
+#[derive(Debug, Default, Clone)]
+pub struct IpLists {
+    /// Range → expiry
+    allow: IpSet<Option<DateTime<Utc>>>,
+    /// Range → expiry
+    deny: IpSet<Option<DateTime<Utc>>>,
+}
+
+impl IpLists {
+    #[must_use]
+    pub fn new(entries: &[IpListEntry]) -> Self {
+        let mut lists = Self::default();
+        for entry in entries {
+            let Ok(range) = entry.address.parse::<IpRange>() else {
+                continue;
+            };
+            match entry.list {
+                IpListKind::Allow => lists.allow.insert(range, entry.expires_at),
+                IpListKind::Deny => lists.deny.insert(range, entry.expires_at),
+            }
+        }
+        lists
+    }
+
+    /// Loads the entries that haven't expired.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn load(pool: &PgPool) -> Result<Self, Error> {
+        Ok(Self::new(&IpListEntry::read_current(pool).await?))
+    }
+
+    /// The list `ip` is on. The allowlist wins if it is on both.
+    #[must_use]
+    pub fn check(&self, ip: &IpAddr) -> Option<IpListKind> {
+        let now = Utc::now();
+        let current = |expires_at: &Option<DateTime<Utc>>| expires_at.is_none_or(|at| at > now);
+        if self.allow.matches(ip).any(current) {
+            Some(IpListKind::Allow)
+        } else if self.deny.matches(ip).any(current) {
+            Some(IpListKind::Deny)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(list: IpListKind, address: &str, expires_at: Option<DateTime<Utc>>) -> IpListEntry {
+        IpListEntry {
+            id: 0,
+            list,
+            address: parse_address(address).unwrap().to_string(),
+            comment: None,
+            expires_at,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_check_lists() {
+        let lists = IpLists::new(&[
+            entry(IpListKind::Allow, "10.0.0.1", None),
+            entry(IpListKind::Deny, "10.0.0.0/24", None),
+            entry(IpListKind::Deny, "2001:db8::/48", None),
+            entry(
+                IpListKind::Deny,
+                "192.0.2.1",
+                Some(Utc::now() - chrono::Duration::hours(1)),
+            ),
+        ]);
+        let check = |ip: &str| lists.check(&ip.parse().unwrap());
+
+        assert_eq!(check("10.0.0.1"), Some(IpListKind::Allow));
+        assert_eq!(check("10.0.0.2"), Some(IpListKind::Deny));
+        assert_eq!(check("2001:db8:0:1::1"), Some(IpListKind::Deny));
+        assert_eq!(check("10.0.1.1"), None);
+        // Expired entries are ignored
+        assert_eq!(check("192.0.2.1"), None);
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(parse_address("1.2.3.4").unwrap().to_string(), "1.2.3.4/32");
+        assert_eq!(parse_address(" 1.2.3.9/24 ").unwrap().to_string(), "1.2.3.0/24");
+        assert_eq!(parse_address("::1").unwrap().to_string(), "::1/128");
+        assert!(parse_address("example.com").is_err());
+    }
+}
@@ -2,6 +2,7 @@
 //!
 //! [`IPData`] almacena la información de geolocalización obtenida
 //! de la base de datos MaxMind GeoIP2 para una dirección IP dada.
+//! Opcionalmente, la base de datos GeoLite2-ASN aporta el sistema autónomo.
 
 use maxminddb::{Reader, geoip2};
 use serde::{Deserialize, Serialize};
@@ -62,4 +63,15 @@ impl IPData {
             },
         }
     }
+
+    /// Looks up the autonomous system (number and organization) of `ip_address`.
+    pub fn lookup_asn(asn_db: &Reader<Vec<u8>>, ip_address: &str) -> Option<(u32, Option<String>)> {
+        let ip = ip_address.parse().ok()?;
+        let asn = asn_db.lookup::<geoip2::Asn>(ip).ok().flatten()?;
+        Some((
+            asn.autonomous_system_number?,
+            asn.autonomous_system_organization
+                .map(std::string::ToString::to_string),
+        ))
+    }
 }
@@ -6,28 +6,62 @@
 //! También contiene el tipo de error central [`AppError`] y el estado
 //! compartido de la aplicación ([`AppState`]).
 
+mod abuse_report;
+mod access_log;
+mod ban_escalation;
+mod ban_export;
+mod ban_listing;
 mod ban_manager;
+mod ban_record;
+mod bouncer;
+mod crowdsec;
 mod data;
 pub mod error;
+mod feed;
+mod ip_list;
 mod ipdata;
 mod oidc;
 mod rate_limiter;
+mod recidive;
 mod request;
 mod response;
 mod rule;
+mod rule_analysis;
+mod store;
+mod subject;
 mod user;
 
-pub use ban_manager::BanManager;
+pub use abuse_report::{AbuseReport, AbuseReporter, ReportDedup};
+pub use access_log::{AccessLogEntry, LogFormat};
+pub use ban_escalation::BanEscalation;
+pub use ban_export::{ExportFormat, render as render_bans};
+pub use ban_listing::{BanFilter, BanListing, BanSort};
+pub use ban_manager::{BanInfo, BanManager, BanRemaining, BanUpdate, RangeAggregation};
+pub use ban_record::{BanHistoryEntry, BanRecord};
+pub use bouncer::{Bouncer, NewBouncer};
+pub use crowdsec::{CrowdSecDecisions, DecisionStream, LapiDecision};
 pub use data::Data;
 pub use error::AppError as Error;
+pub use feed::{Feed, FeedFormat, FeedSets, NewFeed, UpdateFeed, parse_feed};
+pub use ip_list::{
+    IpListEntry, IpListKind, IpLists, NewIpListEntry, UpdateIpListEntry, parse_address,
+};
 pub use ipdata::IPData;
 pub use oidc::{JwtValidator, OidcMetadata};
-pub use rate_limiter::RateLimiter;
-#[allow(unused_imports)]
-pub use rate_limiter::CircularTimestamps;
-pub use request::{NewRequest, ReadRequestParams, Request};
+pub use rate_limiter::{
+    CircularTimestamps, DEFAULT_MAX_KEYS, Gcra, LimiterConfig, RateLimitAlgorithm, RateLimitStatus,
+    RateLimiter,
+};
+pub use recidive::RecidivePolicy;
+pub use request::{NewRequest, ReadRequestParams, RejectionSampler, Request, RequestDecision};
 pub use response::{ApiResponse, EmptyResponse, PagedResponse, Pagination};
-pub use rule::{CacheRule, NewRule, ReadRuleParams, Rule, UpdateRule};
+pub use rule::{
+    CacheRule, MAX_TARPIT_SECONDS, NewRule, Placement, ReadRuleParams, Rule, RuleAction,
+    UpdateRule, place,
+};
+pub use rule_analysis::analyze as analyze_rules;
+pub use store::{MemoryStore, PostgresStore, StateStore, StoreKind};
+pub use subject::{Subject, parse_subjects};
 pub use user::{TokenClaims, User, UserRegister, UserSchema};
 
 use maxminddb::Reader;
@@ -40,14 +74,27 @@ pub struct AppState {
     pub pool: PgPool,
     pub secret: String,
     pub maxmind_db: Reader<Vec<u8>>,
+    /// Optional GeoLite2-ASN database, used for ASN rate-limit keys.
+    pub asn_db: Option<Reader<Vec<u8>>>,
     pub rules: Mutex<Vec<CacheRule>>,
+    /// Global allowlist and denylist, checked before bans and rules.
+    pub ip_lists: Mutex<IpLists>,
+    /// Addresses of the fetched blocklist feeds.
+    pub feeds: Mutex<FeedSets>,
+    /// Decisions pulled from a `CrowdSec` LAPI, kept apart from local bans.
+    pub crowdsec: Mutex<CrowdSecDecisions>,
+    /// Queue of bans to report to `AbuseIPDB`, if it is configured.
+    pub abuse_reporter: Option<AbuseReporter>,
     pub cache: Mutex<Vec<NewRequest>>,
+    /// Samples the requests rejected before the rules so they are saved at
+    /// most once a minute per client.
+    pub rejections: Mutex<RejectionSampler>,
     pub cache_enabled: bool,
     pub cache_size: usize,
     #[allow(dead_code)]
     pub static_dir: String,
-    pub ban_manager: Mutex<BanManager>,
-    pub rate_limiter: Mutex<HashMap<i32, RateLimiter>>, // rule_id → RateLimiter
+    /// Rate-limit counters and bans (in memory or shared through the database).
+    pub store: Box<dyn StateStore>,
     // SSO / OIDC fields
     pub oidc_metadata: Option<OidcMetadata>,
     pub jwt_validator: JwtValidator,
@@ -1,15 +1,232 @@
 //! # Rate Limiter
 //!
-//! Circular ring buffer for tracking request timestamps per IP,
+//! Circular ring buffer for tracking request timestamps per key,
 //! and a rate limiter that checks thresholds against sliding windows.
 //!
+//! The key is a [`Subject`] built from the request according to the rule's
+//! [`KeySpec`]: the client IP, its /24 or /64 prefix, its ASN, a header
+//! value, the host, or any combination of them.
+//!
 //! Inspired by fail2ban-rs's `CircularTimestamps`.
+//!
+//! Cada regla elige el algoritmo con [`RateLimitAlgorithm`]:
+//! - `sliding_window`: N peticiones en `find_time` → ban (estilo fail2ban).
+//! - `gcra`: token bucket (GCRA) con `rate` y `burst`; el exceso se rechaza
+//!   con 429 sin banear, para limitar el caudal de APIs.
+//!
+//! Además, [`AggregateCounter`] cuenta todas las coincidencias de una regla,
+//! vengan de donde vengan, para su límite agregado ([`AggregateLimit`]).
 
-use std::collections::HashMap;
+use crate::models::request::NewRequest;
+use crate::models::subject::{IpRange, Subject};
+use http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
 use std::net::IpAddr;
+use std::str::FromStr;
 use std::time::{Duration, Instant};
 
-/// A fixed-size circular buffer of timestamps for a single IP.
+/// Algorithm a rule uses to limit requests.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitAlgorithm {
+    /// Ban a subject after `max_retry` hits within `find_time_seconds`.
+    #[default]
+    SlidingWindow,
+    /// Token bucket (GCRA): allow `rate` requests per second with bursts
+    /// of up to `burst`; deny the excess with 429, without banning.
+    Gcra,
+}
+
+impl RateLimitAlgorithm {
+    /// Name of the algorithm as stored in the database.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::SlidingWindow => "sliding_window",
+            Self::Gcra => "gcra",
+        }
+    }
+}
+
+impl FromStr for RateLimitAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sliding_window" => Ok(Self::SlidingWindow),
+            "gcra" => Ok(Self::Gcra),
+            other => Err(format!("Unknown rate limit algorithm: {other}")),
+        }
+    }
+}
+
+/// One component of a rate-limit key.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyPart {
+    /// The exact client IP.
+    Ip,
+    /// The client's IPv4 /24 or IPv6 /64 network.
+    Prefix,
+    /// The client's autonomous system number.
+    Asn,
+    /// The value of the rule's `rate_limit_header` (stored hashed).
+    Header,
+    /// The requested host (`X-Forwarded-Host`).
+    Host,
+}
+
+impl KeyPart {
+    /// Name of the part as stored in the database.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Ip => "ip",
+            Self::Prefix => "prefix",
+            Self::Asn => "asn",
+            Self::Header => "header",
+            Self::Host => "host",
+        }
+    }
+}
+
+impl FromStr for KeyPart {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ip" => Ok(Self::Ip),
+            "prefix" => Ok(Self::Prefix),
+            "asn" => Ok(Self::Asn),
+            "header" => Ok(Self::Header),
+            "host" => Ok(Self::Host),
+            other => Err(format!("Unknown rate limit key part: {other}")),
+        }
+    }
+}
+
+/// How a rule builds the rate-limit key of a request.
+///
+/// A single `ip`, `prefix` or `asn` part yields an IP, range or ASN subject,
+/// so bans land on that entity. Anything else yields an opaque
+/// [`Subject::Key`]. Parts that can't be resolved for a request (unknown
+/// ASN, missing header) fall back to the client IP, so omitting a header
+/// never bypasses the limit.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeySpec {
+    parts: Vec<KeyPart>,
+    /// Header used by [`KeyPart::Header`]. `cookie:<name>` selects one cookie.
+    header: Option<String>,
+}
+
+impl KeySpec {
+    /// Creates a key spec; no parts means "client IP".
+    #[must_use]
+    pub fn new(parts: &[KeyPart], header: Option<&str>) -> Self {
+        let mut unique: Vec<KeyPart> = Vec::with_capacity(parts.len());
+        for part in parts {
+            if !unique.contains(part) {
+                unique.push(*part);
+            }
+        }
+        if unique.is_empty() {
+            unique.push(KeyPart::Ip);
+        }
+        Self {
+            parts: unique,
+            header: header
+                .map(|h| h.trim().to_ascii_lowercase())
+                .filter(|h| !h.is_empty()),
+        }
+    }
+
+    /// `true` if the key is just the client IP.
+    #[must_use]
+    pub fn is_ip(&self) -> bool {
+        self.parts == [KeyPart::Ip]
+    }
+
+    /// Builds the subject counted for `request`, or `None` without client IP.
+    #[must_use]
+    pub fn subject(&self, request: &NewRequest, headers: &HeaderMap) -> Option<Subject> {
+        let ip: IpAddr = request.ip_address.as_ref()?.parse().ok()?;
+        match self.parts.as_slice() {
+            [KeyPart::Ip] => return Some(Subject::Ip(ip)),
+            [KeyPart::Prefix] => return Some(Subject::Range(IpRange::default_prefix(ip))),
+            [KeyPart::Asn] => return Some(request.asn.map_or(Subject::Ip(ip), Subject::Asn)),
+            _ => {},
+        }
+        let mut key = String::new();
+        for part in &self.parts {
+            if !key.is_empty() {
+                key.push('|');
+            }
+            let _ = match part {
+                KeyPart::Ip => write!(key, "ip={ip}"),
+                KeyPart::Prefix => write!(key, "net={}", IpRange::default_prefix(ip)),
+                KeyPart::Asn => match request.asn {
+                    Some(asn) => write!(key, "asn={asn}"),
+                    None => write!(key, "ip={ip}"),
+                },
+                KeyPart::Host => write!(key, "host={}", request.fqdn.as_deref().unwrap_or("")),
+                KeyPart::Header => match self.header_value(headers) {
+                    Some((name, value)) => write!(key, "{name}={}", hash_value(&value)),
+                    None => write!(key, "ip={ip}"),
+                },
+            };
+        }
+        Some(Subject::Key(key))
+    }
+
+    /// Stored form of the parts, for the rule's `rate_limit_key` column.
+    #[must_use]
+    pub fn parts(&self) -> &[KeyPart] {
+        &self.parts
+    }
+
+    /// Reads the configured header (or cookie) from the request.
+    fn header_value(&self, headers: &HeaderMap) -> Option<(String, String)> {
+        let header = self.header.as_deref()?;
+        if let Some(cookie) = header.strip_prefix("cookie:") {
+            let value = headers
+                .get_all(http::header::COOKIE)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(';'))
+                .filter_map(|pair| pair.trim().split_once('='))
+                .find(|(name, _)| name.eq_ignore_ascii_case(cookie))
+                .map(|(_, value)| value.to_string())?;
+            return Some((header.to_string(), value));
+        }
+        let value = headers.get(header)?.to_str().ok()?.trim();
+        if value.is_empty() {
+            None
+        } else {
+            Some((header.to_string(), value.to_string()))
+        }
+    }
+}
+
+impl Default for KeySpec {
+    fn default() -> Self {
+        Self::new(&[KeyPart::Ip], None)
+    }
+}
+
+/// Hashes a header value so secrets (API keys, session cookies) are never
+/// kept in memory or shown in the API.
+fn hash_value(value: &str) -> String {
+    openssl::sha::sha256(value.as_bytes())[..8]
+        .iter()
+        .fold(String::with_capacity(16), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// A fixed-size circular buffer of timestamps for a single key.
 ///
 /// Stores only the last `capacity` timestamps. `threshold_reached()`
 /// returns true if the buffer is full AND the oldest timestamp falls
@@ -23,8 +240,10 @@ pub struct CircularTimestamps {
 }
 
 impl CircularTimestamps {
-    /// Create a new ring buffer that tracks up to `capacity` timestamps.
+    /// Create a new ring buffer that tracks up to `capacity` timestamps
+    /// (at least 1).
     pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
         let now = Instant::now();
         Self {
             timestamps: vec![now; capacity],
@@ -46,7 +265,7 @@ impl CircularTimestamps {
     /// Returns `true` if the buffer has reached capacity AND the oldest
     /// timestamp is within `find_time` seconds of the newest.
     ///
-    /// This means the IP has exceeded `max_retry` within the sliding window.
+    /// This means the key has exceeded `max_retry` within the sliding window.
     pub fn threshold_reached(&self, find_time: Duration) -> bool {
         if self.count < self.capacity {
             return false;
@@ -57,81 +276,493 @@ impl CircularTimestamps {
         newest.duration_since(oldest) <= find_time
     }
 
-    /// Number of timestamps stored.
-    #[allow(dead_code)]
-    pub fn len(&self) -> usize {
-        self.count
+    /// Number of timestamps within `find_time` of `now`, and the oldest of
+    /// them.
+    #[must_use]
+    pub fn in_window(&self, find_time: Duration, now: Instant) -> (usize, Option<Instant>) {
+        (0..self.count)
+            .rev()
+            .map(|i| self.timestamps[(self.head + self.capacity - 1 - i) % self.capacity])
+            .filter(|timestamp| now.saturating_duration_since(*timestamp) <= find_time)
+            .fold((0, None), |(count, oldest), timestamp| {
+                (count + 1, oldest.or(Some(timestamp)))
+            })
     }
 
     /// Whether the buffer is empty.
     pub fn is_empty(&self) -> bool {
         self.count == 0
     }
+
+    /// A buffer of `capacity` holding the newest timestamps of this one.
+    #[must_use]
+    pub fn resized(&self, capacity: usize) -> Self {
+        let mut resized = Self::new(capacity);
+        let keep = self.count.min(capacity);
+        for i in (0..keep).rev() {
+            let index = (self.head + self.capacity - 1 - i) % self.capacity;
+            resized.push(self.timestamps[index]);
+        }
+        resized
+    }
+}
+
+/// Generic Cell Rate Algorithm: a token bucket that stores a single
+/// "theoretical arrival time" (TAT) per subject instead of a token count.
+///
+/// Each request pushes the TAT forward by `emission_interval` (1 / rate).
+/// A request is allowed while the TAT stays within `burst` intervals of now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gcra {
+    emission_interval: Duration,
+    burst: u32,
+}
+
+impl Gcra {
+    /// Slowest rate accepted: one request a day.
+    pub const MIN_RATE: f64 = 1.0 / 86_400.0;
+    /// Fastest rate accepted, in requests per second.
+    pub const MAX_RATE: f64 = 1_000_000.0;
+
+    /// Build a limiter allowing `rate` requests per second and bursts of up
+    /// to `burst` requests. Returns `None` if `rate` is not between
+    /// [`Self::MIN_RATE`] and [`Self::MAX_RATE`].
+    #[must_use]
+    pub fn new(rate: f64, burst: u32) -> Option<Self> {
+        if !(Self::MIN_RATE..=Self::MAX_RATE).contains(&rate) {
+            return None;
+        }
+        Some(Self {
+            emission_interval: Duration::try_from_secs_f64(1.0 / rate).ok()?,
+            burst: burst.max(1),
+        })
+    }
+
+    /// Time added to the TAT by each request (1 / rate).
+    #[must_use]
+    pub const fn emission_interval(&self) -> Duration {
+        self.emission_interval
+    }
+
+    /// How far ahead of now the TAT may be after an allowed request.
+    #[must_use]
+    pub fn allowance(&self) -> Duration {
+        self.emission_interval
+            .checked_mul(self.burst)
+            .unwrap_or(Duration::MAX)
+    }
+
+    /// Quota left after an allowed request that moved the TAT `ahead` of now.
+    #[must_use]
+    pub fn status(&self, ahead: Duration) -> RateLimitStatus {
+        let free = self.allowance().saturating_sub(ahead);
+        let tokens = free.as_nanos() / self.emission_interval.as_nanos().max(1);
+        RateLimitStatus {
+            limit: self.burst,
+            remaining: u32::try_from(tokens).unwrap_or(u32::MAX),
+            reset: ahead,
+            exceeded: false,
+        }
+    }
+
+    /// Quota of a denied request that must wait `wait` before retrying.
+    #[must_use]
+    pub const fn throttled(&self, wait: Duration) -> RateLimitStatus {
+        RateLimitStatus {
+            limit: self.burst,
+            remaining: 0,
+            reset: wait,
+            exceeded: true,
+        }
+    }
+
+    /// Checks a request arriving at `now` against the subject's `tat`.
+    ///
+    /// # Errors
+    ///
+    /// Returns how long the subject must wait if the request is over the limit.
+    pub fn check(&self, tat: Option<Instant>, now: Instant) -> Result<Instant, Duration> {
+        let new_tat = tat.map_or(now, |tat| tat.max(now)) + self.emission_interval;
+        let allowance = self.allowance();
+        let ahead = new_tat.duration_since(now);
+        if ahead > allowance {
+            Err(ahead.saturating_sub(allowance))
+        } else {
+            Ok(new_tat)
+        }
+    }
+}
+
+/// Quota of a subject after a hit, for the `RateLimit-*` response headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    /// Requests allowed in the window, or the GCRA burst.
+    pub limit: u32,
+    /// Requests left before the limit is exceeded.
+    pub remaining: u32,
+    /// Time until the quota is available again; when exceeded, how long the
+    /// client must wait.
+    pub reset: Duration,
+    /// The subject is over the limit.
+    pub exceeded: bool,
+}
+
+/// Quota of one tracked subject, as reported by [`RateLimiter::peek`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyState {
+    pub subject: Subject,
+    pub status: RateLimitStatus,
+    /// Oldest hit still in the window (sliding window only).
+    pub window_start: Option<Instant>,
+}
+
+/// Aggregate limit of a rule: `limit` matches from any source within
+/// `window` start a `cooldown` during which the rule denies or challenges
+/// every request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AggregateLimit {
+    pub limit: u32,
+    pub window: Duration,
+    pub cooldown: Duration,
+}
+
+/// State of a rule's aggregate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AggregateStatus {
+    /// Matches counted within the window.
+    pub hits: u32,
+    /// Time left in the cool-down, if the rule is cooling down.
+    pub cooldown_remaining: Option<Duration>,
+}
+
+/// Counts the matches of one rule for its aggregate limit.
+#[derive(Debug, Clone)]
+pub struct AggregateCounter {
+    hits: CircularTimestamps,
+    cooldown_until: Option<Instant>,
+}
+
+impl AggregateCounter {
+    #[must_use]
+    pub fn new(limit: AggregateLimit) -> Self {
+        Self {
+            hits: CircularTimestamps::new(limit.limit.max(1) as usize),
+            cooldown_until: None,
+        }
+    }
+
+    /// Counts a match at `now`, starting the cool-down when the limit is
+    /// reached. Matches during the cool-down are not counted.
+    pub fn record(&mut self, limit: AggregateLimit, now: Instant) -> AggregateStatus {
+        let capacity = limit.limit.max(1) as usize;
+        if self.hits.capacity != capacity {
+            self.hits = self.hits.resized(capacity);
+        }
+        if self.cooldown_until.is_none_or(|until| until <= now) {
+            self.hits.push(now);
+            if self.hits.threshold_reached(limit.window) {
+                self.cooldown_until = Some(now + limit.cooldown);
+            }
+        }
+        self.status(limit, now)
+    }
+
+    /// State at `now`, without counting a match.
+    #[must_use]
+    pub fn status(&self, limit: AggregateLimit, now: Instant) -> AggregateStatus {
+        let (hits, _) = self.hits.in_window(limit.window, now);
+        AggregateStatus {
+            hits: u32::try_from(hits).unwrap_or(u32::MAX),
+            cooldown_remaining: self
+                .cooldown_until
+                .filter(|until| *until > now)
+                .map(|until| until.duration_since(now)),
+        }
+    }
+}
+
+/// Default cap on the subjects tracked by a single rate limiter.
+pub const DEFAULT_MAX_KEYS: usize = 100_000;
+
+/// Algorithm and limits of a [`RateLimiter`], derived from its rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimiterConfig {
+    /// `max_retry` hits within `find_time_seconds` reach the threshold.
+    SlidingWindow {
+        max_retry: u32,
+        find_time_seconds: i64,
+    },
+    /// GCRA token bucket.
+    Gcra(Gcra),
 }
 
-/// Per-rule rate limiter. Maps IP addresses to circular timestamp buffers.
+/// Per-rule rate limiter. Maps subjects to circular timestamp buffers
+/// (sliding window) or to GCRA theoretical arrival times.
+///
+/// At most `max_keys` subjects are tracked: beyond that the least recently
+/// seen subject is evicted, so a flood of spoofed sources can't exhaust
+/// memory between cleanups.
 #[derive(Debug, Clone)]
 pub struct RateLimiter {
-    /// Per-IP ring buffers
-    ip_buffers: HashMap<IpAddr, CircularTimestamps>,
-    /// Maximum retries before ban
-    max_retry: u32,
-    /// Sliding window duration in seconds
-    find_time_seconds: i64,
+    /// Per-subject ring buffers
+    buffers: HashMap<Subject, CircularTimestamps>,
+    /// Per-subject theoretical arrival times, in GCRA mode
+    arrivals: HashMap<Subject, Instant>,
+    /// Algorithm and limits
+    config: LimiterConfig,
+    /// Last-use tick of each tracked subject
+    last_used: HashMap<Subject, u64>,
+    /// Tracked subjects ordered by last use, oldest first
+    lru: BTreeMap<u64, Subject>,
+    /// Monotonic use counter
+    tick: u64,
+    /// Maximum number of tracked subjects
+    max_keys: usize,
+    /// Subjects evicted because the limiter was full
+    evictions: u64,
 }
 
 impl RateLimiter {
     /// Create a new rate limiter with the given threshold.
     pub fn new(max_retry: u32, find_time_seconds: i64) -> Self {
-        Self {
-            ip_buffers: HashMap::new(),
+        Self::with_config(LimiterConfig::SlidingWindow {
             max_retry,
             find_time_seconds,
+        })
+    }
+
+    /// Create an empty rate limiter for `config`.
+    #[must_use]
+    pub fn with_config(config: LimiterConfig) -> Self {
+        Self {
+            buffers: HashMap::new(),
+            arrivals: HashMap::new(),
+            config,
+            last_used: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            max_keys: DEFAULT_MAX_KEYS,
+            evictions: 0,
         }
     }
 
-    /// Record a request from `ip`. Returns `true` if the threshold is reached
-    /// (IP should be banned).
-    pub fn record(&mut self, ip: IpAddr) -> bool {
-        let capacity = self.max_retry as usize;
-        let buffer = self
-            .ip_buffers
-            .entry(ip)
-            .or_insert_with(|| CircularTimestamps::new(capacity));
-        buffer.push(Instant::now());
-        buffer.threshold_reached(Duration::from_secs(self.find_time_seconds as u64))
+    /// Set the maximum number of tracked subjects (at least 1).
+    #[must_use]
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = max_keys.max(1);
+        self
+    }
+
+    /// Apply new limits without losing the history of tracked subjects.
+    ///
+    /// Sliding window buffers are resized keeping their newest timestamps and
+    /// GCRA arrival times are kept as they are. Switching algorithm starts
+    /// from scratch, as the two histories are not comparable.
+    pub fn reconfigure(&mut self, config: LimiterConfig) {
+        match (self.config, config) {
+            (old, new) if old == new => return,
+            (
+                LimiterConfig::SlidingWindow { max_retry: old, .. },
+                LimiterConfig::SlidingWindow { max_retry: new, .. },
+            ) => {
+                if old != new {
+                    let capacity = new.max(1) as usize;
+                    for buffer in self.buffers.values_mut() {
+                        *buffer = buffer.resized(capacity);
+                    }
+                }
+            },
+            (LimiterConfig::Gcra(_), LimiterConfig::Gcra(_)) => {},
+            _ => self.clear(),
+        }
+        self.config = config;
+    }
+
+    /// Record a request from `subject`. Returns `true` if the threshold is
+    /// reached: the subject should be banned (sliding window) or the request
+    /// denied (GCRA).
+    pub fn record(&mut self, subject: impl Into<Subject>) -> bool {
+        self.hit(subject).exceeded
+    }
+
+    /// Record a request from `subject` and return its quota. In GCRA mode
+    /// denied requests don't consume capacity.
+    pub fn hit(&mut self, subject: impl Into<Subject>) -> RateLimitStatus {
+        let subject = subject.into();
+        let now = Instant::now();
+        let status = match self.config {
+            LimiterConfig::SlidingWindow {
+                max_retry,
+                find_time_seconds,
+            } => {
+                self.touch(&subject);
+                let capacity = max_retry as usize;
+                let find_time = Duration::from_secs(u64::try_from(find_time_seconds).unwrap_or(0));
+                let buffer = self
+                    .buffers
+                    .entry(subject)
+                    .or_insert_with(|| CircularTimestamps::new(capacity));
+                buffer.push(now);
+                let (hits, oldest) = buffer.in_window(find_time, now);
+                RateLimitStatus {
+                    limit: max_retry,
+                    remaining: max_retry.saturating_sub(u32::try_from(hits).unwrap_or(u32::MAX)),
+                    reset: oldest.map_or(Duration::ZERO, |oldest| {
+                        (oldest + find_time).saturating_duration_since(now)
+                    }),
+                    exceeded: buffer.threshold_reached(find_time),
+                }
+            },
+            LimiterConfig::Gcra(gcra) => {
+                match gcra.check(self.arrivals.get(&subject).copied(), now) {
+                    Ok(tat) => {
+                        self.touch(&subject);
+                        self.arrivals.insert(subject, tat);
+                        gcra.status(tat.duration_since(now))
+                    },
+                    Err(wait) => return gcra.throttled(wait),
+                }
+            },
+        };
+        self.evict_over_capacity();
+        status
     }
 
     /// Remove expired entries to prevent memory leaks.
-    /// Entries whose newest timestamp is older than `find_time` are removed.
+    /// Entries whose newest timestamp is older than `find_time` are removed,
+    /// as are GCRA subjects whose bucket is full again.
     pub fn cleanup_expired(&mut self) {
-        let find_time = Duration::from_secs(self.find_time_seconds as u64);
-        self.ip_buffers.retain(|_, buffer| {
-            if buffer.is_empty() {
-                return false;
-            }
-            // Keep if the newest entry is still within the window
-            let newest_idx = (buffer.head + buffer.capacity - 1) % buffer.capacity;
-            let newest = buffer.timestamps[newest_idx];
-            Instant::now().duration_since(newest) <= find_time
-        });
+        let now = Instant::now();
+        if let LimiterConfig::SlidingWindow {
+            find_time_seconds, ..
+        } = self.config
+        {
+            let find_time = Duration::from_secs(u64::try_from(find_time_seconds).unwrap_or(0));
+            self.buffers.retain(|_, buffer| {
+                if buffer.is_empty() {
+                    return false;
+                }
+                // Keep if the newest entry is still within the window
+                let newest_idx = (buffer.head + buffer.capacity - 1) % buffer.capacity;
+                let newest = buffer.timestamps[newest_idx];
+                now.duration_since(newest) <= find_time
+            });
+        }
+        self.arrivals.retain(|_, tat| *tat > now);
+        let (buffers, arrivals) = (&self.buffers, &self.arrivals);
+        self.last_used
+            .retain(|subject, _| buffers.contains_key(subject) || arrivals.contains_key(subject));
+        let last_used = &self.last_used;
+        self.lru.retain(|_, subject| last_used.contains_key(subject));
     }
 
-    /// Remove a specific IP from the rate limiter (e.g., after unban).
-    #[allow(dead_code)]
-    pub fn remove_ip(&mut self, ip: &IpAddr) {
-        self.ip_buffers.remove(ip);
+    /// Remove a specific subject from the rate limiter. Returns `true` if it
+    /// was tracked.
+    pub fn remove(&mut self, subject: &Subject) -> bool {
+        let removed =
+            self.buffers.remove(subject).is_some() | self.arrivals.remove(subject).is_some();
+        if let Some(tick) = self.last_used.remove(subject) {
+            self.lru.remove(&tick);
+        }
+        removed
     }
 
-    /// Number of tracked IPs.
-    #[allow(dead_code)]
+    /// Current quota of the tracked subjects, or only of `subject`, without
+    /// counting a hit. Subjects with nothing in the window are left out.
+    #[must_use]
+    pub fn peek(&self, subject: Option<&Subject>) -> Vec<KeyState> {
+        let now = Instant::now();
+        match self.config {
+            LimiterConfig::SlidingWindow {
+                max_retry,
+                find_time_seconds,
+            } => {
+                let find_time = Duration::from_secs(u64::try_from(find_time_seconds).unwrap_or(0));
+                self.buffers
+                    .iter()
+                    .filter(|(tracked, _)| subject.is_none_or(|subject| subject == *tracked))
+                    .filter_map(|(tracked, buffer)| {
+                        let (hits, oldest) = buffer.in_window(find_time, now);
+                        let oldest = oldest?;
+                        let hits = u32::try_from(hits).unwrap_or(u32::MAX);
+                        Some(KeyState {
+                            subject: tracked.clone(),
+                            status: RateLimitStatus {
+                                limit: max_retry,
+                                remaining: max_retry.saturating_sub(hits),
+                                reset: (oldest + find_time).saturating_duration_since(now),
+                                exceeded: hits >= max_retry,
+                            },
+                            window_start: Some(oldest),
+                        })
+                    })
+                    .collect()
+            },
+            LimiterConfig::Gcra(gcra) => self
+                .arrivals
+                .iter()
+                .filter(|(tracked, tat)| {
+                    **tat > now && subject.is_none_or(|subject| subject == *tracked)
+                })
+                .map(|(tracked, tat)| KeyState {
+                    subject: tracked.clone(),
+                    status: gcra.status(tat.duration_since(now)),
+                    window_start: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Forget every tracked subject.
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+        self.arrivals.clear();
+        self.last_used.clear();
+        self.lru.clear();
+    }
+
+    /// Number of tracked subjects.
     pub fn len(&self) -> usize {
-        self.ip_buffers.len()
+        self.last_used.len()
     }
 
-    /// Whether no IPs are tracked.
+    /// Whether no subjects are tracked.
     pub fn is_empty(&self) -> bool {
-        self.ip_buffers.is_empty()
+        self.last_used.is_empty()
+    }
+
+    /// Maximum number of tracked subjects.
+    #[must_use]
+    pub const fn max_keys(&self) -> usize {
+        self.max_keys
+    }
+
+    /// Number of subjects evicted because the limiter was full.
+    #[must_use]
+    pub const fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// Marks `subject` as the most recently used.
+    fn touch(&mut self, subject: &Subject) {
+        self.tick += 1;
+        if let Some(previous) = self.last_used.insert(subject.clone(), self.tick) {
+            self.lru.remove(&previous);
+        }
+        self.lru.insert(self.tick, subject.clone());
+    }
+
+    /// Evicts the least recently used subjects beyond `max_keys`.
+    fn evict_over_capacity(&mut self) {
+        while self.last_used.len() > self.max_keys {
+            let Some((_, subject)) = self.lru.pop_first() else {
+                break;
+            };
+            self.last_used.remove(&subject);
+            self.buffers.remove(&subject);
+            self.arrivals.remove(&subject);
+            self.evictions += 1;
+        }
     }
 }
 
@@ -182,6 +813,104 @@ mod tests {
         assert!(rl.record(ip));  // 3rd → threshold reached
     }
 
+    #[test]
+    fn test_hit_reports_remaining_quota() {
+        let mut rl = RateLimiter::new(3, 60);
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+        let first = rl.hit(ip);
+        assert_eq!((first.limit, first.remaining, first.exceeded), (3, 2, false));
+        assert!(first.reset > Duration::from_secs(59) && first.reset.as_secs() <= 60);
+        rl.hit(ip);
+        let third = rl.hit(ip);
+        assert_eq!((third.remaining, third.exceeded), (0, true));
+
+        let mut rl = RateLimiter::with_config(LimiterConfig::Gcra(Gcra::new(1.0, 2).unwrap()));
+        assert_eq!(rl.hit(ip).remaining, 1);
+        assert_eq!(rl.hit(ip).remaining, 0);
+        let denied = rl.hit(ip);
+        assert!(denied.exceeded && denied.reset <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_peek_and_remove() {
+        let mut rl = RateLimiter::new(5, 60);
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+        rl.record(a);
+        rl.record(a);
+        rl.record(b);
+        assert_eq!(rl.peek(None).len(), 2);
+        let keys = rl.peek(Some(&Subject::Ip(a)));
+        assert_eq!(keys.len(), 1);
+        assert_eq!((keys[0].status.limit, keys[0].status.remaining), (5, 3));
+        assert!(keys[0].window_start.is_some());
+        assert!(rl.remove(&Subject::Ip(a)));
+        assert!(!rl.remove(&Subject::Ip(a)));
+        assert!(rl.peek(Some(&Subject::Ip(a))).is_empty());
+        assert_eq!(rl.len(), 1);
+    }
+
+    #[test]
+    fn test_aggregate_counter_cools_down() {
+        let limit = AggregateLimit {
+            limit: 3,
+            window: Duration::from_secs(10),
+            cooldown: Duration::from_secs(30),
+        };
+        let mut counter = AggregateCounter::new(limit);
+        let now = Instant::now();
+        assert_eq!(counter.record(limit, now).cooldown_remaining, None);
+        assert_eq!(counter.record(limit, now).cooldown_remaining, None);
+        let tripped = counter.record(limit, now + Duration::from_secs(1));
+        assert_eq!(tripped.hits, 3);
+        assert_eq!(tripped.cooldown_remaining, Some(Duration::from_secs(30)));
+        // Matches during the cool-down are not counted
+        let cooling = counter.record(limit, now + Duration::from_secs(5));
+        assert_eq!(cooling.hits, 3);
+        assert_eq!(cooling.cooldown_remaining, Some(Duration::from_secs(26)));
+        let after = counter.status(limit, now + Duration::from_secs(31));
+        assert_eq!((after.hits, after.cooldown_remaining), (0, None));
+    }
+
+    #[test]
+    fn test_key_spec_subjects() {
+        let mut request: NewRequest = serde_json::from_value(serde_json::json!({
+            "ip_address": "10.1.2.3",
+            "protocol": "https",
+            "fqdn": "api.example.com",
+            "path": "/v1",
+            "query": null,
+            "city_name": null,
+            "country_name": null,
+            "country_code": null,
+            "rule_id": null,
+            "created_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", "secret".parse().unwrap());
+
+        let prefix = KeySpec::new(&[KeyPart::Prefix], None);
+        assert_eq!(
+            prefix.subject(&request, &headers).unwrap().to_string(),
+            "10.1.2.0/24"
+        );
+
+        let asn = KeySpec::new(&[KeyPart::Asn], None);
+        assert_eq!(asn.subject(&request, &headers).unwrap().to_string(), "10.1.2.3");
+        request.asn = Some(64500);
+        assert_eq!(asn.subject(&request, &headers).unwrap().to_string(), "AS64500");
+
+        let composite = KeySpec::new(&[KeyPart::Host, KeyPart::Header], Some("X-Api-Key"));
+        let key = composite.subject(&request, &headers).unwrap().to_string();
+        assert!(key.starts_with("key:host=api.example.com|x-api-key="));
+        assert!(!key.contains("secret"));
+
+        headers.remove("x-api-key");
+        let key = composite.subject(&request, &headers).unwrap().to_string();
+        assert_eq!(key, "key:host=api.example.com|ip=10.1.2.3");
+    }
+
     #[test]
     fn test_rate_limiter_cleanup() {
         let mut rl = RateLimiter::new(3, 1); // 1 second window
@@ -194,4 +923,90 @@ mod tests {
         rl.cleanup_expired();
         assert_eq!(rl.len(), 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_gcra_allows_burst_then_throttles() {
+        let gcra = Gcra::new(10.0, 3).unwrap();
+        let now = Instant::now();
+        let mut tat = None;
+        for _ in 0..3 {
+            tat = Some(gcra.check(tat, now).unwrap());
+        }
+        let wait = gcra.check(tat, now).unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(100));
+        // One emission interval later a token is available again
+        assert!(gcra.check(tat, now + Duration::from_millis(100)).is_ok());
+        assert!(Gcra::new(0.0, 3).is_none());
+    }
+
+    #[test]
+    fn test_gcra_rejects_absurd_rates() {
+        for rate in [f64::NAN, f64::INFINITY, -1.0, 1e-300, 1e300] {
+            assert!(Gcra::new(rate, 1).is_none(), "{rate}");
+        }
+        let gcra = Gcra::new(Gcra::MIN_RATE, u32::MAX).unwrap();
+        assert!(gcra.allowance() >= gcra.emission_interval());
+    }
+
+    #[test]
+    fn test_gcra_rate_limiter_never_bans_other_subjects() {
+        let mut rl = RateLimiter::with_config(LimiterConfig::Gcra(Gcra::new(1.0, 2).unwrap()));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        assert!(!rl.hit(ip).exceeded);
+        assert!(!rl.hit(ip).exceeded);
+        let denied = rl.hit(ip);
+        assert!(denied.exceeded && denied.reset > Duration::ZERO);
+        assert!(!rl.hit(other).exceeded);
+        assert_eq!(rl.len(), 2);
+    }
+
+    #[test]
+    fn test_lru_eviction_caps_tracked_subjects() {
+        let mut rl = RateLimiter::new(3, 60).with_max_keys(2);
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+        let c: IpAddr = "10.0.0.3".parse().unwrap();
+        rl.record(a);
+        rl.record(b);
+        // `a` becomes the most recently used, so `b` is evicted for `c`
+        rl.record(a);
+        rl.record(c);
+        assert_eq!(rl.len(), 2);
+        assert_eq!(rl.evictions(), 1);
+        // `a` kept its history: the third hit reaches the threshold
+        assert!(rl.record(a));
+    }
+
+    #[test]
+    fn test_reconfigure_keeps_history() {
+        let mut rl = RateLimiter::new(5, 60);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        assert!(!rl.record(ip));
+        assert!(!rl.record(ip));
+        rl.reconfigure(LimiterConfig::SlidingWindow {
+            max_retry: 3,
+            find_time_seconds: 60,
+        });
+        assert!(rl.record(ip));
+
+        rl.reconfigure(LimiterConfig::Gcra(Gcra::new(1.0, 1).unwrap()));
+        assert!(rl.is_empty());
+        assert!(!rl.record(ip));
+        assert!(rl.record(ip));
+    }
+
+    #[test]
+    fn test_reconfigure_to_zero_max_retry() {
+        let mut rl = RateLimiter::new(5, 60);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        assert!(!rl.record(ip));
+        rl.reconfigure(LimiterConfig::SlidingWindow {
+            max_retry: 0,
+            find_time_seconds: 60,
+        });
+        assert!(rl.record(ip));
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        assert!(rl.record(other));
+    }
+}
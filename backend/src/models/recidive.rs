@@ -0,0 +1,245 @@
+//! # Jail de reincidentes
+//!
+//! Como el jail `recidive` de fail2ban: un sujeto baneado `max_bans` veces por
+//! cualquier regla en `find_days` días recibe un ban global largo, o
+//! permanente si `ban_seconds` es negativo. Se cuentan los bans del sujeto
+//! baneado (una IP, o el prefijo, ASN o clave de las reglas que no banean por
+//! IP), que es también el que recibe el ban de reincidencia.
+//!
+//! La política vive en la tabla `settings` y se lee en cada ban, así que un
+//! cambio vale para todas las réplicas sin reiniciar. El ban de reincidencia
+//! guarda en `bans.triggered_by` los ids de los bans que lo provocaron, que
+//! se ven en el historial del sujeto.
+
+use crate::models::ban_manager::BanInfo;
+use crate::models::ban_record::BanRecord;
+use crate::models::error::AppError;
+use crate::models::store::StateStore;
+use crate::models::subject::Subject;
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    Error, Row,
+    postgres::{PgPool, PgRow},
+    query,
+};
+use tracing::info;
+
+/// Cross-rule ban policy for repeat offenders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecidivePolicy {
+    pub enabled: bool,
+    /// Rule bans that trigger the recidive ban.
+    pub max_bans: i32,
+    /// Window the rule bans are counted in.
+    pub find_days: i32,
+    /// Duration of the recidive ban; negative for a permanent ban.
+    pub ban_seconds: i64,
+}
+
+impl Default for RecidivePolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bans: 5,
+            find_days: 1,
+            ban_seconds: 604_800,
+        }
+    }
+}
+
+impl RecidivePolicy {
+    /// Builds the policy from `recidive_*` settings; missing or invalid
+    /// values keep their default.
+    #[must_use]
+    pub fn from_settings<'a>(settings: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut policy = Self::default();
+        for (key, value) in settings {
+            let value = value.trim();
+            match key {
+                "recidive_enabled" => policy.enabled = value.parse().unwrap_or(policy.enabled),
+                "recidive_max_bans" => policy.max_bans = value.parse().unwrap_or(policy.max_bans),
+                "recidive_find_days" => {
+                    policy.find_days = value.parse().unwrap_or(policy.find_days);
+                },
+                "recidive_ban_seconds" => {
+                    policy.ban_seconds = value.parse().unwrap_or(policy.ban_seconds);
+                },
+                _ => {},
+            }
+        }
+        policy
+    }
+
+    /// Reads the policy from the `settings` table.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn load(pool: &PgPool) -> Result<Self, Error> {
+        let settings: Vec<(String, String)> =
+            query("SELECT key, value FROM settings WHERE key LIKE 'recidive%'")
+                .map(|row: PgRow| (row.get("key"), row.get("value")))
+                .fetch_all(pool)
+                .await?;
+        Ok(Self::from_settings(
+            settings
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        ))
+    }
+
+    /// Stores the policy in the `settings` table.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn save(&self, pool: &PgPool) -> Result<(), Error> {
+        query(
+            "INSERT INTO settings (key, value)
+            SELECT * FROM UNNEST($1::text[], $2::text[])
+            ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, updated_at = NOW()",
+        )
+        .bind(vec![
+            "recidive_enabled",
+            "recidive_max_bans",
+            "recidive_find_days",
+            "recidive_ban_seconds",
+        ])
+        .bind(vec![
+            self.enabled.to_string(),
+            self.max_bans.to_string(),
+            self.find_days.to_string(),
+            self.ban_seconds.to_string(),
+        ])
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Checks the values are usable.
+    ///
+    /// # Errors
+    /// Describes the first invalid value.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_bans < 2 {
+            Err("recidive_max_bans must be at least 2".to_string())
+        } else if !(1..=365).contains(&self.find_days) {
+            Err("recidive_find_days must be between 1 and 365".to_string())
+        } else if self.ban_seconds == 0 {
+            Err("recidive_ban_seconds must not be 0".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// `true` if `bans` recent rule bans trigger a recidive ban.
+    #[must_use]
+    pub fn is_triggered(&self, bans: usize) -> bool {
+        self.enabled && usize::try_from(self.max_bans).is_ok_and(|max_bans| bans >= max_bans)
+    }
+
+    /// Duration of the recidive ban.
+    #[must_use]
+    pub const fn ban_duration(&self) -> i64 {
+        if self.ban_seconds < 0 {
+            BanInfo::PERMANENT
+        } else {
+            self.ban_seconds
+        }
+    }
+
+    /// Reason recorded on a recidive ban.
+    #[must_use]
+    pub fn reason(&self, bans: usize) -> String {
+        format!("Recidive: {bans} bans in {} day(s)", self.find_days)
+    }
+
+    /// Bans `subject` globally if its rule bans reached the configured count.
+    /// Returns the recidive ban, if one was issued.
+    ///
+    /// # Errors
+    /// Fails if the settings or the bans can't be read, or the ban stored.
+    pub async fn apply(
+        pool: &PgPool,
+        store: &dyn StateStore,
+        subject: &Subject,
+    ) -> Result<Option<BanInfo>, AppError> {
+        let policy = Self::load(pool).await?;
+        if !policy.enabled {
+            return Ok(None);
+        }
+        let triggers = BanRecord::recidive_triggers(pool, subject, policy.find_days).await?;
+        if !policy.is_triggered(triggers.len()) {
+            return Ok(None);
+        }
+        let reason = policy.reason(triggers.len());
+        info!("Banning recidive {}: {}", subject, reason);
+        let ban = store
+            .ban(
+                subject.clone(),
+                None,
+                reason,
+                Some(policy.ban_duration()),
+                triggers,
+            )
+            .await?;
+        Ok(Some(ban))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_settings_keeps_defaults() {
+        let policy = RecidivePolicy::from_settings([
+            ("recidive_enabled", "true"),
+            ("recidive_max_bans", "3"),
+            ("recidive_ban_seconds", "-1"),
+            ("recidive_find_days", "not a number"),
+            ("log_retention_days", "30"),
+        ]);
+        assert_eq!(
+            policy,
+            RecidivePolicy {
+                enabled: true,
+                max_bans: 3,
+                find_days: 1,
+                ban_seconds: -1,
+            }
+        );
+        assert_eq!(policy.ban_duration(), BanInfo::PERMANENT);
+        assert!(policy.validate().is_ok());
+    }
+
+    #[test]
+    fn test_is_triggered() {
+        let mut policy = RecidivePolicy {
+            max_bans: 3,
+            ..RecidivePolicy::default()
+        };
+        assert!(!policy.is_triggered(3));
+        policy.enabled = true;
+        assert!(!policy.is_triggered(2));
+        assert!(policy.is_triggered(3));
+    }
+
+    #[test]
+    fn test_validate() {
+        for invalid in [
+            RecidivePolicy {
+                max_bans: 1,
+                ..RecidivePolicy::default()
+            },
+            RecidivePolicy {
+                find_days: 0,
+                ..RecidivePolicy::default()
+            },
+            RecidivePolicy {
+                ban_seconds: 0,
+                ..RecidivePolicy::default()
+            },
+        ] {
+            assert!(invalid.validate().is_err(), "{invalid:?}");
+        }
+    }
+}
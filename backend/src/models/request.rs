@@ -3,6 +3,12 @@
 //! Define [`Request`] y [`NewRequest`], las estructuras que representan
 //! las peticiones HTTP capturadas por el servicio. Incluye operaciones
 //! CRUD y consultas estadísticas (top países, top reglas, evolución temporal).
+//!
+//! Cada petición guarda la decisión que tomó `shuul()` ([`RequestDecision`])
+//! y su motivo, por los que se puede filtrar el listado y las estadísticas.
+//! Las peticiones rechazadas en la puerta (bans, lista de bloqueados, feeds,
+//! `CrowdSec`) se muestrean con [`RejectionSampler`] para que un cliente
+//! baneado no llene la tabla.
 
 use chrono::{DateTime, Utc};
 use http::Uri;
@@ -11,13 +17,111 @@ use serde::{Deserialize, Serialize};
 use sqlx::{
     Error, FromRow, Row,
     postgres::{PgPool, PgRow},
-    query, query_as,
+    query,
 };
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use tracing::debug;
 
 use crate::models::IPData;
+use crate::models::access_log::AccessLogEntry;
+use crate::models::rule::RuleAction;
+
+/// What `shuul()` did with a request.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestDecision {
+    /// Let through, by a rule or because no rule matched.
+    #[default]
+    Allow,
+    /// Rejected by a rule: deny, tarpit, challenge or an aggregate limit.
+    Deny,
+    /// Rejected because the client, its range, ASN, country or key is banned.
+    Banned,
+    /// Rejected because it exceeded a rule's rate limit.
+    RateLimited,
+    /// Let through after matching only `log_only` or `rate_limit_only` rules.
+    Monitor,
+}
+
+impl RequestDecision {
+    /// Name of the decision as stored in the database.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+            Self::Banned => "banned",
+            Self::RateLimited => "rate_limited",
+            Self::Monitor => "monitor",
+        }
+    }
+
+    /// Decision for a request answered by a rule with `action`.
+    #[must_use]
+    pub const fn from_action(action: RuleAction) -> Self {
+        match action {
+            RuleAction::Allow => Self::Allow,
+            RuleAction::Deny | RuleAction::Tarpit | RuleAction::Challenge => Self::Deny,
+            RuleAction::LogOnly | RuleAction::RateLimitOnly => Self::Monitor,
+        }
+    }
+}
+
+impl FromStr for RequestDecision {
+    type Err = String;
 
-#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "deny" => Ok(Self::Deny),
+            "banned" => Ok(Self::Banned),
+            "rate_limited" => Ok(Self::RateLimited),
+            "monitor" => Ok(Self::Monitor),
+            other => Err(format!("Unknown request decision: {other}")),
+        }
+    }
+}
+
+/// Minimum time between two saved rejections of the same client.
+pub const REJECTION_SAMPLE_INTERVAL: Duration = Duration::from_mins(1);
+/// Most clients tracked at once; while it is full of recent ones, further
+/// clients are not sampled.
+const REJECTION_SAMPLE_KEYS: usize = 10_000;
+
+/// Keeps one saved request per client and [`REJECTION_SAMPLE_INTERVAL`] of
+/// those rejected before the rules are evaluated.
+#[derive(Debug, Default)]
+pub struct RejectionSampler {
+    saved: HashMap<String, Instant>,
+}
+
+impl RejectionSampler {
+    /// `true` if a rejection of `client` at `now` should be saved, which then
+    /// counts as its last saved one. `false` for a new client while
+    /// [`REJECTION_SAMPLE_KEYS`] clients were saved within the interval.
+    pub fn allow(&mut self, client: &str, now: Instant) -> bool {
+        if self
+            .saved
+            .get(client)
+            .is_some_and(|saved| now.duration_since(*saved) < REJECTION_SAMPLE_INTERVAL)
+        {
+            return false;
+        }
+        if self.saved.len() >= REJECTION_SAMPLE_KEYS {
+            self.saved
+                .retain(|_, saved| now.duration_since(*saved) < REJECTION_SAMPLE_INTERVAL);
+            if self.saved.len() >= REJECTION_SAMPLE_KEYS {
+                return false;
+            }
+        }
+        self.saved.insert(client.to_string(), now);
+        true
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Request {
     id: i32,
     pub ip_address: Option<String>,
@@ -29,6 +133,9 @@ pub struct Request {
     pub country_name: Option<String>,
     pub country_code: Option<String>,
     pub rule_id: Option<i32>,
+    pub tags: Vec<String>,
+    pub decision: RequestDecision,
+    pub reason: Option<String>,
     created_at: DateTime<Utc>,
 }
 
@@ -43,6 +150,19 @@ pub struct NewRequest {
     pub country_name: Option<String>,
     pub country_code: Option<String>,
     pub rule_id: Option<i32>,
+    /// Tags added by the log-only rules that matched the request.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub decision: RequestDecision,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Autonomous system of the client IP (not persisted).
+    #[serde(default, skip_serializing)]
+    pub asn: Option<u32>,
+    /// Response status, only known for access-log lines (not persisted).
+    #[serde(default, skip_serializing)]
+    pub status: Option<String>,
     created_at: DateTime<Utc>,
 }
 
@@ -57,6 +177,8 @@ pub struct ReadRequestParams {
     pub city_name: Option<String>,
     pub country_name: Option<String>,
     pub country_code: Option<String>,
+    pub decision: Option<String>,
+    pub reason: Option<String>,
     pub page: Option<u32>,
     pub limit: Option<u32>,
     pub sort_by: Option<String>,
@@ -82,7 +204,11 @@ use crate::constants::DEFAULT_LIMIT;
 use crate::constants::DEFAULT_PAGE;
 
 impl NewRequest {
-    pub fn from_request(headers: &http::HeaderMap, maxmind_db: &Reader<Vec<u8>>) -> Self {
+    pub fn from_request(
+        headers: &http::HeaderMap,
+        maxmind_db: &Reader<Vec<u8>>,
+        asn_db: Option<&Reader<Vec<u8>>>,
+    ) -> Self {
         let protocol = headers
             .get("x-forwarded-proto")
             .map(|s| s.to_str())
@@ -106,6 +232,7 @@ impl NewRequest {
             .and_then(std::result::Result::ok)
             .unwrap_or("");
         let ip_data = IPData::complete(maxmind_db, ip);
+        let asn = asn_db.and_then(|db| IPData::lookup_asn(db, ip)).map(|(asn, _)| asn);
         let ip_address = if ip.is_empty() {
             None
         } else {
@@ -146,6 +273,41 @@ impl NewRequest {
             country_name,
             country_code,
             rule_id: None,
+            tags: Vec::new(),
+            decision: RequestDecision::Allow,
+            reason: None,
+            asn,
+            status: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Builds a request from a parsed access log line, with geolocation.
+    #[must_use]
+    pub fn from_access_log(
+        entry: AccessLogEntry,
+        maxmind_db: &Reader<Vec<u8>>,
+        asn_db: Option<&Reader<Vec<u8>>>,
+    ) -> Self {
+        let ip_data = IPData::complete(maxmind_db, &entry.ip_address);
+        let asn = asn_db
+            .and_then(|db| IPData::lookup_asn(db, &entry.ip_address))
+            .map(|(asn, _)| asn);
+        Self {
+            ip_address: Some(entry.ip_address),
+            protocol: entry.protocol,
+            fqdn: entry.fqdn,
+            path: Some(entry.path),
+            query: entry.query,
+            city_name: ip_data.city_name.filter(|s| !s.is_empty()),
+            country_name: ip_data.country_name.filter(|s| !s.is_empty()),
+            country_code: ip_data.country_code.filter(|s| !s.is_empty()),
+            rule_id: None,
+            tags: Vec::new(),
+            decision: RequestDecision::Allow,
+            reason: None,
+            asn,
+            status: Some(entry.status.to_string()),
             created_at: Utc::now(),
         }
     }
@@ -164,12 +326,15 @@ impl Request {
             country_name: row.get("country_name"),
             country_code: row.get("country_code"),
             rule_id: row.get("rule_id"),
+            tags: row.get("tags"),
+            decision: row.get::<String, _>("decision").parse().unwrap_or_default(),
+            reason: row.get("reason"),
             created_at: row.get("created_at"),
         }
     }
 
     pub async fn create(pool: &PgPool, request: NewRequest) -> Result<Self, Error> {
-        let sql = "INSERT INTO requests (ip_address, protocol, fqdn, path, query, city_name, country_name, country_code, rule_id, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING *";
+        let sql = "INSERT INTO requests (ip_address, protocol, fqdn, path, query, city_name, country_name, country_code, rule_id, tags, decision, reason, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) RETURNING *";
         query(sql)
             .bind(request.ip_address)
             .bind(request.protocol)
@@ -180,6 +345,9 @@ impl Request {
             .bind(request.country_name)
             .bind(request.country_code)
             .bind(request.rule_id)
+            .bind(request.tags)
+            .bind(request.decision.as_str())
+            .bind(request.reason)
             .bind(request.created_at)
             .map(Self::from_row)
             .fetch_one(pool)
@@ -195,15 +363,29 @@ impl Request {
             .await
     }
 
+    /// Counts requests: `total`, `filtered` (rejected, whatever the reason)
+    /// or those with the given decision.
     pub async fn read_info(pool: &PgPool, info: &str) -> Result<i64, Error> {
-        let sql = if info == "total" {
-            "SELECT count(*) FROM requests"
+        let decisions: Vec<&str> = if info == "total" {
+            Vec::new()
         } else if info == "filtered" {
-            "SELECT count(*) FROM requests WHERE rule_id IS NOT NULL"
+            [
+                RequestDecision::Deny,
+                RequestDecision::Banned,
+                RequestDecision::RateLimited,
+            ]
+            .map(RequestDecision::as_str)
+            .to_vec()
         } else {
-            return Err(Error::RowNotFound);
+            let decision = info
+                .parse::<RequestDecision>()
+                .map_err(|_| Error::RowNotFound)?;
+            vec![decision.as_str()]
         };
+        let sql = "SELECT count(*) FROM requests
+            WHERE cardinality($1::text[]) = 0 OR decision = ANY($1)";
         query(sql)
+            .bind(decisions)
             .map(|cp_row: PgRow| cp_row.get(0))
             .fetch_one(pool)
             .await
@@ -213,14 +395,14 @@ impl Request {
         if requests.is_empty() {
             return Ok(Vec::new());
         }
-        let num_columns = 10; // Número de columnas a insertar: ip_address, protocol, ..., created_at
+        let num_columns = 13; // Número de columnas a insertar: ip_address, protocol, ..., created_at
         let mut placeholders = String::new();
         //let mut all_bindings = Vec::new(); // Vector para almacenar todos los valores a enlazar
         for (i, _request) in requests.iter().enumerate() {
             let start_index = i * num_columns + 1;
-            // Genera ($1, $2, $3, ... $10), ($11, $12, ... $20), etc.
+            // Genera ($1, $2, $3, ... $13), ($14, $15, ... $26), etc.
             placeholders.push_str(&format!(
-                "(${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${})",
+                "(${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${})",
                 start_index,
                 start_index + 1,
                 start_index + 2,
@@ -230,21 +412,25 @@ impl Request {
                 start_index + 6,
                 start_index + 7,
                 start_index + 8,
-                start_index + 9
+                start_index + 9,
+                start_index + 10,
+                start_index + 11,
+                start_index + 12
             ));
             if i < requests.len() - 1 {
                 placeholders.push_str(", ");
             }
         }
         let base_sql = "INSERT INTO requests (ip_address, protocol,
-        fqdn, path, query, city_name, country_name, country_code, rule_id, created_at) VALUES ";
+        fqdn, path, query, city_name, country_name, country_code, rule_id, tags, decision, reason,
+        created_at) VALUES ";
         let full_sql = format!("{base_sql} {placeholders} RETURNING *");
 
         // 2. Ejecutar la consulta con Transaction para el binding
         let mut transaction = pool.begin().await?;
 
         // Necesitamos usar `query_as` o `query` para el binding dinámico.
-        let mut query_builder = query_as::<_, Self>(&full_sql);
+        let mut query_builder = query(&full_sql);
 
         for request in &requests {
             query_builder = query_builder
@@ -257,10 +443,16 @@ impl Request {
                 .bind(&request.country_name)
                 .bind(&request.country_code)
                 .bind(request.rule_id)
+                .bind(&request.tags)
+                .bind(request.decision.as_str())
+                .bind(&request.reason)
                 .bind(request.created_at);
         }
 
-        let created_requests = query_builder.fetch_all(&mut *transaction).await?;
+        let created_requests = query_builder
+            .map(Self::from_row)
+            .fetch_all(&mut *transaction)
+            .await?;
 
         transaction.commit().await?;
 
@@ -277,6 +469,8 @@ impl Request {
             ("city_name", &params.city_name),
             ("country_name", &params.country_name),
             ("country_code", &params.country_code),
+            ("decision", &params.decision),
+            ("reason", &params.reason),
         ];
         let active_filters: Vec<(&str, String)> = filters
             .into_iter()
@@ -310,6 +504,8 @@ impl Request {
             ("city_name", &params.city_name),
             ("country_name", &params.country_name),
             ("country_code", &params.country_code),
+            ("decision", &params.decision),
+            ("reason", &params.reason),
         ];
         let active_filters: Vec<(&str, String)> = filters
             .into_iter()
@@ -333,6 +529,8 @@ impl Request {
             "city_name",
             "country_name",
             "country_code",
+            "decision",
+            "reason",
         ]
         .contains(&sort_by)
         {
@@ -369,9 +567,14 @@ impl Request {
             .await
     }
 
+    /// Top 10 rules by matched requests, optionally only those with
+    /// `decision`.
     #[allow(clippy::needless_raw_string_hashes)]
-    pub async fn top_rules(pool: &PgPool) -> Result<Vec<(String, i32, f32)>, Error> {
-        debug!("Computing top rules");
+    pub async fn top_rules(
+        pool: &PgPool,
+        decision: Option<RequestDecision>,
+    ) -> Result<Vec<(String, i32, f32)>, Error> {
+        debug!("Computing top rules (decision: {:?})", decision);
         let sql = r#"SELECT
     CASE
         WHEN ranking <= 10 THEN rule_id::text
@@ -380,7 +583,9 @@ impl Request {
     SUM(total_requests)::integer AS count,
     -- Calculate the percentage of each group relative to the total
     ROUND(
-        (SUM(total_requests) * 100.0 / (SELECT COUNT(*) FROM requests))::numeric,
+        (SUM(total_requests) * 100.0 / (
+            SELECT COUNT(*) FROM requests WHERE $1::text IS NULL OR decision = $1
+        ))::numeric,
         1
     )::float4 AS percentage
 FROM
@@ -395,6 +600,7 @@ FROM
         requests
     WHERE
         rule_id IS NOT NULL
+        AND ($1::text IS NULL OR decision = $1)
     GROUP BY
         rule_id
 ) AS ranked_requests
@@ -404,6 +610,7 @@ ORDER BY
     count DESC;
     "#;
         query(sql)
+            .bind(decision.map(RequestDecision::as_str))
             .map(|row: PgRow| {
                 let country: String = row.get("rule");
                 let count: i32 = row.get("count");
@@ -413,9 +620,14 @@ ORDER BY
             .fetch_all(pool)
             .await
     }
+
+    /// Top 10 countries by requests, optionally only those with `decision`.
     #[allow(clippy::needless_raw_string_hashes)]
-    pub async fn top_countries(pool: &PgPool) -> Result<Vec<(String, i32, f32)>, Error> {
-        debug!("Computing top countries");
+    pub async fn top_countries(
+        pool: &PgPool,
+        decision: Option<RequestDecision>,
+    ) -> Result<Vec<(String, i32, f32)>, Error> {
+        debug!("Computing top countries (decision: {:?})", decision);
         let sql = r#"SELECT
     CASE
         WHEN ranking <= 10 THEN country_name
@@ -424,7 +636,9 @@ ORDER BY
     SUM(total_requests)::integer AS count,
     -- Calculate the percentage of each group relative to the total
     ROUND(
-        (SUM(total_requests) * 100.0 / (SELECT COUNT(*) FROM requests))::numeric,
+        (SUM(total_requests) * 100.0 / (
+            SELECT COUNT(*) FROM requests WHERE $1::text IS NULL OR decision = $1
+        ))::numeric,
         1
     )::float4 AS percentage
 FROM
@@ -437,6 +651,8 @@ FROM
         ROW_NUMBER() OVER (ORDER BY COUNT(*) DESC) AS ranking
     FROM
         requests
+    WHERE
+        $1::text IS NULL OR decision = $1
     GROUP BY
         country_name
 ) AS ranked_requests
@@ -446,6 +662,7 @@ ORDER BY
     count DESC;
     "#;
         query(sql)
+            .bind(decision.map(RequestDecision::as_str))
             .map(|row: PgRow| {
                 let country: String = row.get("country");
                 let count: i32 = row.get("count");
@@ -457,9 +674,21 @@ ORDER BY
     }
     // request.rs -> impl Request -> evolution
 
+    /// Requests per `unit` over the `last` units, one series per country
+    /// (top 10 plus `other`) or per decision, as `series` says. Optionally
+    /// only requests with `decision`.
     #[allow(clippy::needless_raw_string_hashes)]
-    pub async fn evolution(pool: &PgPool, unit: &str, last: i32) -> Result<Vec<TimeSeries>, Error> {
-        debug!("Evolution params - unit: {}, last: {}", unit, last);
+    pub async fn evolution(
+        pool: &PgPool,
+        unit: &str,
+        last: i32,
+        series: &str,
+        decision: Option<RequestDecision>,
+    ) -> Result<Vec<TimeSeries>, Error> {
+        debug!(
+            "Evolution params - unit: {}, last: {}, series: {}, decision: {:?}",
+            unit, last, series, decision
+        );
 
         // ... (Validación de unit_group y determinación de x_output - esto está correcto) ...
         let unit_group = match unit {
@@ -467,50 +696,57 @@ ORDER BY
             "day" => "day",
             _ => return Err(Error::RowNotFound),
         };
+        let column = match series {
+            "country" => "country_code",
+            "decision" => "decision",
+            _ => return Err(Error::RowNotFound),
+        };
         let sql = format!(
-            r#"WITH ranked_countries AS (
+            r#"WITH ranked_series AS (
             -- CTE 1: Calcula el ranking total de peticiones en el periodo
             SELECT
-                country_code,
+                {column} AS series,
                 -- Usamos ROW_NUMBER() para un Top 10 estricto
                 ROW_NUMBER() OVER (ORDER BY COUNT(*) DESC) AS ranking
             FROM
                 requests
             WHERE
                 created_at >= NOW() - INTERVAL '1 {unit_group}' * $1 
-                AND country_code IS NOT NULL
+                AND {column} IS NOT NULL
+                AND ($2::text IS NULL OR decision = $2)
             GROUP BY
-                country_code
+                series
         ),
         -- NUEVO CTE 3: Consolidar y sumar las peticiones para 'other' o códigos reales
         aggregated_series AS (
             SELECT
                 CASE
-                    -- Aplica la etiqueta 'other' a todos los países fuera del Top 10
-                    WHEN rp.ranking <= 10 THEN gr.country_code
+                    -- Aplica la etiqueta 'other' a todas las series fuera del Top 10
+                    WHEN rp.ranking <= 10 THEN gr.series
                     ELSE 'other'
-                END AS series_id, -- El ID de la serie (país o 'other')
+                END AS series_id, -- El ID de la serie (país, decisión o 'other')
                 gr.date_group,
                 -- SUMA las peticiones para cada (series_id, date_group)
                 SUM(gr.num_requests) AS total_requests_for_point
             FROM
-                ranked_countries AS rp
+                ranked_series AS rp
             JOIN (
-                -- CTE 2: Cuenta las peticiones por país y unidad de tiempo
+                -- CTE 2: Cuenta las peticiones por serie y unidad de tiempo
                 SELECT
-                    country_code,
+                    {column} AS series,
                     DATE_TRUNC('{unit_group}', created_at) AS date_group,
                     COUNT(*)::integer AS num_requests
                 FROM
                     requests
                 WHERE
                     created_at >= NOW() - INTERVAL '1 {unit_group}' * $1 
-                    AND country_code IS NOT NULL
+                    AND {column} IS NOT NULL
+                    AND ($2::text IS NULL OR decision = $2)
                 GROUP BY
-                    country_code,
+                    series,
                     date_group
             ) AS gr
-            ON rp.country_code = gr.country_code
+            ON rp.series = gr.series
             GROUP BY
                 series_id,
                 gr.date_group -- AGREGAMOS POR SERIE Y UNIDAD DE TIEMPO
@@ -535,7 +771,71 @@ ORDER BY
         );
         sqlx::query_as::<_, TimeSeries>(&sql)
             .bind(last)
+            .bind(decision.map(RequestDecision::as_str))
             .fetch_all(pool)
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decision_round_trip() {
+        for decision in [
+            RequestDecision::Allow,
+            RequestDecision::Deny,
+            RequestDecision::Banned,
+            RequestDecision::RateLimited,
+            RequestDecision::Monitor,
+        ] {
+            assert_eq!(decision.as_str().parse(), Ok(decision));
+            assert_eq!(
+                serde_json::to_value(decision).unwrap(),
+                serde_json::Value::from(decision.as_str())
+            );
+        }
+        assert!("filtered".parse::<RequestDecision>().is_err());
+    }
+
+    #[test]
+    fn test_rejection_sampler_per_client() {
+        let mut sampler = RejectionSampler::default();
+        let now = Instant::now();
+        assert!(sampler.allow("192.0.2.1", now));
+        assert!(!sampler.allow("192.0.2.1", now + Duration::from_secs(30)));
+        assert!(sampler.allow("192.0.2.2", now + Duration::from_secs(30)));
+        assert!(sampler.allow("192.0.2.1", now + REJECTION_SAMPLE_INTERVAL));
+    }
+
+    #[test]
+    fn test_rejection_sampler_stays_bounded() {
+        let mut sampler = RejectionSampler::default();
+        let now = Instant::now();
+        for client in 0..REJECTION_SAMPLE_KEYS {
+            assert!(sampler.allow(&client.to_string(), now));
+        }
+        assert!(!sampler.allow("flood", now + Duration::from_secs(1)));
+        assert_eq!(sampler.saved.len(), REJECTION_SAMPLE_KEYS);
+        // Once the tracked clients go stale there is room again
+        assert!(sampler.allow("flood", now + REJECTION_SAMPLE_INTERVAL));
+        assert_eq!(sampler.saved.len(), 1);
+    }
+
+    #[test]
+    fn test_decision_from_action() {
+        assert_eq!(
+            RequestDecision::from_action(RuleAction::Tarpit),
+            RequestDecision::Deny
+        );
+        assert_eq!(
+            RequestDecision::from_action(RuleAction::LogOnly),
+            RequestDecision::Monitor
+        );
+        assert_eq!(
+            RequestDecision::from_action(RuleAction::Allow),
+            RequestDecision::Allow
+        );
+    }
+}
@@ -1,13 +1,18 @@
 //! # Modelo de reglas de filtrado
 //!
 //! Define [`Rule`], [`NewRule`], [`UpdateRule`] y [`CacheRule`].
-//! Las reglas determinan si una petición HTTP debe ser permitida,
-//! denegada y/o almacenada en la base de datos.
+//! Las reglas determinan qué hacer con una petición HTTP ([`RuleAction`]):
+//! permitirla, denegarla, retrasarla (tarpit), registrarla, desafiarla o sólo
+//! contarla para el rate limiting; y si debe almacenarse en la base de datos.
 //!
 //! [`CacheRule`] envuelve una [`Rule`] con un [`Regex`] precompilado
 //! para la coincidencia rápida de URIs en memoria.
 
 use crate::models::request::NewRequest;
+use crate::models::rate_limiter::{
+    AggregateLimit, Gcra, KeyPart, KeySpec, LimiterConfig, RateLimitAlgorithm,
+};
+use axum::http::StatusCode;
 use chrono::{DateTime, Utc};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -16,11 +21,115 @@ use sqlx::{
     postgres::{PgPool, PgRow},
     query,
 };
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A rule over its aggregate limit denies requests unless told otherwise.
+const fn default_aggregate_action() -> RuleAction {
+    RuleAction::Deny
+}
+
+/// Status `shuul()` answers when a rule's rate limit is exceeded, and while
+/// the resulting ban lasts.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverLimitStatus {
+    /// 403 for sliding window bans, 429 for GCRA throttling.
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "403")]
+    Forbidden,
+    #[serde(rename = "429")]
+    TooManyRequests,
+}
+
+impl OverLimitStatus {
+    /// Value stored in the database.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Forbidden => "403",
+            Self::TooManyRequests => "429",
+        }
+    }
+}
+
+impl FromStr for OverLimitStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "403" => Ok(Self::Forbidden),
+            "429" => Ok(Self::TooManyRequests),
+            other => Err(format!("Unknown over-limit status: {other}")),
+        }
+    }
+}
+
+/// What `shuul()` does with a request matching a rule.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    /// Let the request through.
+    #[default]
+    Allow,
+    /// Reject the request.
+    Deny,
+    /// Delay the response by `tarpit_seconds` (at most
+    /// [`MAX_TARPIT_SECONDS`]), then reject the request.
+    Tarpit,
+    /// Tag and store the request; evaluation continues with the next rule.
+    LogOnly,
+    /// Redirect the client to `challenge_url` for verification.
+    Challenge,
+    /// Count the request for rate limiting; evaluation continues with the next rule.
+    RateLimitOnly,
+}
+
+impl RuleAction {
+    /// Name of the action as stored in the database.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+            Self::Tarpit => "tarpit",
+            Self::LogOnly => "log_only",
+            Self::Challenge => "challenge",
+            Self::RateLimitOnly => "rate_limit_only",
+        }
+    }
+
+    /// `true` if the action decides the outcome and stops rule evaluation.
+    #[must_use]
+    pub const fn is_terminal(self) -> bool {
+        !matches!(self, Self::LogOnly | Self::RateLimitOnly)
+    }
+}
+
+impl FromStr for RuleAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "deny" => Ok(Self::Deny),
+            "tarpit" => Ok(Self::Tarpit),
+            "log_only" => Ok(Self::LogOnly),
+            "challenge" => Ok(Self::Challenge),
+            "rate_limit_only" => Ok(Self::RateLimitOnly),
+            other => Err(format!("Unknown rule action: {other}")),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Rule {
     pub id: i32,
     pub weight: i32,
-    pub allow: bool,
+    pub action: RuleAction,
     pub store: bool,
     pub ip_address: Option<String>,
     pub protocol: Option<String>,
@@ -30,7 +139,35 @@ pub struct Rule {
     pub city_name: Option<String>,
     pub country_name: Option<String>,
     pub country_code: Option<String>,
+    #[serde(default)]
+    pub status_code: Option<String>,
     pub rate_limit_enabled: bool,
+    #[serde(default)]
+    pub rate_limit_key: Vec<KeyPart>,
+    pub rate_limit_header: Option<String>,
+    #[serde(default)]
+    pub rate_limit_algorithm: RateLimitAlgorithm,
+    #[serde(default)]
+    pub rate_limit_rate: f64,
+    #[serde(default)]
+    pub rate_limit_burst: i32,
+    #[serde(default)]
+    pub over_limit_status: OverLimitStatus,
+    #[serde(default)]
+    pub aggregate_limit: i32,
+    #[serde(default)]
+    pub aggregate_window_seconds: i64,
+    #[serde(default)]
+    pub aggregate_cooldown_seconds: i64,
+    #[serde(default = "default_aggregate_action")]
+    pub aggregate_action: RuleAction,
+    /// Blocklist feed the client IP must be listed in.
+    #[serde(default)]
+    pub feed_id: Option<i32>,
+    /// `AbuseIPDB` categories the banned IP is reported with; empty to not
+    /// report it.
+    #[serde(default)]
+    pub abuseipdb_categories: Vec<i32>,
     pub max_retry: i32,
     pub find_time_seconds: i64,
     pub ban_time_seconds: i64,
@@ -40,6 +177,9 @@ pub struct Rule {
     pub ban_count_decay_days: i32,
     pub ignoreip: Vec<String>,
     pub webhook: Option<String>,
+    pub tarpit_seconds: i32,
+    pub challenge_url: Option<String>,
+    pub tag: Option<String>,
     pub active: bool,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
@@ -56,6 +196,7 @@ pub struct CacheRule {
     pub city_name: Option<Regex>,
     pub country_name: Option<Regex>,
     pub country_code: Option<Regex>,
+    pub status_code: Option<Regex>,
 }
 
 impl CacheRule {
@@ -106,6 +247,11 @@ impl CacheRule {
                 .as_ref()
                 .filter(|r| !r.is_empty())
                 .and_then(|r| Regex::new(r).ok()),
+            status_code: rule
+                .status_code
+                .as_ref()
+                .filter(|r| !r.is_empty())
+                .and_then(|r| Regex::new(r).ok()),
         }
     }
 
@@ -136,13 +282,18 @@ impl CacheRule {
             && check_match(self.city_name.as_ref(), request.city_name.as_ref())
             && check_match(self.country_name.as_ref(), request.country_name.as_ref())
             && check_match(self.country_code.as_ref(), request.country_code.as_ref())
+            // El status sólo se conoce en las líneas de access log: una regla
+            // con condición de status nunca coincide en forward auth.
+            && self.status_code.as_ref().is_none_or(|regex| {
+                request.status.as_ref().is_some_and(|status| regex.is_match(status))
+            })
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NewRule {
     pub weight: i32,
-    pub allow: bool,
+    pub action: RuleAction,
     pub store: bool,
     pub ip_address: Option<String>,
     pub protocol: Option<String>,
@@ -152,7 +303,25 @@ pub struct NewRule {
     pub city_name: Option<String>,
     pub country_name: Option<String>,
     pub country_code: Option<String>,
+    #[serde(default)]
+    pub status_code: Option<String>,
     pub rate_limit_enabled: Option<bool>,
+    #[serde(default)]
+    pub rate_limit_key: Option<Vec<KeyPart>>,
+    pub rate_limit_header: Option<String>,
+    pub rate_limit_algorithm: Option<RateLimitAlgorithm>,
+    pub rate_limit_rate: Option<f64>,
+    pub rate_limit_burst: Option<i32>,
+    #[serde(default)]
+    pub over_limit_status: Option<OverLimitStatus>,
+    pub aggregate_limit: Option<i32>,
+    pub aggregate_window_seconds: Option<i64>,
+    pub aggregate_cooldown_seconds: Option<i64>,
+    pub aggregate_action: Option<RuleAction>,
+    #[serde(default)]
+    pub feed_id: Option<i32>,
+    #[serde(default)]
+    pub abuseipdb_categories: Option<Vec<i32>>,
     pub max_retry: Option<i32>,
     pub find_time_seconds: Option<i64>,
     pub ban_time_seconds: Option<i64>,
@@ -162,6 +331,9 @@ pub struct NewRule {
     pub ban_count_decay_days: Option<i32>,
     pub ignoreip: Option<Vec<String>>,
     pub webhook: Option<String>,
+    pub tarpit_seconds: Option<i32>,
+    pub challenge_url: Option<String>,
+    pub tag: Option<String>,
     pub active: bool,
 }
 
@@ -169,7 +341,7 @@ pub struct NewRule {
 pub struct UpdateRule {
     pub id: i32,
     pub weight: i32,
-    pub allow: bool,
+    pub action: RuleAction,
     pub store: bool,
     pub ip_address: Option<String>,
     pub protocol: Option<String>,
@@ -179,7 +351,25 @@ pub struct UpdateRule {
     pub city_name: Option<String>,
     pub country_name: Option<String>,
     pub country_code: Option<String>,
+    #[serde(default)]
+    pub status_code: Option<String>,
     pub rate_limit_enabled: Option<bool>,
+    #[serde(default)]
+    pub rate_limit_key: Option<Vec<KeyPart>>,
+    pub rate_limit_header: Option<String>,
+    pub rate_limit_algorithm: Option<RateLimitAlgorithm>,
+    pub rate_limit_rate: Option<f64>,
+    pub rate_limit_burst: Option<i32>,
+    #[serde(default)]
+    pub over_limit_status: Option<OverLimitStatus>,
+    pub aggregate_limit: Option<i32>,
+    pub aggregate_window_seconds: Option<i64>,
+    pub aggregate_cooldown_seconds: Option<i64>,
+    pub aggregate_action: Option<RuleAction>,
+    #[serde(default)]
+    pub feed_id: Option<i32>,
+    #[serde(default)]
+    pub abuseipdb_categories: Option<Vec<i32>>,
     pub max_retry: Option<i32>,
     pub find_time_seconds: Option<i64>,
     pub ban_time_seconds: Option<i64>,
@@ -189,6 +379,9 @@ pub struct UpdateRule {
     pub ban_count_decay_days: Option<i32>,
     pub ignoreip: Option<Vec<String>>,
     pub webhook: Option<String>,
+    pub tarpit_seconds: Option<i32>,
+    pub challenge_url: Option<String>,
+    pub tag: Option<String>,
     pub active: bool,
 }
 #[allow(dead_code)]
@@ -196,7 +389,7 @@ pub struct UpdateRule {
 pub struct ReadRuleParams {
     pub id: Option<i32>,
     pub weight: Option<i32>,
-    pub allow: Option<bool>,
+    pub action: Option<RuleAction>,
     pub store: Option<bool>,
     pub ip_address: Option<String>,
     pub protocol: Option<String>,
@@ -206,7 +399,19 @@ pub struct ReadRuleParams {
     pub city_name: Option<String>,
     pub country_name: Option<String>,
     pub country_code: Option<String>,
+    #[serde(default)]
+    pub status_code: Option<String>,
     pub rate_limit_enabled: Option<bool>,
+    pub rate_limit_header: Option<String>,
+    pub rate_limit_algorithm: Option<RateLimitAlgorithm>,
+    pub rate_limit_rate: Option<f64>,
+    pub rate_limit_burst: Option<i32>,
+    #[serde(default)]
+    pub over_limit_status: Option<OverLimitStatus>,
+    pub aggregate_limit: Option<i32>,
+    pub aggregate_window_seconds: Option<i64>,
+    pub aggregate_cooldown_seconds: Option<i64>,
+    pub aggregate_action: Option<RuleAction>,
     pub max_retry: Option<i32>,
     pub find_time_seconds: Option<i64>,
     pub ban_time_seconds: Option<i64>,
@@ -216,6 +421,9 @@ pub struct ReadRuleParams {
     pub ban_count_decay_days: Option<i32>,
     pub ignoreip: Option<Vec<String>>,
     pub webhook: Option<String>,
+    pub tarpit_seconds: Option<i32>,
+    pub challenge_url: Option<String>,
+    pub tag: Option<String>,
     pub active: Option<bool>,
     pub page: Option<u32>,
     pub limit: Option<u32>,
@@ -225,18 +433,112 @@ pub struct ReadRuleParams {
 use crate::constants::DEFAULT_LIMIT;
 use crate::constants::DEFAULT_PAGE;
 
+/// Gap between consecutive weights when rules are renumbered.
+pub const WEIGHT_STEP: i32 = 10;
+
+/// Longest delay a tarpit rule may hold a request, in seconds.
+pub const MAX_TARPIT_SECONDS: i32 = 30;
+
+/// Where to place a rule relative to another one when reordering.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    Before,
+    After,
+}
+
+/// Moves `id` right before or after `anchor` in `order`.
+///
+/// Returns `None` if either id is not in `order` or both are the same rule.
+#[must_use]
+pub fn place(order: &[i32], id: i32, anchor: i32, placement: Placement) -> Option<Vec<i32>> {
+    if id == anchor || !order.contains(&id) {
+        return None;
+    }
+    let mut order: Vec<i32> = order.iter().copied().filter(|r| *r != id).collect();
+    let index = order.iter().position(|r| *r == anchor)?;
+    let index = match placement {
+        Placement::Before => index,
+        Placement::After => index + 1,
+    };
+    order.insert(index, id);
+    Some(order)
+}
+
 impl From<Rule> for CacheRule {
     fn from(val: Rule) -> Self {
         CacheRule::from_rule(val)
     }
 }
 
+/// Database form of a rate-limit key; an empty key means the client IP.
+fn key_parts(parts: Option<&[KeyPart]>) -> Vec<String> {
+    KeySpec::new(parts.unwrap_or_default(), None)
+        .parts()
+        .iter()
+        .map(|part| part.as_str().to_string())
+        .collect()
+}
+
 impl Rule {
+    /// Rate-limit key used to count requests matching this rule.
+    #[must_use]
+    pub fn key_spec(&self) -> KeySpec {
+        KeySpec::new(&self.rate_limit_key, self.rate_limit_header.as_deref())
+    }
+
+    /// Status answered when this rule's rate limit is exceeded.
+    #[must_use]
+    pub const fn over_limit_status(&self) -> StatusCode {
+        match (self.over_limit_status, self.rate_limit_algorithm) {
+            (OverLimitStatus::Forbidden, _)
+            | (OverLimitStatus::Auto, RateLimitAlgorithm::SlidingWindow) => StatusCode::FORBIDDEN,
+            (OverLimitStatus::TooManyRequests, _)
+            | (OverLimitStatus::Auto, RateLimitAlgorithm::Gcra) => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    /// Aggregate limit of this rule, if it has one.
+    #[must_use]
+    pub fn aggregate_limit(&self) -> Option<AggregateLimit> {
+        let limit = u32::try_from(self.aggregate_limit).ok().filter(|limit| *limit > 0)?;
+        Some(AggregateLimit {
+            limit,
+            window: Duration::from_secs(u64::try_from(self.aggregate_window_seconds).unwrap_or(0)),
+            cooldown: Duration::from_secs(
+                u64::try_from(self.aggregate_cooldown_seconds).unwrap_or(0),
+            ),
+        })
+    }
+
+    /// Rate limiter algorithm and limits of this rule.
+    ///
+    /// A GCRA rule with an invalid rate falls back to the sliding window and
+    /// a `max_retry` below 1 is taken as 1; the rule analysis reports both.
+    #[must_use]
+    pub fn limiter_config(&self) -> LimiterConfig {
+        let sliding_window = || LimiterConfig::SlidingWindow {
+            max_retry: u32::try_from(self.max_retry)
+                .ok()
+                .filter(|max_retry| *max_retry > 0)
+                .unwrap_or(1),
+            find_time_seconds: self.find_time_seconds,
+        };
+        match self.rate_limit_algorithm {
+            RateLimitAlgorithm::SlidingWindow => sliding_window(),
+            RateLimitAlgorithm::Gcra => Gcra::new(
+                self.rate_limit_rate,
+                u32::try_from(self.rate_limit_burst).unwrap_or(1),
+            )
+            .map_or_else(sliding_window, LimiterConfig::Gcra),
+        }
+    }
+
     fn from_row(row: PgRow) -> Self {
         Self {
             id: row.get("id"),
             weight: row.get("weight"),
-            allow: row.get("allow"),
+            action: row.get::<String, _>("action").parse().unwrap_or_default(),
             store: row.get("store"),
             ip_address: row.get("ip_address"),
             protocol: row.get("protocol"),
@@ -246,7 +548,33 @@ impl Rule {
             city_name: row.get("city_name"),
             country_name: row.get("country_name"),
             country_code: row.get("country_code"),
+            status_code: row.get("status_code"),
             rate_limit_enabled: row.get("rate_limit_enabled"),
+            rate_limit_key: row
+                .get::<Vec<String>, _>("rate_limit_key")
+                .iter()
+                .filter_map(|part| part.parse().ok())
+                .collect(),
+            rate_limit_header: row.get("rate_limit_header"),
+            rate_limit_algorithm: row
+                .get::<String, _>("rate_limit_algorithm")
+                .parse()
+                .unwrap_or_default(),
+            rate_limit_rate: row.get("rate_limit_rate"),
+            rate_limit_burst: row.get("rate_limit_burst"),
+            over_limit_status: row
+                .get::<String, _>("over_limit_status")
+                .parse()
+                .unwrap_or_default(),
+            aggregate_limit: row.get("aggregate_limit"),
+            aggregate_window_seconds: row.get("aggregate_window_seconds"),
+            aggregate_cooldown_seconds: row.get("aggregate_cooldown_seconds"),
+            aggregate_action: row
+                .get::<String, _>("aggregate_action")
+                .parse()
+                .unwrap_or_else(|_| default_aggregate_action()),
+            feed_id: row.get("feed_id"),
+            abuseipdb_categories: row.get("abuseipdb_categories"),
             max_retry: row.get("max_retry"),
             find_time_seconds: row.get("find_time_seconds"),
             ban_time_seconds: row.get("ban_time_seconds"),
@@ -256,6 +584,9 @@ impl Rule {
             ban_count_decay_days: row.get("ban_count_decay_days"),
             ignoreip: row.get("ignoreip"),
             webhook: row.get("webhook"),
+            tarpit_seconds: row.get("tarpit_seconds"),
+            challenge_url: row.get("challenge_url"),
+            tag: row.get("tag"),
             active: row.get("active"),
             created_at: row.get("created_at"),
             updated_at: row.get("updated_at"),
@@ -263,18 +594,24 @@ impl Rule {
     }
 
     pub async fn create(pool: &PgPool, rule: NewRule) -> Result<Self, Error> {
-        let sql = "INSERT INTO rules (weight, allow, store,
+        let sql = "INSERT INTO rules (weight, action, store,
             ip_address, protocol, fqdn, path, query, city_name, country_name,
             country_code, rate_limit_enabled, max_retry, find_time_seconds,
             ban_time_seconds, bantime_increment, bantime_multipliers,
             bantime_maxtime_seconds, ban_count_decay_days, ignoreip, webhook,
-            active, created_at, updated_at) VALUES ($1, $2, $3,
+            tarpit_seconds, challenge_url, tag,
+            active, created_at, updated_at, rate_limit_key, rate_limit_header,
+            rate_limit_algorithm, rate_limit_rate, rate_limit_burst, status_code,
+            over_limit_status, aggregate_limit, aggregate_window_seconds,
+            aggregate_cooldown_seconds, aggregate_action, feed_id, abuseipdb_categories)
+            VALUES ($1, $2, $3,
             $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17,
-            $18, $19, $20, $21, $22, $23, $24) RETURNING *";
+            $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29,
+            $30, $31, $32, $33, $34, $35, $36, $37, $38, $39, $40) RETURNING *";
         let now = Utc::now();
         query(sql)
             .bind(rule.weight)
-            .bind(rule.allow)
+            .bind(rule.action.as_str())
             .bind(rule.store)
             .bind(rule.ip_address)
             .bind(rule.protocol)
@@ -294,9 +631,29 @@ impl Rule {
             .bind(rule.ban_count_decay_days.unwrap_or(30))
             .bind(rule.ignoreip.unwrap_or_default())
             .bind(rule.webhook)
+            .bind(rule.tarpit_seconds.unwrap_or(10))
+            .bind(rule.challenge_url)
+            .bind(rule.tag)
             .bind(rule.active)
             .bind(now)
             .bind(now)
+            .bind(key_parts(rule.rate_limit_key.as_deref()))
+            .bind(rule.rate_limit_header)
+            .bind(rule.rate_limit_algorithm.unwrap_or_default().as_str())
+            .bind(rule.rate_limit_rate.unwrap_or(1.0))
+            .bind(rule.rate_limit_burst.unwrap_or(10))
+            .bind(rule.status_code)
+            .bind(rule.over_limit_status.unwrap_or_default().as_str())
+            .bind(rule.aggregate_limit.unwrap_or(0))
+            .bind(rule.aggregate_window_seconds.unwrap_or(60))
+            .bind(rule.aggregate_cooldown_seconds.unwrap_or(300))
+            .bind(
+                rule.aggregate_action
+                    .unwrap_or_else(default_aggregate_action)
+                    .as_str(),
+            )
+            .bind(rule.feed_id)
+            .bind(rule.abuseipdb_categories.unwrap_or_default())
             .map(Self::from_row)
             .fetch_one(pool)
             .await
@@ -319,7 +676,7 @@ impl Rule {
     pub async fn update(pool: &PgPool, rule: UpdateRule) -> Result<Self, Error> {
         let sql = "UPDATE rules set
                 weight = $1,
-                allow = $2,
+                action = $2,
                 store = $3,
                 ip_address = $4,
                 protocol = $5,
@@ -339,14 +696,30 @@ impl Rule {
                 ban_count_decay_days = $19,
                 ignoreip = $20,
                 webhook = $21,
-                active = $22,
-                updated_at = $23
-            WHERE id = $24
+                tarpit_seconds = $22,
+                challenge_url = $23,
+                tag = $24,
+                active = $25,
+                updated_at = $26,
+                rate_limit_key = $28,
+                rate_limit_header = $29,
+                rate_limit_algorithm = $30,
+                rate_limit_rate = $31,
+                rate_limit_burst = $32,
+                status_code = $33,
+                over_limit_status = $34,
+                aggregate_limit = $35,
+                aggregate_window_seconds = $36,
+                aggregate_cooldown_seconds = $37,
+                aggregate_action = $38,
+                feed_id = $39,
+                abuseipdb_categories = $40
+            WHERE id = $27
             RETURNING *";
         let now = Utc::now();
         query(sql)
             .bind(rule.weight)
-            .bind(rule.allow)
+            .bind(rule.action.as_str())
             .bind(rule.store)
             .bind(rule.ip_address)
             .bind(rule.protocol)
@@ -366,9 +739,29 @@ impl Rule {
             .bind(rule.ban_count_decay_days.unwrap_or(30))
             .bind(rule.ignoreip.unwrap_or_default())
             .bind(rule.webhook)
+            .bind(rule.tarpit_seconds.unwrap_or(10))
+            .bind(rule.challenge_url)
+            .bind(rule.tag)
             .bind(rule.active)
             .bind(now)
             .bind(rule.id)
+            .bind(key_parts(rule.rate_limit_key.as_deref()))
+            .bind(rule.rate_limit_header)
+            .bind(rule.rate_limit_algorithm.unwrap_or_default().as_str())
+            .bind(rule.rate_limit_rate.unwrap_or(1.0))
+            .bind(rule.rate_limit_burst.unwrap_or(10))
+            .bind(rule.status_code)
+            .bind(rule.over_limit_status.unwrap_or_default().as_str())
+            .bind(rule.aggregate_limit.unwrap_or(0))
+            .bind(rule.aggregate_window_seconds.unwrap_or(60))
+            .bind(rule.aggregate_cooldown_seconds.unwrap_or(300))
+            .bind(
+                rule.aggregate_action
+                    .unwrap_or_else(default_aggregate_action)
+                    .as_str(),
+            )
+            .bind(rule.feed_id)
+            .bind(rule.abuseipdb_categories.unwrap_or_default())
             .map(Self::from_row)
             .fetch_one(pool)
             .await
@@ -384,6 +777,7 @@ impl Rule {
             ("city_name", &params.city_name),
             ("country_name", &params.country_name),
             ("country_code", &params.country_code),
+            ("status_code", &params.status_code),
         ];
         let active_filters: Vec<(&str, String)> = filters
             .into_iter()
@@ -417,6 +811,7 @@ impl Rule {
             ("city_name", &params.city_name),
             ("country_name", &params.country_name),
             ("country_code", &params.country_code),
+            ("status_code", &params.status_code),
         ];
         let active_filters: Vec<(&str, String)> = filters
             .into_iter()
@@ -462,6 +857,57 @@ impl Rule {
             .await
     }
 
+    /// Returns the ids of all rules in evaluation order.
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn read_order(pool: &PgPool) -> Result<Vec<i32>, Error> {
+        let sql = "SELECT id FROM rules ORDER BY weight ASC, id ASC";
+        query(sql)
+            .map(|row: PgRow| row.get("id"))
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Rewrites the weight of every rule following the order of `ids`.
+    ///
+    /// Runs in a single transaction: `ids` must contain every rule exactly
+    /// once, otherwise nothing is changed and `None` is returned. Weights are
+    /// renumbered as multiples of [`WEIGHT_STEP`].
+    ///
+    /// # Errors
+    /// Returns the underlying database error.
+    pub async fn reorder(pool: &PgPool, ids: &[i32]) -> Result<Option<Vec<Self>>, Error> {
+        let mut transaction = pool.begin().await?;
+        let current: Vec<i32> = query("SELECT id FROM rules ORDER BY id FOR UPDATE")
+            .map(|row: PgRow| row.get("id"))
+            .fetch_all(&mut *transaction)
+            .await?;
+        let mut requested = ids.to_vec();
+        requested.sort_unstable();
+        if requested != current {
+            return Ok(None);
+        }
+        let weights: Vec<i32> = (1..)
+            .map(|position| position * WEIGHT_STEP)
+            .take(ids.len())
+            .collect();
+        let sql = "UPDATE rules SET weight = v.weight, updated_at = $3
+            FROM UNNEST($1::int[], $2::int[]) AS v(id, weight)
+            WHERE rules.id = v.id
+            RETURNING rules.*";
+        let mut rules = query(sql)
+            .bind(ids)
+            .bind(&weights)
+            .bind(Utc::now())
+            .map(Self::from_row)
+            .fetch_all(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        rules.sort_by_key(|r| r.weight);
+        Ok(Some(rules))
+    }
+
     pub async fn read(pool: &PgPool, id: i32) -> Result<Self, Error> {
         let sql = "SELECT * FROM rules WHERE id = $1";
         query(sql)
@@ -480,3 +926,87 @@ impl Rule {
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_place_before_and_after() {
+        let order = [1, 2, 3, 4];
+        assert_eq!(
+            place(&order, 4, 2, Placement::Before),
+            Some(vec![1, 4, 2, 3])
+        );
+        assert_eq!(
+            place(&order, 1, 3, Placement::After),
+            Some(vec![2, 3, 1, 4])
+        );
+        assert_eq!(
+            place(&order, 1, 4, Placement::After),
+            Some(vec![2, 3, 4, 1])
+        );
+    }
+
+    #[test]
+    fn test_place_unknown_rule() {
+        let order = [1, 2, 3];
+        assert_eq!(place(&order, 9, 2, Placement::Before), None);
+        assert_eq!(place(&order, 1, 9, Placement::Before), None);
+        assert_eq!(place(&order, 2, 2, Placement::After), None);
+    }
+
+    fn rule(algorithm: &str, status: &str) -> Rule {
+        serde_json::from_value(serde_json::json!({
+            "id": 1, "weight": 10, "action": "allow", "store": true,
+            "ip_address": null, "protocol": null, "fqdn": null, "path": null,
+            "query": null, "city_name": null, "country_name": null,
+            "country_code": null, "rate_limit_enabled": true,
+            "rate_limit_header": null, "rate_limit_algorithm": algorithm,
+            "over_limit_status": status, "max_retry": 5,
+            "find_time_seconds": 600, "ban_time_seconds": 3600,
+            "bantime_increment": false, "bantime_multipliers": [1],
+            "bantime_maxtime_seconds": 3600, "ban_count_decay_days": 30,
+            "ignoreip": [], "webhook": null, "tarpit_seconds": 10,
+            "challenge_url": null, "tag": null, "active": true,
+            "created_at": "2026-01-01T00:00:00Z",
+            "updated_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_over_limit_status_defaults_per_algorithm() {
+        assert_eq!(
+            rule("sliding_window", "auto").over_limit_status(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            rule("gcra", "auto").over_limit_status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            rule("sliding_window", "429").over_limit_status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            rule("gcra", "403").over_limit_status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[test]
+    fn test_limiter_config_takes_max_retry_below_one_as_one() {
+        for max_retry in [0, -1, i32::MIN] {
+            let mut rule = rule("sliding_window", "auto");
+            rule.max_retry = max_retry;
+            assert_eq!(
+                rule.limiter_config(),
+                LimiterConfig::SlidingWindow {
+                    max_retry: 1,
+                    find_time_seconds: 600,
+                }
+            );
+        }
+    }
+}
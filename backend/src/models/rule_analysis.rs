@@ -0,0 +1,500 @@
+//! # Análisis de reglas
+//!
+//! Inspecciona el conjunto de reglas activas ([`CacheRule`]) en el orden en
+//! que las evalúa `shuul()` (la primera coincidencia gana) y detecta:
+//!
+//! - reglas **ensombrecidas**: una regla anterior coincide siempre que ella lo haría,
+//! - reglas **duplicadas**: mismas condiciones y misma acción,
+//! - pares **contradictorios**: mismas condiciones con acciones distintas,
+//! - **rate limiting inalcanzable**: parámetros con los que nunca se banea ni
+//!   se limita.
+//!
+//! La inclusión entre expresiones regulares es indecidible en general, así que
+//! el análisis es conservador: sólo informa cuando puede demostrar la inclusión.
+//! El feed de una regla cuenta como una condición más: una regla limitada a un
+//! feed sólo cubre a las limitadas al mismo feed.
+
+use crate::models::CacheRule;
+use crate::models::rate_limiter::{Gcra, RateLimitAlgorithm};
+use regex::Regex;
+use serde::Serialize;
+
+/// Patterns that match any value (including the empty string).
+const MATCH_ALL_PATTERNS: [&str; 5] = [".*", "^.*", ".*$", "^.*$", "^"];
+
+/// Kind of problem detected in the rule set.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleIssueKind {
+    /// An earlier rule always matches first, so this rule never applies.
+    Shadowed,
+    /// Same conditions and same decision as an earlier rule.
+    Duplicate,
+    /// Same conditions as an earlier rule but a different decision.
+    Contradictory,
+    /// Rate limiting is enabled but can never ban anyone.
+    IneffectiveRateLimit,
+}
+
+/// A single finding of the analysis.
+#[derive(Debug, Serialize, Clone)]
+pub struct RuleIssue {
+    pub kind: RuleIssueKind,
+    /// Rule affected by the issue.
+    pub rule_id: i32,
+    /// Earlier rule responsible for the issue, if any.
+    pub related_rule_id: Option<i32>,
+    pub message: String,
+}
+
+/// Analyzes `rules` in evaluation order and returns every issue found.
+#[must_use]
+pub fn analyze(rules: &[CacheRule]) -> Vec<RuleIssue> {
+    let mut issues = Vec::new();
+    for (index, rule) in rules.iter().enumerate() {
+        if let Some(issue) = find_earlier_match(&rules[..index], rule) {
+            issues.push(issue);
+        }
+        if let Some(reason) = ineffective_rate_limit(rule) {
+            issues.push(RuleIssue {
+                kind: RuleIssueKind::IneffectiveRateLimit,
+                rule_id: rule.rule.id,
+                related_rule_id: None,
+                message: reason,
+            });
+        }
+    }
+    issues
+}
+
+/// Looks for the first earlier rule that makes `rule` unreachable.
+///
+/// Non-terminal rules (log-only, rate-limit-only) never stop evaluation, so
+/// they can only be reported as duplicates of each other.
+fn find_earlier_match(earlier: &[CacheRule], rule: &CacheRule) -> Option<RuleIssue> {
+    // Rules with a status condition only see access-log lines and the rest
+    // only see forward-auth requests, so the two sets never interact.
+    for previous in earlier
+        .iter()
+        .filter(|previous| previous.status_code.is_some() == rule.status_code.is_some())
+    {
+        if !previous.rule.action.is_terminal() {
+            if previous.rule.action == rule.rule.action && same_conditions(previous, rule) {
+                return Some(RuleIssue {
+                    kind: RuleIssueKind::Duplicate,
+                    rule_id: rule.rule.id,
+                    related_rule_id: Some(previous.rule.id),
+                    message: format!(
+                        "Rule {} has the same conditions and action as rule {}",
+                        rule.rule.id, previous.rule.id
+                    ),
+                });
+            }
+            continue;
+        }
+        if same_conditions(previous, rule) {
+            let (kind, message) = if previous.rule.action == rule.rule.action {
+                (
+                    RuleIssueKind::Duplicate,
+                    format!(
+                        "Rule {} has the same conditions and action as rule {}",
+                        rule.rule.id, previous.rule.id
+                    ),
+                )
+            } else {
+                (
+                    RuleIssueKind::Contradictory,
+                    format!(
+                        "Rule {} ({}) has the same conditions as rule {} ({}); rule {} wins",
+                        rule.rule.id,
+                        rule.rule.action.as_str(),
+                        previous.rule.id,
+                        previous.rule.action.as_str(),
+                        previous.rule.id
+                    ),
+                )
+            };
+            return Some(RuleIssue {
+                kind,
+                rule_id: rule.rule.id,
+                related_rule_id: Some(previous.rule.id),
+                message,
+            });
+        }
+        if covers(previous, rule) {
+            return Some(RuleIssue {
+                kind: RuleIssueKind::Shadowed,
+                rule_id: rule.rule.id,
+                related_rule_id: Some(previous.rule.id),
+                message: format!(
+                    "Rule {} (weight {}) always matches before rule {} (weight {})",
+                    previous.rule.id, previous.rule.weight, rule.rule.id, rule.rule.weight
+                ),
+            });
+        }
+    }
+    None
+}
+
+/// Returns the compiled condition regexes of a rule, in a fixed field order.
+const fn conditions(rule: &CacheRule) -> [Option<&Regex>; 9] {
+    [
+        rule.ip_address.as_ref(),
+        rule.protocol.as_ref(),
+        rule.fqdn.as_ref(),
+        rule.path.as_ref(),
+        rule.query.as_ref(),
+        rule.city_name.as_ref(),
+        rule.country_name.as_ref(),
+        rule.country_code.as_ref(),
+        rule.status_code.as_ref(),
+    ]
+}
+
+/// `true` if both rules have exactly the same condition patterns and feed.
+fn same_conditions(a: &CacheRule, b: &CacheRule) -> bool {
+    a.rule.feed_id == b.rule.feed_id
+        && conditions(a)
+            .iter()
+            .zip(conditions(b).iter())
+            .all(|(x, y)| normalize(*x) == normalize(*y))
+}
+
+/// Treats match-all patterns as an absent condition.
+fn normalize(regex: Option<&Regex>) -> Option<&str> {
+    regex
+        .map(Regex::as_str)
+        .filter(|p| !MATCH_ALL_PATTERNS.contains(p))
+}
+
+/// `true` if every request matched by `later` is also matched by `earlier`.
+///
+/// A rule gated on a feed only matches IPs in it, so it only covers rules
+/// gated on the same feed.
+fn covers(earlier: &CacheRule, later: &CacheRule) -> bool {
+    earlier
+        .rule
+        .feed_id
+        .is_none_or(|feed_id| later.rule.feed_id == Some(feed_id))
+        && conditions(earlier)
+            .iter()
+            .zip(conditions(later).iter())
+            .all(|(e, l)| field_covers(*e, *l))
+}
+
+/// `true` if the language of `later` is provably included in `earlier`.
+///
+/// A missing (or uncompilable) condition matches everything, mirroring
+/// [`CacheRule::matches`].
+fn field_covers(earlier: Option<&Regex>, later: Option<&Regex>) -> bool {
+    let Some(earlier_pattern) = normalize(earlier) else {
+        return true;
+    };
+    let Some(later_pattern) = normalize(later) else {
+        return false;
+    };
+    if earlier_pattern == later_pattern {
+        return true;
+    }
+    let Some(earlier) = earlier else {
+        return false;
+    };
+    // `^literal$` matches exactly one value: test it against the earlier regex.
+    if let Some(value) = later_pattern
+        .strip_suffix('$')
+        .and_then(literal_prefix)
+        .filter(|(_, complete)| *complete)
+        .map(|(value, _)| value)
+    {
+        return earlier.is_match(&value);
+    }
+    // Every value matched by `later` starts with its literal prefix; if the
+    // earlier pattern is a plain literal (optionally anchored at the start),
+    // inclusion of that prefix is enough.
+    let Some((prefix, _)) = literal_prefix(later_pattern) else {
+        return false;
+    };
+    match unescape_literal(earlier_pattern.strip_prefix('^').unwrap_or(earlier_pattern)) {
+        Some(literal) if earlier_pattern.starts_with('^') => prefix.starts_with(&literal),
+        Some(literal) => prefix.contains(&literal),
+        None => false,
+    }
+}
+
+/// Extracts the literal prefix every match of an anchored (`^...`) pattern
+/// must start with. The boolean is `true` when the whole pattern is literal.
+///
+/// Returns `None` for unanchored patterns or patterns with a top-level
+/// alternation, where no common prefix can be guaranteed.
+fn literal_prefix(pattern: &str) -> Option<(String, bool)> {
+    let body = pattern.strip_prefix('^')?;
+    if has_top_level_alternation(body) {
+        return None;
+    }
+    let mut prefix = String::new();
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        let literal = match c {
+            '\\' => match chars.next() {
+                Some(escaped) if !escaped.is_ascii_alphanumeric() => escaped,
+                _ => return Some((prefix, false)),
+            },
+            '.' | '?' | '*' | '{' | '+' | '(' | ')' | '[' | ']' | '|' | '$' | '^' => {
+                return Some((prefix, false));
+            },
+            c => c,
+        };
+        if matches!(chars.peek(), Some('?' | '*' | '{')) {
+            // The character is optional or repeated: it is not guaranteed.
+            return Some((prefix, false));
+        }
+        prefix.push(literal);
+    }
+    Some((prefix, true))
+}
+
+/// Unescapes a pattern made only of literal characters, or returns `None`.
+fn unescape_literal(pattern: &str) -> Option<String> {
+    let mut literal = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) if !escaped.is_ascii_alphanumeric() => literal.push(escaped),
+                _ => return None,
+            },
+            '.' | '?' | '*' | '+' | '{' | '}' | '(' | ')' | '[' | ']' | '|' | '$' | '^' => {
+                return None;
+            },
+            c => literal.push(c),
+        }
+    }
+    Some(literal)
+}
+
+/// `true` if `pattern` has a `|` outside of any group or character class.
+fn has_top_level_alternation(pattern: &str) -> bool {
+    let mut depth = 0usize;
+    let mut in_class = false;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            },
+            '[' if !in_class => in_class = true,
+            ']' if in_class => in_class = false,
+            '(' if !in_class => depth += 1,
+            ')' if !in_class => depth = depth.saturating_sub(1),
+            '|' if !in_class && depth == 0 => return true,
+            _ => {},
+        }
+    }
+    false
+}
+
+/// Explains why a rate-limited rule can never produce a ban, if it can't.
+fn ineffective_rate_limit(rule: &CacheRule) -> Option<String> {
+    let rule = &rule.rule;
+    if !rule.rate_limit_enabled {
+        return None;
+    }
+    if rule.rate_limit_algorithm == RateLimitAlgorithm::Gcra {
+        return if Gcra::new(rule.rate_limit_rate, 1).is_none() {
+            Some(format!(
+                "rate_limit_rate is {}; GCRA needs a rate between {} and {} per second",
+                rule.rate_limit_rate,
+                Gcra::MIN_RATE,
+                Gcra::MAX_RATE
+            ))
+        } else if rule.rate_limit_burst < 1 {
+            Some(format!(
+                "rate_limit_burst is {}; it must be at least 1",
+                rule.rate_limit_burst
+            ))
+        } else {
+            None
+        };
+    }
+    if rule.find_time_seconds <= 0 && rule.max_retry > 1 {
+        Some(format!(
+            "find_time_seconds is {}; {} hits can never fall inside the window",
+            rule.find_time_seconds, rule.max_retry
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Rule;
+    use serde_json::json;
+    use std::slice;
+
+    fn rule(id: i32, action: &str, path: Option<&str>, country_code: Option<&str>) -> CacheRule {
+        let rule: Rule = serde_json::from_value(json!({
+            "id": id,
+            "weight": id * 10,
+            "action": action,
+            "store": true,
+            "ip_address": null,
+            "protocol": null,
+            "fqdn": null,
+            "path": path,
+            "query": null,
+            "city_name": null,
+            "country_name": null,
+            "country_code": country_code,
+            "rate_limit_enabled": false,
+            "max_retry": 5,
+            "find_time_seconds": 600,
+            "ban_time_seconds": 3600,
+            "bantime_increment": false,
+            "bantime_multipliers": [1, 2, 4, 8],
+            "bantime_maxtime_seconds": 604_800,
+            "ban_count_decay_days": 30,
+            "ignoreip": [],
+            "webhook": null,
+            "tarpit_seconds": 10,
+            "challenge_url": null,
+            "tag": null,
+            "active": true,
+            "created_at": "2026-01-01T00:00:00Z",
+            "updated_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap();
+        CacheRule::from_rule(rule)
+    }
+
+    fn kinds(issues: &[RuleIssue]) -> Vec<(RuleIssueKind, i32, Option<i32>)> {
+        issues
+            .iter()
+            .map(|i| (i.kind, i.rule_id, i.related_rule_id))
+            .collect()
+    }
+
+    #[test]
+    fn test_catch_all_shadows_later_rules() {
+        let rules = vec![
+            rule(1, "allow", None, None),
+            rule(2, "deny", Some(r"^/admin"), None),
+        ];
+        assert_eq!(
+            kinds(&analyze(&rules)),
+            vec![(RuleIssueKind::Shadowed, 2, Some(1))]
+        );
+    }
+
+    #[test]
+    fn test_prefix_shadows_longer_prefix() {
+        let rules = vec![
+            rule(1, "deny", Some(r"^/wp-admin"), None),
+            rule(2, "deny", Some(r"^/wp-admin/admin-ajax\.php$"), None),
+            rule(3, "deny", Some(r"^/wp-admins?/x"), None),
+            rule(4, "deny", Some(r"^/wp-(admin|login)"), None),
+        ];
+        assert_eq!(
+            kinds(&analyze(&rules)),
+            vec![
+                (RuleIssueKind::Shadowed, 2, Some(1)),
+                (RuleIssueKind::Shadowed, 3, Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exact_literal_checked_against_regex() {
+        let rules = vec![
+            rule(1, "deny", Some(r"\.(env|git)$"), None),
+            rule(2, "deny", Some(r"^/app/\.env$"), None),
+            rule(3, "deny", Some(r"^/app/\.envrc$"), None),
+        ];
+        assert_eq!(
+            kinds(&analyze(&rules)),
+            vec![(RuleIssueKind::Shadowed, 2, Some(1))]
+        );
+    }
+
+    #[test]
+    fn test_narrower_earlier_rule_does_not_shadow() {
+        let rules = vec![
+            rule(1, "deny", Some(r"^/admin"), Some("CN")),
+            rule(2, "deny", Some(r"^/admin"), None),
+        ];
+        assert!(analyze(&rules).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_and_contradictory() {
+        let rules = vec![
+            rule(1, "deny", Some(r"^/login"), Some("RU")),
+            rule(2, "deny", Some(r"^/login"), Some("RU")),
+            rule(3, "allow", Some(r"^/login"), Some("RU")),
+        ];
+        assert_eq!(
+            kinds(&analyze(&rules)),
+            vec![
+                (RuleIssueKind::Duplicate, 2, Some(1)),
+                (RuleIssueKind::Contradictory, 3, Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_non_terminal_rules_do_not_shadow() {
+        let rules = vec![
+            rule(1, "log_only", None, None),
+            rule(2, "log_only", None, None),
+            rule(3, "deny", Some(r"^/admin"), None),
+        ];
+        assert_eq!(
+            kinds(&analyze(&rules)),
+            vec![(RuleIssueKind::Duplicate, 2, Some(1))]
+        );
+    }
+
+    #[test]
+    fn test_feed_gated_rule_only_covers_same_feed() {
+        let mut gated = rule(1, "deny", None, None);
+        gated.rule.feed_id = Some(7);
+        let ungated = rule(2, "deny", Some(r"^/admin"), None);
+        let mut same_feed = rule(3, "deny", Some(r"^/admin"), None);
+        same_feed.rule.feed_id = Some(7);
+        let mut other_feed = rule(4, "deny", None, None);
+        other_feed.rule.feed_id = Some(8);
+        assert_eq!(
+            kinds(&analyze(&[gated, ungated, same_feed, other_feed])),
+            vec![(RuleIssueKind::Shadowed, 3, Some(1))]
+        );
+    }
+
+    #[test]
+    fn test_ineffective_rate_limit() {
+        let mut limited = rule(1, "allow", Some(r"^/login"), None);
+        limited.rule.rate_limit_enabled = true;
+        limited.rule.max_retry = 3;
+        limited.rule.find_time_seconds = 0;
+        let issues = analyze(slice::from_ref(&limited));
+        assert_eq!(
+            kinds(&issues),
+            vec![(RuleIssueKind::IneffectiveRateLimit, 1, None)]
+        );
+        // A single hit bans whatever the window
+        limited.rule.max_retry = 0;
+        assert!(analyze(&[limited]).is_empty());
+    }
+
+    #[test]
+    fn test_literal_prefix() {
+        assert_eq!(
+            literal_prefix(r"^/a\.b$"),
+            Some(("/a.b".to_string(), false))
+        );
+        assert_eq!(literal_prefix(r"^/a\.b"), Some(("/a.b".to_string(), true)));
+        assert_eq!(literal_prefix(r"^/ab?"), Some(("/a".to_string(), false)));
+        assert_eq!(literal_prefix(r"^/a|/b"), None);
+        assert_eq!(literal_prefix(r"/a"), None);
+    }
+}
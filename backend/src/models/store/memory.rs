@@ -0,0 +1,479 @@
+//! # Almacén en memoria
+//!
+//! Implementación por defecto de [`StateStore`]: un [`RateLimiter`] por regla
+//! y un [`BanManager`], ambos locales al proceso.
+//!
+//! Con [`MemoryStore::with_persistence`] cada ban y desban se escribe también
+//! en la tabla `bans`, junto con el nivel de escalado del sujeto en
+//! `ban_escalations`, y [`MemoryStore::restore`] recarga ambos al arrancar.
+//!
+//! Si el [`BanManager`] agrega rangos, el ban de una IP puede traer consigo el
+//! de su /24 o /64.
+
+use super::{LimiterStats, StateStore, StoreFuture, TrackedKey};
+use crate::models::ban_escalation::BanEscalation;
+use crate::models::ban_manager::{BanInfo, BanManager, BanUpdate};
+use crate::models::ban_record::BanRecord;
+use crate::models::error::AppError;
+use crate::models::rate_limiter::{
+    AggregateCounter, AggregateLimit, AggregateStatus, LimiterConfig, RateLimitStatus, RateLimiter,
+};
+use crate::models::subject::Subject;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::future::ready;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tracing::{debug, error, info, warn};
+
+/// Process-local rate limiters and bans.
+#[derive(Debug)]
+pub struct MemoryStore {
+    rate_limiters: Mutex<HashMap<i32, RateLimiter>>, // rule_id → RateLimiter
+    aggregates: Mutex<HashMap<i32, AggregateCounter>>, // rule_id → AggregateCounter
+    ban_manager: Mutex<BanManager>,
+    /// Maximum subjects tracked by each rate limiter (LRU eviction beyond).
+    max_keys: usize,
+    /// Evictions already reported by the cleanup task.
+    reported_evictions: AtomicU64,
+    /// Database the bans are persisted to, if any.
+    pool: Option<PgPool>,
+    /// Escalation decay of the ban manager, readable without its lock.
+    ban_count_decay_days: i64,
+}
+
+impl MemoryStore {
+    #[must_use]
+    pub fn new(ban_manager: BanManager, max_keys: usize) -> Self {
+        Self {
+            ban_count_decay_days: ban_manager.ban_count_decay_days(),
+            rate_limiters: Mutex::new(HashMap::new()),
+            aggregates: Mutex::new(HashMap::new()),
+            ban_manager: Mutex::new(ban_manager),
+            max_keys,
+            reported_evictions: AtomicU64::new(0),
+            pool: None,
+        }
+    }
+
+    /// Persist bans and unbans to the `bans` table of `pool`.
+    #[must_use]
+    pub fn with_persistence(mut self, pool: PgPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Loads the escalation counters that haven't decayed and the bans still
+    /// in force. Returns how many bans were restored.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the table can't be read.
+    pub async fn restore(&self) -> Result<usize, AppError> {
+        let Some(pool) = &self.pool else {
+            return Ok(0);
+        };
+        let escalations = BanEscalation::read_current(pool, self.ban_count_decay_days).await?;
+        let records = BanRecord::read_active(pool).await?;
+        let restored = records.len();
+        self.with_bans(|ban_manager| {
+            for escalation in escalations {
+                ban_manager.restore_escalation(
+                    escalation.subject,
+                    escalation.level,
+                    escalation.last_ban_at,
+                );
+            }
+            for record in records {
+                ban_manager.restore(record.subject, record.ban);
+            }
+        })?;
+        Ok(restored)
+    }
+
+    fn with_limiters<T>(
+        &self,
+        f: impl FnOnce(&mut HashMap<i32, RateLimiter>) -> T,
+    ) -> Result<T, AppError> {
+        let mut rate_limiters = self
+            .rate_limiters
+            .lock()
+            .map_err(|_| AppError::CachePoisoned)?;
+        Ok(f(&mut rate_limiters))
+    }
+
+    fn with_aggregates<T>(
+        &self,
+        f: impl FnOnce(&mut HashMap<i32, AggregateCounter>) -> T,
+    ) -> Result<T, AppError> {
+        let mut aggregates = self
+            .aggregates
+            .lock()
+            .map_err(|_| AppError::CachePoisoned)?;
+        Ok(f(&mut aggregates))
+    }
+
+    fn with_bans<T>(&self, f: impl FnOnce(&mut BanManager) -> T) -> Result<T, AppError> {
+        let mut ban_manager = self
+            .ban_manager
+            .lock()
+            .map_err(|_| AppError::CachePoisoned)?;
+        Ok(f(&mut ban_manager))
+    }
+}
+
+/// Stores a ban and the escalation counter of its subject. The ban is
+/// enforced even if it can't be persisted, so errors are only logged.
+async fn persist_ban(
+    pool: &PgPool,
+    subject: &Subject,
+    ban: &BanInfo,
+    escalation: Option<(u32, DateTime<Utc>)>,
+    triggered_by: &[i32],
+) {
+    if let Err(e) = BanRecord::create(pool, subject, ban, triggered_by).await {
+        error!("Cannot persist ban of {}: {}", subject, e);
+    }
+    if let Some((level, last_ban_at)) = escalation
+        && let Err(e) = BanEscalation::save(pool, subject, level, last_ban_at).await
+    {
+        error!("Cannot persist escalation of {}: {}", subject, e);
+    }
+}
+
+/// Wall-clock time of `instant`.
+fn wall_clock(instant: Instant) -> DateTime<Utc> {
+    chrono::Duration::from_std(instant.elapsed())
+        .map_or_else(|_| Utc::now(), |ago| Utc::now() - ago)
+}
+
+impl StateStore for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn record_hit<'a>(
+        &'a self,
+        rule_id: i32,
+        config: LimiterConfig,
+        subject: &'a Subject,
+    ) -> StoreFuture<'a, RateLimitStatus> {
+        Box::pin(ready(self.with_limiters(|rate_limiters| {
+            let rl = rate_limiters
+                .entry(rule_id)
+                .or_insert_with(|| RateLimiter::with_config(config).with_max_keys(self.max_keys));
+            // The rule may have been edited since the limiter was created.
+            rl.reconfigure(config);
+            rl.hit(subject.clone())
+        })))
+    }
+
+    fn reconfigure(&self, rule_id: i32, config: LimiterConfig) -> StoreFuture<'_, ()> {
+        Box::pin(ready(self.with_limiters(|rate_limiters| {
+            if let Some(rl) = rate_limiters.get_mut(&rule_id) {
+                rl.reconfigure(config);
+            }
+        })))
+    }
+
+    fn record_aggregate(
+        &self,
+        rule_id: i32,
+        limit: AggregateLimit,
+    ) -> StoreFuture<'_, AggregateStatus> {
+        Box::pin(ready(self.with_aggregates(|aggregates| {
+            aggregates
+                .entry(rule_id)
+                .or_insert_with(|| AggregateCounter::new(limit))
+                .record(limit, Instant::now())
+        })))
+    }
+
+    fn aggregate_status(
+        &self,
+        rule_id: i32,
+        limit: AggregateLimit,
+    ) -> StoreFuture<'_, AggregateStatus> {
+        Box::pin(ready(self.with_aggregates(|aggregates| {
+            aggregates.get(&rule_id).map_or(
+                AggregateStatus {
+                    hits: 0,
+                    cooldown_remaining: None,
+                },
+                |counter| counter.status(limit, Instant::now()),
+            )
+        })))
+    }
+
+    fn counters<'a>(
+        &'a self,
+        rule_id: i32,
+        _config: LimiterConfig,
+        subject: Option<&'a Subject>,
+    ) -> StoreFuture<'a, Vec<TrackedKey>> {
+        Box::pin(ready(self.with_limiters(|rate_limiters| {
+            rate_limiters
+                .get(&rule_id)
+                .map(|rl| {
+                    rl.peek(subject)
+                        .into_iter()
+                        .map(|key| {
+                            TrackedKey::new(
+                                rule_id,
+                                key.subject.to_string(),
+                                key.status,
+                                key.window_start.map(wall_clock),
+                            )
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        })))
+    }
+
+    fn reset_counters<'a>(
+        &'a self,
+        rule_id: Option<i32>,
+        subject: Option<&'a Subject>,
+    ) -> StoreFuture<'a, u64> {
+        Box::pin(ready(
+            self.with_limiters(|rate_limiters| {
+                let mut reset = 0;
+                for (_, rl) in rate_limiters
+                    .iter_mut()
+                    .filter(|(id, _)| rule_id.is_none_or(|rule_id| rule_id == **id))
+                {
+                    if let Some(subject) = subject {
+                        reset += u64::from(rl.remove(subject));
+                    } else {
+                        reset += rl.len() as u64;
+                        rl.clear();
+                    }
+                }
+                reset
+            })
+            .and_then(|reset| {
+                if let (Some(rule_id), None) = (rule_id, subject) {
+                    self.with_aggregates(|aggregates| aggregates.remove(&rule_id))?;
+                }
+                Ok(reset)
+            }),
+        ))
+    }
+
+    fn limiter_stats(&self) -> StoreFuture<'_, Vec<LimiterStats>> {
+        Box::pin(ready(self.with_limiters(|rate_limiters| {
+            rate_limiters
+                .iter()
+                .map(|(rule_id, rl)| LimiterStats {
+                    rule_id: *rule_id,
+                    tracked_keys: rl.len(),
+                    max_keys: Some(rl.max_keys()),
+                    evictions: rl.evictions(),
+                })
+                .collect()
+        })))
+    }
+
+    fn ban_count_decay_days(&self) -> i64 {
+        self.ban_count_decay_days
+    }
+
+    fn ban(
+        &self,
+        subject: Subject,
+        rule_id: Option<i32>,
+        reason: String,
+        duration: Option<i64>,
+        triggered_by: Vec<i32>,
+    ) -> StoreFuture<'_, BanInfo> {
+        Box::pin(async move {
+            let mut issued = self.with_bans(|ban_manager| {
+                let ban = ban_manager
+                    .ban(subject.clone(), rule_id, reason, duration)
+                    .clone();
+                let mut issued = vec![(subject.clone(), ban)];
+                if let Subject::Ip(ip) = subject
+                    && let Some(range) = ban_manager.aggregate_range(ip)
+                    && let Some(aggregation) = ban_manager.range_aggregation()
+                {
+                    info!("Banning range {}: {}", range, aggregation.reason(range));
+                    let range_ban = ban_manager
+                        .ban(range, rule_id, aggregation.reason(range), None)
+                        .clone();
+                    issued.push((Subject::Range(range), range_ban));
+                }
+                issued
+                    .into_iter()
+                    .map(|(subject, ban)| {
+                        let escalation = ban_manager.escalation(&subject);
+                        (subject, ban, escalation)
+                    })
+                    .collect::<Vec<_>>()
+            })?;
+            if let Some(pool) = &self.pool {
+                // Only the requested ban is a recidive one, never its range.
+                let mut triggered_by = triggered_by.as_slice();
+                for (subject, ban, escalation) in &issued {
+                    persist_ban(pool, subject, ban, *escalation, triggered_by).await;
+                    triggered_by = &[];
+                }
+            }
+            Ok(issued.swap_remove(0).1)
+        })
+    }
+
+    fn unban<'a>(&'a self, subject: &'a Subject, rule_id: Option<i32>) -> StoreFuture<'a, bool> {
+        Box::pin(async move {
+            let mut removed =
+                self.with_bans(|ban_manager| ban_manager.unban(subject.clone(), rule_id))?;
+            if let Some(pool) = &self.pool {
+                removed |= BanRecord::expire(pool, subject, rule_id).await? > 0;
+            }
+            Ok(removed)
+        })
+    }
+
+    fn update_ban<'a>(
+        &'a self,
+        subject: &'a Subject,
+        rule_id: Option<i32>,
+        update: BanUpdate,
+    ) -> StoreFuture<'a, Option<BanInfo>> {
+        Box::pin(async move {
+            let updated =
+                self.with_bans(|ban_manager| ban_manager.update(subject, rule_id, &update))?;
+            if updated.is_some()
+                && let Some(pool) = &self.pool
+            {
+                BanRecord::update(pool, subject, rule_id, &update).await?;
+            }
+            Ok(updated)
+        })
+    }
+
+    fn reset_escalation<'a>(&'a self, subject: &'a Subject) -> StoreFuture<'a, bool> {
+        Box::pin(async move {
+            let mut reset = self.with_bans(|ban_manager| ban_manager.reset_escalation(subject))?;
+            if let Some(pool) = &self.pool {
+                reset |= BanEscalation::delete(pool, subject).await? > 0;
+            }
+            Ok(reset)
+        })
+    }
+
+    fn find_ban<'a>(&'a self, subjects: &'a [Subject]) -> StoreFuture<'a, Option<BanInfo>> {
+        Box::pin(ready(self.with_bans(|ban_manager| {
+            ban_manager.find_ban(subjects).cloned()
+        })))
+    }
+
+    fn active_bans(&self) -> StoreFuture<'_, Vec<(Subject, BanInfo)>> {
+        Box::pin(ready(self.with_bans(|ban_manager| {
+            ban_manager
+                .active_bans()
+                .into_iter()
+                .map(|(subject, ban)| (subject.clone(), ban.clone()))
+                .collect()
+        })))
+    }
+
+    fn cleanup(&self) -> StoreFuture<'_, ()> {
+        Box::pin(async move {
+            self.with_bans(|ban_manager| {
+                let before = ban_manager.active_count();
+                ban_manager.cleanup_expired();
+                let after = ban_manager.active_count();
+                if before != after {
+                    debug!("Ban cleanup: {} → {} active bans", before, after);
+                }
+            })
+            .and_then(|()| {
+                self.with_limiters(|rate_limiters| {
+                    let total_evictions = |rate_limiters: &HashMap<i32, RateLimiter>| -> u64 {
+                        rate_limiters.values().map(RateLimiter::evictions).sum()
+                    };
+                    let evictions = total_evictions(rate_limiters);
+                    let reported = self.reported_evictions.load(Ordering::Relaxed);
+                    if evictions > reported {
+                        warn!(
+                            "Rate limiters full: {} keys evicted since the last cleanup",
+                            evictions - reported
+                        );
+                    }
+                    rate_limiters.retain(|_, rl| {
+                        rl.cleanup_expired();
+                        !rl.is_empty()
+                    });
+                    // Dropped limiters take their eviction count with them.
+                    self.reported_evictions
+                        .store(total_evictions(rate_limiters), Ordering::Relaxed);
+                })
+            })?;
+            if let Some(pool) = &self.pool {
+                let expired = BanRecord::expire_elapsed(pool).await?;
+                let decayed =
+                    BanEscalation::delete_decayed(pool, self.ban_count_decay_days).await?;
+                if expired > 0 || decayed > 0 {
+                    debug!(
+                        "Marked {} stored bans as expired, removed {} escalations",
+                        expired, decayed
+                    );
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    #[tokio::test]
+    async fn test_hits_over_limit_then_ban_is_found() {
+        let store = MemoryStore::new(BanManager::new(60, false, vec![1], 3600, 30), 10);
+        let config = LimiterConfig::SlidingWindow {
+            max_retry: 2,
+            find_time_seconds: 60,
+        };
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let subject = Subject::from(ip);
+
+        let first = store.record_hit(1, config, &subject).await.unwrap();
+        assert_eq!((first.remaining, first.exceeded), (1, false));
+        assert!(
+            store
+                .record_hit(1, config, &subject)
+                .await
+                .unwrap()
+                .exceeded
+        );
+        store
+            .ban(
+                subject.clone(),
+                Some(1),
+                "test".to_string(),
+                None,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+
+        let found = store
+            .find_ban(std::slice::from_ref(&subject))
+            .await
+            .unwrap();
+        assert_eq!(found.map(|ban| ban.rule_id), Some(Some(1)));
+        let counters = store.counters(1, config, None).await.unwrap();
+        assert_eq!((counters.len(), counters[0].hits), (1, 2));
+        assert_eq!(store.reset_counters(None, Some(&subject)).await.unwrap(), 1);
+        assert!(store.counters(1, config, None).await.unwrap().is_empty());
+
+        assert!(store.unban(&subject, Some(1)).await.unwrap());
+        assert!(store.find_ban(&[subject]).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,193 @@
+//! # Almacén de estado compartido
+//!
+//! [`StateStore`] abstrae dónde viven los contadores de rate limiting y los
+//! bans, incluidos los contadores de los límites agregados de cada regla:
+//!
+//! - [`MemoryStore`]: en memoria del proceso (por defecto). Rápido, pero cada
+//!   réplica cuenta por separado.
+//! - [`PostgresStore`]: en la base de datos, compartido entre réplicas, de modo que
+//!   N réplicas tras un balanceador no multiplican por N los intentos
+//!   permitidos.
+//!
+//! Se elige con la variable de entorno `STATE_STORE` (`memory` o `postgres`).
+
+mod memory;
+mod postgres;
+
+pub use memory::MemoryStore;
+pub use postgres::PostgresStore;
+
+use crate::models::ban_manager::{BanInfo, BanUpdate};
+use crate::models::error::AppError;
+use crate::models::rate_limiter::{
+    AggregateLimit, AggregateStatus, LimiterConfig, RateLimitStatus,
+};
+use crate::models::subject::Subject;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+
+/// Boxed future returned by [`StateStore`] methods.
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, AppError>> + Send + 'a>>;
+
+/// Size of the rate limiter of one rule.
+#[derive(Debug, Clone, Serialize)]
+pub struct LimiterStats {
+    pub rule_id: i32,
+    pub tracked_keys: usize,
+    /// Maximum tracked keys, if the store caps them.
+    pub max_keys: Option<usize>,
+    pub evictions: u64,
+}
+
+/// Counters of one subject in one rule's rate limiter.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackedKey {
+    pub rule_id: i32,
+    pub subject: String,
+    /// Hits counted against the limit (tokens used, for GCRA).
+    pub hits: u32,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Oldest hit still in the window (sliding window only).
+    pub window_start: Option<DateTime<Utc>>,
+    /// Seconds until the counters are back to zero.
+    pub reset_seconds: u64,
+}
+
+impl TrackedKey {
+    #[must_use]
+    pub const fn new(
+        rule_id: i32,
+        subject: String,
+        status: RateLimitStatus,
+        window_start: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            rule_id,
+            subject,
+            hits: status.limit - status.remaining,
+            limit: status.limit,
+            remaining: status.remaining,
+            window_start,
+            reset_seconds: status.reset.as_secs(),
+        }
+    }
+}
+
+/// Storage for rate-limit counters and bans.
+pub trait StateStore: Send + Sync {
+    /// Name of the backend, for logs.
+    fn name(&self) -> &'static str;
+
+    /// Counts a hit of `subject` on rule `rule_id` and returns its quota
+    /// under the limit described by `config`.
+    fn record_hit<'a>(
+        &'a self,
+        rule_id: i32,
+        config: LimiterConfig,
+        subject: &'a Subject,
+    ) -> StoreFuture<'a, RateLimitStatus>;
+
+    /// Applies new limits to the counters of a rule that was edited.
+    fn reconfigure(&self, rule_id: i32, config: LimiterConfig) -> StoreFuture<'_, ()>;
+
+    /// Counts a match of rule `rule_id` towards its aggregate `limit` and
+    /// returns the rule's aggregate state.
+    fn record_aggregate(
+        &self,
+        rule_id: i32,
+        limit: AggregateLimit,
+    ) -> StoreFuture<'_, AggregateStatus>;
+
+    /// Aggregate state of rule `rule_id`, without counting a match.
+    fn aggregate_status(
+        &self,
+        rule_id: i32,
+        limit: AggregateLimit,
+    ) -> StoreFuture<'_, AggregateStatus>;
+
+    /// Counters of rule `rule_id`, limited by `config`: every tracked key,
+    /// or only `subject`'s.
+    fn counters<'a>(
+        &'a self,
+        rule_id: i32,
+        config: LimiterConfig,
+        subject: Option<&'a Subject>,
+    ) -> StoreFuture<'a, Vec<TrackedKey>>;
+
+    /// Forgets the counters of `subject`, of rule `rule_id` (including its
+    /// aggregate limit), or of `subject` in rule `rule_id`. Returns the
+    /// number of keys reset.
+    fn reset_counters<'a>(
+        &'a self,
+        rule_id: Option<i32>,
+        subject: Option<&'a Subject>,
+    ) -> StoreFuture<'a, u64>;
+
+    /// Size of each rule's rate limiter.
+    fn limiter_stats(&self) -> StoreFuture<'_, Vec<LimiterStats>>;
+
+    /// Days after which a subject's escalation level decays.
+    fn ban_count_decay_days(&self) -> i64;
+
+    /// Bans `subject`. Without `duration`, the duration follows the
+    /// escalation policy. `triggered_by` holds the ids of the bans behind a
+    /// recidive ban and is empty for any other ban.
+    fn ban(
+        &self,
+        subject: Subject,
+        rule_id: Option<i32>,
+        reason: String,
+        duration: Option<i64>,
+        triggered_by: Vec<i32>,
+    ) -> StoreFuture<'_, BanInfo>;
+
+    /// Lifts the bans of `subject` issued by `rule_id`. Returns `true` if
+    /// any ban was lifted.
+    fn unban<'a>(&'a self, subject: &'a Subject, rule_id: Option<i32>) -> StoreFuture<'a, bool>;
+
+    /// Applies `update` to the active bans of `subject` issued by `rule_id`.
+    /// Returns the updated ban, or `None` if there was none.
+    fn update_ban<'a>(
+        &'a self,
+        subject: &'a Subject,
+        rule_id: Option<i32>,
+        update: BanUpdate,
+    ) -> StoreFuture<'a, Option<BanInfo>>;
+
+    /// Forgets the escalation level of `subject`. Returns `true` if it had
+    /// one.
+    fn reset_escalation<'a>(&'a self, subject: &'a Subject) -> StoreFuture<'a, bool>;
+
+    /// First active ban among `subjects`; IP subjects also match banned ranges.
+    fn find_ban<'a>(&'a self, subjects: &'a [Subject]) -> StoreFuture<'a, Option<BanInfo>>;
+
+    /// Every active ban.
+    fn active_bans(&self) -> StoreFuture<'_, Vec<(Subject, BanInfo)>>;
+
+    /// Drops expired bans and counters.
+    fn cleanup(&self) -> StoreFuture<'_, ()>;
+}
+
+/// Which [`StateStore`] implementation to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StoreKind {
+    #[default]
+    Memory,
+    Postgres,
+}
+
+impl FromStr for StoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "memory" => Ok(Self::Memory),
+            "postgres" => Ok(Self::Postgres),
+            other => Err(format!("Unknown state store: {other}")),
+        }
+    }
+}
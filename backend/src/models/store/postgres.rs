@@ -0,0 +1,506 @@
+//! # Almacén en Postgres
+//!
+//! Implementación de [`StateStore`] compartida entre réplicas:
+//!
+//! - Ventana deslizante: una fila por intento en `rate_limit_hits` (unlogged);
+//!   se cuentan los intentos de la ventana en la misma sentencia que inserta,
+//!   bajo un advisory lock por regla y sujeto para que dos réplicas no cuenten
+//!   a la vez sin ver el intento de la otra.
+//! - GCRA: un `tat` por sujeto en `rate_limit_arrivals`, actualizado con un
+//!   único upsert condicional, así que dos réplicas no pueden gastar el mismo
+//!   token.
+//! - Límites agregados: una fila por coincidencia en `rule_aggregate_hits` y el
+//!   fin del enfriamiento de cada regla en `rule_cooldowns`.
+//! - Bans: la tabla `bans`, a través de [`BanRecord`]. Los rangos se buscan
+//!   con el operador `>>=` de `inet`. El nivel de escalado vive en
+//!   `ban_escalations` ([`BanEscalation`]) y se incrementa con un único upsert.
+//!   La agregación de rangos cuenta las IPs distintas baneadas en la tabla.
+
+use super::{LimiterStats, StateStore, StoreFuture, TrackedKey};
+use crate::models::ban_escalation::BanEscalation;
+use crate::models::ban_manager::{BanInfo, BanManager, BanUpdate};
+use crate::models::ban_record::BanRecord;
+use crate::models::rate_limiter::{
+    AggregateLimit, AggregateStatus, LimiterConfig, RateLimitStatus,
+};
+use crate::models::subject::{IpRange, Subject};
+use sqlx::{PgPool, Row, postgres::PgRow, query};
+use std::net::IpAddr;
+use std::slice;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// Rate limits and bans shared through the database.
+#[derive(Debug)]
+pub struct PostgresStore {
+    pool: PgPool,
+    /// Escalation policy; its in-memory bans are not used.
+    policy: BanManager,
+}
+
+impl PostgresStore {
+    #[must_use]
+    pub const fn new(pool: PgPool, policy: BanManager) -> Self {
+        Self { pool, policy }
+    }
+
+    /// Stores a ban of `subject` at its next escalation level.
+    async fn insert_ban(
+        &self,
+        subject: &Subject,
+        rule_id: Option<i32>,
+        reason: String,
+        duration: Option<i64>,
+        triggered_by: &[i32],
+    ) -> Result<BanInfo, sqlx::Error> {
+        let escalation_level =
+            BanEscalation::escalate(&self.pool, subject, self.policy.ban_count_decay_days())
+                .await?;
+        let ban = BanInfo {
+            banned_at: Instant::now(),
+            ban_duration_seconds: duration
+                .unwrap_or_else(|| self.policy.calculate_ban_duration(escalation_level)),
+            escalation_level,
+            rule_id,
+            reason,
+        };
+        BanRecord::create(&self.pool, subject, &ban, triggered_by).await?;
+        debug!("Stored ban of {} (level {})", subject, escalation_level);
+        Ok(ban)
+    }
+
+    /// Bans the /24 or /64 of `ip` if enough distinct IPs in it were banned
+    /// recently and it isn't banned yet.
+    async fn aggregate_range(&self, ip: IpAddr, rule_id: Option<i32>) -> Result<(), sqlx::Error> {
+        let Some(aggregation) = self.policy.range_aggregation() else {
+            return Ok(());
+        };
+        let range = IpRange::default_prefix(ip);
+        let range_subject = Subject::Range(range);
+        if BanRecord::find_active(&self.pool, slice::from_ref(&range_subject))
+            .await?
+            .is_some()
+        {
+            return Ok(());
+        }
+        let banned = BanRecord::distinct_ips_banned(&self.pool, &range, aggregation.window).await?;
+        if usize::try_from(banned).unwrap_or(usize::MAX) >= aggregation.threshold {
+            info!("Banning range {}: {} IPs banned", range, banned);
+            self.insert_ban(
+                &range_subject,
+                rule_id,
+                aggregation.reason(range),
+                None,
+                &[],
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Time left in the cool-down of rule `rule_id`, if any.
+    async fn cooldown_remaining(&self, rule_id: i32) -> Result<Option<Duration>, sqlx::Error> {
+        query(
+            "SELECT EXTRACT(EPOCH FROM until - NOW())::float8 FROM rule_cooldowns
+            WHERE rule_id = $1 AND until > NOW()",
+        )
+        .bind(rule_id)
+        .map(|row: PgRow| seconds(row.get(0)))
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Matches of rule `rule_id` within `window`.
+    async fn aggregate_hits(&self, rule_id: i32, window: Duration) -> Result<u32, sqlx::Error> {
+        let hits: i64 = query(
+            "SELECT COUNT(*) FROM rule_aggregate_hits
+            WHERE rule_id = $1 AND hit_at > NOW() - make_interval(secs => $2)",
+        )
+        .bind(rule_id)
+        .bind(window.as_secs_f64())
+        .map(|row: PgRow| row.get(0))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(u32::try_from(hits).unwrap_or(u32::MAX))
+    }
+}
+
+/// Duration from a number of seconds computed by the database; negative
+/// values are clamped to zero.
+fn seconds(value: f64) -> Duration {
+    Duration::try_from_secs_f64(value.max(0.0)).unwrap_or_default()
+}
+
+impl StateStore for PostgresStore {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn record_hit<'a>(
+        &'a self,
+        rule_id: i32,
+        config: LimiterConfig,
+        subject: &'a Subject,
+    ) -> StoreFuture<'a, RateLimitStatus> {
+        Box::pin(async move {
+            match config {
+                LimiterConfig::SlidingWindow {
+                    max_retry,
+                    find_time_seconds,
+                } => {
+                    let subject = subject.to_string();
+                    // Concurrent hits of the key wait here, so each one counts
+                    // the committed hits of the others.
+                    let mut transaction = self.pool.begin().await?;
+                    query("SELECT pg_advisory_xact_lock($1, hashtext($2))")
+                        .bind(rule_id)
+                        .bind(&subject)
+                        .execute(&mut *transaction)
+                        .await?;
+                    let sql = "WITH hit AS (
+                            INSERT INTO rate_limit_hits (rule_id, subject) VALUES ($1, $2)
+                        )
+                        SELECT COUNT(*) + 1,
+                            EXTRACT(EPOCH FROM COALESCE(MIN(hit_at), NOW())
+                                + make_interval(secs => $3::bigint) - NOW())::float8
+                        FROM rate_limit_hits
+                        WHERE rule_id = $1 AND subject = $2
+                            AND hit_at > NOW() - make_interval(secs => $3::bigint)";
+                    let (hits, reset): (i64, f64) = query(sql)
+                        .bind(rule_id)
+                        .bind(&subject)
+                        .bind(find_time_seconds)
+                        .map(|row: PgRow| (row.get(0), row.get(1)))
+                        .fetch_one(&mut *transaction)
+                        .await?;
+                    transaction.commit().await?;
+                    Ok(RateLimitStatus {
+                        limit: max_retry,
+                        remaining: u32::try_from(i64::from(max_retry) - hits).unwrap_or(0),
+                        reset: seconds(reset),
+                        exceeded: hits >= i64::from(max_retry),
+                    })
+                },
+                LimiterConfig::Gcra(gcra) => {
+                    // No row returned: the conditional update was rejected.
+                    let sql = "INSERT INTO rate_limit_arrivals (rule_id, subject, tat)
+                        VALUES ($1, $2, NOW() + make_interval(secs => $3))
+                        ON CONFLICT (rule_id, subject) DO UPDATE
+                        SET tat = GREATEST(rate_limit_arrivals.tat, NOW())
+                            + make_interval(secs => $3)
+                        WHERE GREATEST(rate_limit_arrivals.tat, NOW())
+                            + make_interval(secs => $3) <= NOW() + make_interval(secs => $4)
+                        RETURNING EXTRACT(EPOCH FROM tat - NOW())::float8";
+                    let allowed: Option<f64> = query(sql)
+                        .bind(rule_id)
+                        .bind(subject.to_string())
+                        .bind(gcra.emission_interval().as_secs_f64())
+                        .bind(gcra.allowance().as_secs_f64())
+                        .map(|row: PgRow| row.get(0))
+                        .fetch_optional(&self.pool)
+                        .await?;
+                    if let Some(ahead) = allowed {
+                        return Ok(gcra.status(seconds(ahead)));
+                    }
+                    let ahead: f64 = query(
+                        "SELECT EXTRACT(EPOCH FROM tat - NOW())::float8 FROM rate_limit_arrivals
+                        WHERE rule_id = $1 AND subject = $2",
+                    )
+                    .bind(rule_id)
+                    .bind(subject.to_string())
+                    .map(|row: PgRow| row.get(0))
+                    .fetch_one(&self.pool)
+                    .await?;
+                    let wait = (seconds(ahead) + gcra.emission_interval())
+                        .saturating_sub(gcra.allowance());
+                    Ok(gcra.throttled(wait))
+                },
+            }
+        })
+    }
+
+    fn reconfigure(&self, _rule_id: i32, _config: LimiterConfig) -> StoreFuture<'_, ()> {
+        // Limits travel with every hit; the stored counters stay valid.
+        Box::pin(async { Ok(()) })
+    }
+
+    fn record_aggregate(
+        &self,
+        rule_id: i32,
+        limit: AggregateLimit,
+    ) -> StoreFuture<'_, AggregateStatus> {
+        Box::pin(async move {
+            // Matches during the cool-down are not counted.
+            if let Some(remaining) = self.cooldown_remaining(rule_id).await? {
+                return Ok(AggregateStatus {
+                    hits: self.aggregate_hits(rule_id, limit.window).await?,
+                    cooldown_remaining: Some(remaining),
+                });
+            }
+            query("INSERT INTO rule_aggregate_hits (rule_id) VALUES ($1)")
+                .bind(rule_id)
+                .execute(&self.pool)
+                .await?;
+            let hits = self.aggregate_hits(rule_id, limit.window).await?;
+            if hits < limit.limit {
+                return Ok(AggregateStatus {
+                    hits,
+                    cooldown_remaining: None,
+                });
+            }
+            query(
+                "INSERT INTO rule_cooldowns (rule_id, until)
+                VALUES ($1, NOW() + make_interval(secs => $2))
+                ON CONFLICT (rule_id) DO UPDATE SET until = EXCLUDED.until",
+            )
+            .bind(rule_id)
+            .bind(limit.cooldown.as_secs_f64())
+            .execute(&self.pool)
+            .await?;
+            debug!("Rule {} over its aggregate limit, cooling down", rule_id);
+            Ok(AggregateStatus {
+                hits,
+                cooldown_remaining: Some(limit.cooldown),
+            })
+        })
+    }
+
+    fn aggregate_status(
+        &self,
+        rule_id: i32,
+        limit: AggregateLimit,
+    ) -> StoreFuture<'_, AggregateStatus> {
+        Box::pin(async move {
+            Ok(AggregateStatus {
+                hits: self.aggregate_hits(rule_id, limit.window).await?,
+                cooldown_remaining: self.cooldown_remaining(rule_id).await?,
+            })
+        })
+    }
+
+    fn counters<'a>(
+        &'a self,
+        rule_id: i32,
+        config: LimiterConfig,
+        subject: Option<&'a Subject>,
+    ) -> StoreFuture<'a, Vec<TrackedKey>> {
+        Box::pin(async move {
+            let subject = subject.map(ToString::to_string);
+            match config {
+                LimiterConfig::SlidingWindow {
+                    max_retry,
+                    find_time_seconds,
+                } => {
+                    let sql = "SELECT subject, COUNT(*) AS hits, MIN(hit_at) AS window_start,
+                            EXTRACT(EPOCH FROM MIN(hit_at)
+                                + make_interval(secs => $2::bigint) - NOW())::float8 AS reset
+                        FROM rate_limit_hits
+                        WHERE rule_id = $1
+                            AND hit_at > NOW() - make_interval(secs => $2::bigint)
+                            AND ($3::text IS NULL OR subject = $3)
+                        GROUP BY subject";
+                    Ok(query(sql)
+                        .bind(rule_id)
+                        .bind(find_time_seconds)
+                        .bind(subject)
+                        .map(|row: PgRow| {
+                            let hits = u32::try_from(row.get::<i64, _>("hits")).unwrap_or(u32::MAX);
+                            let status = RateLimitStatus {
+                                limit: max_retry,
+                                remaining: max_retry.saturating_sub(hits),
+                                reset: seconds(row.get("reset")),
+                                exceeded: hits >= max_retry,
+                            };
+                            TrackedKey::new(
+                                rule_id,
+                                row.get("subject"),
+                                status,
+                                row.get("window_start"),
+                            )
+                        })
+                        .fetch_all(&self.pool)
+                        .await?)
+                },
+                LimiterConfig::Gcra(gcra) => {
+                    let sql = "SELECT subject, EXTRACT(EPOCH FROM tat - NOW())::float8 AS ahead
+                        FROM rate_limit_arrivals
+                        WHERE rule_id = $1 AND tat > NOW()
+                            AND ($2::text IS NULL OR subject = $2)";
+                    Ok(query(sql)
+                        .bind(rule_id)
+                        .bind(subject)
+                        .map(|row: PgRow| {
+                            let status = gcra.status(seconds(row.get("ahead")));
+                            TrackedKey::new(rule_id, row.get("subject"), status, None)
+                        })
+                        .fetch_all(&self.pool)
+                        .await?)
+                },
+            }
+        })
+    }
+
+    fn reset_counters<'a>(
+        &'a self,
+        rule_id: Option<i32>,
+        subject: Option<&'a Subject>,
+    ) -> StoreFuture<'a, u64> {
+        Box::pin(async move {
+            let subject = subject.map(ToString::to_string);
+            let mut reset = 0;
+            for table in ["rate_limit_hits", "rate_limit_arrivals"] {
+                let sql = format!(
+                    "WITH deleted AS (
+                        DELETE FROM {table}
+                        WHERE ($1::int IS NULL OR rule_id = $1)
+                            AND ($2::text IS NULL OR subject = $2)
+                        RETURNING rule_id, subject
+                    )
+                    SELECT COUNT(DISTINCT (rule_id, subject)) FROM deleted"
+                );
+                let keys: i64 = query(&sql)
+                    .bind(rule_id)
+                    .bind(&subject)
+                    .map(|row: PgRow| row.get(0))
+                    .fetch_one(&self.pool)
+                    .await?;
+                reset += u64::try_from(keys).unwrap_or_default();
+            }
+            if let (Some(rule_id), None) = (rule_id, &subject) {
+                for table in ["rule_aggregate_hits", "rule_cooldowns"] {
+                    query(&format!("DELETE FROM {table} WHERE rule_id = $1"))
+                        .bind(rule_id)
+                        .execute(&self.pool)
+                        .await?;
+                }
+            }
+            Ok(reset)
+        })
+    }
+
+    fn limiter_stats(&self) -> StoreFuture<'_, Vec<LimiterStats>> {
+        Box::pin(async move {
+            let sql = "SELECT rule_id, COUNT(DISTINCT subject) AS tracked_keys
+                FROM rate_limit_hits GROUP BY rule_id
+                UNION ALL
+                SELECT rule_id, COUNT(*) AS tracked_keys
+                FROM rate_limit_arrivals GROUP BY rule_id";
+            Ok(query(sql)
+                .map(|row: PgRow| LimiterStats {
+                    rule_id: row.get("rule_id"),
+                    tracked_keys: usize::try_from(row.get::<i64, _>("tracked_keys"))
+                        .unwrap_or_default(),
+                    max_keys: None,
+                    evictions: 0,
+                })
+                .fetch_all(&self.pool)
+                .await?)
+        })
+    }
+
+    fn ban_count_decay_days(&self) -> i64 {
+        self.policy.ban_count_decay_days()
+    }
+
+    fn ban(
+        &self,
+        subject: Subject,
+        rule_id: Option<i32>,
+        reason: String,
+        duration: Option<i64>,
+        triggered_by: Vec<i32>,
+    ) -> StoreFuture<'_, BanInfo> {
+        Box::pin(async move {
+            let ban = self
+                .insert_ban(&subject, rule_id, reason, duration, &triggered_by)
+                .await?;
+            if let Subject::Ip(ip) = subject {
+                self.aggregate_range(ip, rule_id).await?;
+            }
+            Ok(ban)
+        })
+    }
+
+    fn unban<'a>(&'a self, subject: &'a Subject, rule_id: Option<i32>) -> StoreFuture<'a, bool> {
+        Box::pin(async move { Ok(BanRecord::expire(&self.pool, subject, rule_id).await? > 0) })
+    }
+
+    fn update_ban<'a>(
+        &'a self,
+        subject: &'a Subject,
+        rule_id: Option<i32>,
+        update: BanUpdate,
+    ) -> StoreFuture<'a, Option<BanInfo>> {
+        Box::pin(async move {
+            Ok(BanRecord::update(&self.pool, subject, rule_id, &update)
+                .await?
+                .pop()
+                .map(|record| record.ban))
+        })
+    }
+
+    fn reset_escalation<'a>(&'a self, subject: &'a Subject) -> StoreFuture<'a, bool> {
+        Box::pin(async move { Ok(BanEscalation::delete(&self.pool, subject).await? > 0) })
+    }
+
+    fn find_ban<'a>(&'a self, subjects: &'a [Subject]) -> StoreFuture<'a, Option<BanInfo>> {
+        Box::pin(async move {
+            Ok(BanRecord::find_active(&self.pool, subjects)
+                .await?
+                .map(|record| record.ban))
+        })
+    }
+
+    fn active_bans(&self) -> StoreFuture<'_, Vec<(Subject, BanInfo)>> {
+        Box::pin(async move {
+            Ok(BanRecord::read_active(&self.pool)
+                .await?
+                .into_iter()
+                .map(|record| (record.subject, record.ban))
+                .collect())
+        })
+    }
+
+    fn cleanup(&self) -> StoreFuture<'_, ()> {
+        Box::pin(async move {
+            let hits = query(
+                "DELETE FROM rate_limit_hits h
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM rules r
+                    WHERE r.id = h.rule_id
+                        AND h.hit_at > NOW() - make_interval(secs => r.find_time_seconds)
+                )",
+            )
+            .execute(&self.pool)
+            .await?;
+            let arrivals = query("DELETE FROM rate_limit_arrivals WHERE tat < NOW()")
+                .execute(&self.pool)
+                .await?;
+            query(
+                "DELETE FROM rule_aggregate_hits h
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM rules r
+                    WHERE r.id = h.rule_id
+                        AND h.hit_at > NOW() - make_interval(secs => r.aggregate_window_seconds)
+                )",
+            )
+            .execute(&self.pool)
+            .await?;
+            query("DELETE FROM rule_cooldowns WHERE until < NOW()")
+                .execute(&self.pool)
+                .await?;
+            let bans = BanRecord::expire_elapsed(&self.pool).await?;
+            let escalations =
+                BanEscalation::delete_decayed(&self.pool, self.policy.ban_count_decay_days())
+                    .await?;
+            debug!(
+                "State cleanup: {} hits, {} arrivals, {} escalations removed, {} bans expired",
+                hits.rows_affected(),
+                arrivals.rows_affected(),
+                escalations,
+                bans
+            );
+            Ok(())
+        })
+    }
+}
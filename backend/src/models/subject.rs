@@ -0,0 +1,335 @@
+//! # Sujetos de rate limiting y bans
+//!
+//! [`Subject`] identifica la entidad que cuenta un [`RateLimiter`](super::RateLimiter)
+//! y a la que apunta un ban: una IP, un rango de red, un sistema autónomo (ASN),
+//! un país o una clave opaca (cabecera, host + IP, combinaciones...).
+//!
+//! [`IpRange`] representa un rango CIDR (`1.2.3.0/24`, `2001:db8::/64`) e
+//! [`IpSet`] un conjunto de rangos indexado por longitud de prefijo, de modo
+//! que comprobar una IP cuesta una búsqueda por longitud en uso.
+
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// A CIDR network range. The address is always stored masked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Builds the range of length `prefix_len` containing `ip`.
+    ///
+    /// Returns `None` if `prefix_len` is longer than the address.
+    #[must_use]
+    pub fn new(ip: IpAddr, prefix_len: u8) -> Option<Self> {
+        let network = match ip {
+            IpAddr::V4(v4) => {
+                if prefix_len > 32 {
+                    return None;
+                }
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(prefix_len))
+                    .unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+            },
+            IpAddr::V6(v6) => {
+                if prefix_len > 128 {
+                    return None;
+                }
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(prefix_len))
+                    .unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+            },
+        };
+        Some(Self {
+            network,
+            prefix_len,
+        })
+    }
+
+    /// The /24 (IPv4) or /64 (IPv6) network of `ip`.
+    #[must_use]
+    pub fn default_prefix(ip: IpAddr) -> Self {
+        let prefix_len = if ip.is_ipv4() { 24 } else { 64 };
+        // Both lengths are valid for their address family.
+        Self::new(ip, prefix_len).unwrap_or(Self {
+            network: ip,
+            prefix_len,
+        })
+    }
+
+    #[must_use]
+    pub const fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    #[must_use]
+    pub const fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// `true` if `ip` belongs to this range.
+    #[must_use]
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        ip.is_ipv4() == self.network.is_ipv4()
+            && Self::new(*ip, self.prefix_len).is_some_and(|r| r.network == self.network)
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ip, len) = s
+            .split_once('/')
+            .ok_or_else(|| format!("Invalid CIDR range: {s}"))?;
+        let ip: IpAddr = ip
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid CIDR address: {s}"))?;
+        let len: u8 = len
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid CIDR prefix length: {s}"))?;
+        Self::new(ip, len).ok_or_else(|| format!("Invalid CIDR prefix length: {s}"))
+    }
+}
+
+/// CIDR ranges, each with a value, indexed by prefix length.
+#[derive(Debug, Clone)]
+pub struct IpSet<T> {
+    ranges: HashMap<IpRange, T>,
+    /// Prefix lengths in use, as `(is_ipv4, prefix_len)`
+    lengths: BTreeSet<(bool, u8)>,
+}
+
+impl<T> Default for IpSet<T> {
+    fn default() -> Self {
+        Self {
+            ranges: HashMap::new(),
+            lengths: BTreeSet::new(),
+        }
+    }
+}
+
+impl<T> IpSet<T> {
+    pub fn insert(&mut self, range: IpRange, value: T) {
+        self.lengths
+            .insert((range.network().is_ipv4(), range.prefix_len()));
+        self.ranges.insert(range, value);
+    }
+
+    /// Values of the ranges containing `ip`, longest prefix first.
+    pub fn matches<'a>(&'a self, ip: &'a IpAddr) -> impl Iterator<Item = &'a T> + 'a {
+        self.lengths
+            .iter()
+            .rev()
+            .filter(|(is_ipv4, _)| *is_ipv4 == ip.is_ipv4())
+            .filter_map(|(_, len)| IpRange::new(*ip, *len))
+            .filter_map(|range| self.ranges.get(&range))
+    }
+
+    /// `true` if a range contains `ip`.
+    #[must_use]
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.matches(ip).next().is_some()
+    }
+
+}
+
+impl FromIterator<IpRange> for IpSet<()> {
+    fn from_iter<I: IntoIterator<Item = IpRange>>(iter: I) -> Self {
+        let mut set = Self::default();
+        for range in iter {
+            set.insert(range, ());
+        }
+        set
+    }
+}
+
+/// Entity counted by a rate limiter and targeted by a ban.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Subject {
+    /// A single client address.
+    Ip(IpAddr),
+    /// A network range (subnet).
+    Range(IpRange),
+    /// An autonomous system number.
+    Asn(u32),
+    /// A country, by its upper-case ISO 3166-1 alpha-2 code.
+    Country(String),
+    /// Any other rate-limit key (header value, host + IP, composite...).
+    Key(String),
+}
+
+impl Subject {
+    /// Short name of the subject type, used in API responses.
+    #[must_use]
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Ip(_) => "ip",
+            Self::Range(_) => "range",
+            Self::Asn(_) => "asn",
+            Self::Country(_) => "country",
+            Self::Key(_) => "key",
+        }
+    }
+}
+
+impl From<IpAddr> for Subject {
+    fn from(ip: IpAddr) -> Self {
+        Self::Ip(ip)
+    }
+}
+
+impl From<&IpAddr> for Subject {
+    fn from(ip: &IpAddr) -> Self {
+        Self::Ip(*ip)
+    }
+}
+
+impl From<IpRange> for Subject {
+    fn from(range: IpRange) -> Self {
+        Self::Range(range)
+    }
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(ip) => write!(f, "{ip}"),
+            Self::Range(range) => write!(f, "{range}"),
+            Self::Asn(asn) => write!(f, "AS{asn}"),
+            Self::Country(code) => write!(f, "country:{code}"),
+            Self::Key(key) => write!(f, "key:{key}"),
+        }
+    }
+}
+
+/// Parses the [`Display`](fmt::Display) form: `1.2.3.4`, `1.2.3.0/24`,
+/// `AS64500`, `country:ES` or `key:<value>`.
+impl FromStr for Subject {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(key) = s.strip_prefix("key:") {
+            return Ok(Self::Key(key.to_string()));
+        }
+        if let Some(code) = s.strip_prefix("country:") {
+            return if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+                Ok(Self::Country(code.to_ascii_uppercase()))
+            } else {
+                Err(format!("Invalid country code: {s}"))
+            };
+        }
+        if let Some(asn) = s.strip_prefix("AS").or_else(|| s.strip_prefix("as")) {
+            return asn
+                .parse()
+                .map(Self::Asn)
+                .map_err(|_| format!("Invalid ASN: {s}"));
+        }
+        if s.contains('/') {
+            return s.parse().map(Self::Range);
+        }
+        s.parse()
+            .map(Self::Ip)
+            .map_err(|_| format!("Invalid ban subject: {s}"))
+    }
+}
+
+impl Serialize for Subject {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// Parses a pasted list of subjects separated by whitespace or commas. Text
+/// after `#` on a line is a comment. Returns the subjects, without
+/// duplicates, and the entries that didn't parse.
+#[must_use]
+pub fn parse_subjects(text: &str) -> (Vec<Subject>, Vec<String>) {
+    let mut subjects: Vec<Subject> = Vec::new();
+    let mut invalid = Vec::new();
+    for entry in text
+        .lines()
+        .map(|line| line.split_once('#').map_or(line, |(entry, _)| entry))
+        .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+        .filter(|entry| !entry.is_empty())
+    {
+        match entry.parse() {
+            Ok(subject) if !subjects.contains(&subject) => subjects.push(subject),
+            Ok(_) => {},
+            Err(_) => invalid.push(entry.to_string()),
+        }
+    }
+    (subjects, invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_masks_and_contains() {
+        let ip: IpAddr = "192.168.10.77".parse().unwrap();
+        let range = IpRange::default_prefix(ip);
+        assert_eq!(range.to_string(), "192.168.10.0/24");
+        assert!(range.contains(&"192.168.10.1".parse().unwrap()));
+        assert!(!range.contains(&"192.168.11.1".parse().unwrap()));
+
+        let ip: IpAddr = "2001:db8:1:2:3:4:5:6".parse().unwrap();
+        let range = IpRange::default_prefix(ip);
+        assert_eq!(range.to_string(), "2001:db8:1:2::/64");
+        assert!(range.contains(&"2001:db8:1:2::1".parse().unwrap()));
+        assert!(!range.contains(&"192.168.10.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_subject_round_trip() {
+        for s in [
+            "10.0.0.1",
+            "10.0.0.0/8",
+            "2001:db8::/48",
+            "AS64500",
+            "country:ES",
+            "key:abc",
+        ] {
+            let subject: Subject = s.parse().unwrap();
+            assert_eq!(subject.to_string(), s);
+        }
+        assert!("10.0.0.0/33".parse::<Subject>().is_err());
+        assert!("not-an-ip".parse::<Subject>().is_err());
+        assert_eq!(
+            "country:fr".parse::<Subject>(),
+            Ok(Subject::Country("FR".to_string()))
+        );
+        assert!("country:FRA".parse::<Subject>().is_err());
+    }
+
+    #[test]
+    fn test_parse_subjects() {
+        let (subjects, invalid) =
+            parse_subjects("10.0.0.1, 10.0.0.0/8\n# comment\nAS64500 10.0.0.1 # again\nbogus,");
+        assert_eq!(
+            subjects.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["10.0.0.1", "10.0.0.0/8", "AS64500"]
+        );
+        assert_eq!(invalid, ["bogus"]);
+    }
+}
@@ -3,6 +3,7 @@
 //! Catálogo de reglas recomendadas para servicios populares.
 //! Los usuarios pueden aplicar estas plantillas desde el frontend.
 
+use crate::models::RuleAction;
 use serde::Serialize;
 
 /// A preconfigured rule template.
@@ -15,7 +16,7 @@ pub struct RuleTemplate {
     pub path: Option<String>,
     pub query: Option<String>,
     pub country_code: Option<String>,
-    pub allow: bool,
+    pub action: RuleAction,
     pub store: bool,
     pub rate_limit_enabled: bool,
     pub max_retry: Option<i32>,
@@ -42,7 +43,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/wp-login\.php".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -62,7 +63,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/xmlrpc\.php".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -82,7 +83,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/wp-admin".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(10),
@@ -102,7 +103,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/wp-json/".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(30),
@@ -122,7 +123,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/wp-content/uploads/.*\.(php|phtml|php5)$".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -146,7 +147,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/administrator/".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -166,7 +167,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/index\.php\?option=com_users".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -190,7 +191,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(user|users?/login)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -210,7 +211,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/install\.php".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -234,7 +235,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/_debugbar".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -254,7 +255,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/telescope".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -278,7 +279,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(phpmyadmin|pma|mysql|phpPgAdmin)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -298,7 +299,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(cpanel|whm|webmail|:2083|:2087)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -318,7 +319,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(webmin|usermin)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -338,7 +339,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(adminer|adminer-)".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -362,7 +363,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(api|auth)/(login|signin|token)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -382,7 +383,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(api|auth)/(register|signup)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -402,7 +403,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(api|auth)/(reset|forgot|recover)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -422,7 +423,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(graphql|api/graphql)".into()),
             query: Some(r"(__schema|__type)".into()),
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -446,7 +447,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(manager|host-manager)/".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -466,7 +467,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(jenkins|ci)/".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -486,7 +487,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/\.git".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -510,7 +511,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"\.(env|bak|sql|dump|config|yml|yaml|json|log|ini)$".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -530,7 +531,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(vendor|node_modules|storage|cache|logs|tmp|\.git|\.env)".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -550,7 +551,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"\.\./".into()),
             query: Some(r"\.\./".into()),
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -570,7 +571,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: None,
             query: Some(r"(\bunion\b.*\bselect\b|'\s+or\s+|=|\bexec\b|\bxp_cmdshell\b)".into()),
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -590,7 +591,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: None,
             query: Some(r"(<script|<iframe|onerror=|onload=|javascript:)".into()),
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(10),
@@ -610,7 +611,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"(phpinfo|info\.php)".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -634,7 +635,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: None,
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -654,7 +655,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: None,
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(60),
@@ -678,7 +679,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: None,
             query: None,
             country_code: Some(r"^(RU|CN|KP|IR)$".into()),
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -698,7 +699,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: None,
             query: None,
             country_code: Some(r"^(RU|CN|KP|IR|VN|UA|BR|IN)$".into()),
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -721,7 +722,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(admin|index\.php/admin)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -741,7 +742,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/rest/".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(30),
@@ -761,7 +762,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(checkout|onepage|checkout/cart)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(20),
@@ -781,7 +782,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(admin|admin[0-9]+|administration)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -801,7 +802,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/install/".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -821,7 +822,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(login|index\.php/login)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -841,7 +842,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/s/".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(30),
@@ -861,7 +862,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/api\.php".into()),
             query: Some(r"action=(edit|delete|move|import|upload)".into()),
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(10),
@@ -881,7 +882,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(ghost|ghost/api|admin)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -905,7 +906,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(login|grafana/login)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -925,7 +926,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(kibana|app/kibana)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -945,7 +946,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/api/v1/namespaces/kubernetes-dashboard".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -965,7 +966,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/portainer/api/auth".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -985,7 +986,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(dashboard|api/http)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -1005,7 +1006,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(pgadmin|pgadmin4)/login".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -1025,7 +1026,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(auth/login|api/auth)".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -1049,7 +1050,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: None,
             query: Some(r"\$\{|jndi:|\$\{jndi:(ldap|rmi|dns|http)".into()),
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -1069,7 +1070,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"/(docker\.sock|var/run/docker\.sock)".into()),
             query: None,
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: false,
             max_retry: None,
@@ -1089,7 +1090,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: None,
             query: Some(r"169\.254\.169\.254|instance-data|imds".into()),
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(3),
@@ -1109,7 +1110,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: None,
             query: Some(r"(\$\{[^}]+\}|\{\{[^}]+\}\}|#\{[^}]+\}|<%=|<%#)".into()),
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -1129,7 +1130,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: None,
             query: Some(r"(;\s*(id|whoami|cat|rm|wget|curl|bash|sh|nc)\b|\|\s*(id|whoami|cat|rm)\b|\$\(cat|\`cat)".into()),
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -1149,7 +1150,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: None,
             query: Some(r"(redirect|return|next|url|to|dest|target|goto)=https?://".into()),
             country_code: None,
-            allow: false,
+            action: RuleAction::Deny,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(10),
@@ -1173,7 +1174,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(roundcube|webmail|mail)/".into()),
             query: Some(r"_task=login".into()),
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -1193,7 +1194,7 @@ pub fn all_templates() -> Vec<RuleTemplate> {
             path: Some(r"^/(rainloop|snappymail)/".into()),
             query: None,
             country_code: None,
-            allow: true,
+            action: RuleAction::Allow,
             store: true,
             rate_limit_enabled: true,
             max_retry: Some(5),
@@ -45,7 +45,7 @@ async fn test_ban_unban_cycle() {
     assert!(bm.is_banned(&ip).is_none());
     bm.ban(ip, Some(1), "test".to_string(), None);
     assert!(bm.is_banned(&ip).is_some());
-    bm.unban(&ip, Some(1));
+    bm.unban(ip, Some(1));
     assert!(bm.is_banned(&ip).is_none());
 }
 
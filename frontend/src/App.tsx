@@ -24,6 +24,9 @@ const RequestsPage = lazy(() => import('@/pages/admin/requests_page'));
 const ChartsPage = lazy(() => import('@/pages/admin/charts_page'));
 const UsersPage = lazy(() => import('@/pages/admin/users_page'));
 const BansPage = lazy(() => import('@/pages/admin/bans_page'));
+const IpListsPage = lazy(() => import('@/pages/admin/ip_lists_page'));
+const FeedsPage = lazy(() => import('@/pages/admin/feeds_page'));
+const BouncersPage = lazy(() => import('@/pages/admin/bouncers_page'));
 const TemplatesPage = lazy(() => import('@/pages/admin/templates_page'));
 const SettingsPage = lazy(() => import('@/pages/admin/settings_page'));
 
@@ -77,6 +80,9 @@ export default class App extends React.Component {
                                                     <Route path="charts" element={<ChartsPage />} />
                                                     <Route path="users" element={<UsersPage />} />
                                                     <Route path="bans" element={<BansPage />} />
+                                                    <Route path="ip-lists" element={<IpListsPage />} />
+                                                    <Route path="feeds" element={<FeedsPage />} />
+                                                    <Route path="bouncers" element={<BouncersPage />} />
                                                     <Route path="templates" element={<TemplatesPage />} />
                                                     <Route path="settings" element={<SettingsPage />} />
                                                 </Route>
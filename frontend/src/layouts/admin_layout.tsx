@@ -10,6 +10,9 @@ import {
     LogoutOutlined,
     UserOutlined,
     StopOutlined,
+    SafetyOutlined,
+    CloudDownloadOutlined,
+    ApiOutlined,
     AppstoreOutlined,
     SettingOutlined,
 } from '@ant-design/icons';
@@ -49,6 +52,9 @@ const navigations:{[key: string]: string}  = {
     6: "/admin/bans",
     7: "/admin/templates",
     8: "/admin/settings",
+    9: "/admin/ip-lists",
+    10: "/admin/feeds",
+    11: "/admin/bouncers",
 }
 
 const items: MenuItem[] = [
@@ -58,6 +64,9 @@ const items: MenuItem[] = [
     getItem('Charts', '4', <PieChartOutlined />),
     getItem('Users', '5', <UserOutlined />),
     getItem('Bans', '6', <StopOutlined />),
+    getItem('IP Lists', '9', <SafetyOutlined />),
+    getItem('Feeds', '10', <CloudDownloadOutlined />),
+    getItem('Bouncers', '11', <ApiOutlined />),
     getItem('Templates', '7', <AppstoreOutlined />),
     getItem('Settings', '8', <SettingOutlined />),
 ];
@@ -1,12 +1,19 @@
+export type BanKind = 'ip' | 'range' | 'asn' | 'country' | 'key';
+
 export default interface Ban {
-    id: number;
+    id: string;
     ip_address: string;
+    kind: BanKind;
     rule_id?: number;
-    jail_name: string;
-    banned_at: string;
+    reason: string;
     ban_duration_seconds: number;
     escalation_level: number;
-    reason?: string;
-    expired: boolean;
-    created_at: string;
-}
\ No newline at end of file
+    time_remaining_seconds?: number;
+    permanent: boolean;
+    banned_at: string;
+    country_code?: string;
+    country_name?: string;
+    city_name?: string;
+    asn?: number;
+    as_org?: string;
+}
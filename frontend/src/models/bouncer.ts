@@ -0,0 +1,6 @@
+export default interface Bouncer {
+    id: number;
+    name: string;
+    last_pull_at?: string;
+    created_at: string;
+}
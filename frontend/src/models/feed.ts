@@ -0,0 +1,17 @@
+export type FeedFormat = 'plain' | 'spamhaus_drop' | 'firehol_netset' | 'csv';
+
+export default interface Feed {
+    id: number;
+    name: string;
+    source: string;
+    format: FeedFormat;
+    csv_column: number;
+    refresh_seconds: number;
+    deny: boolean;
+    active: boolean;
+    last_fetch_at?: string;
+    last_status?: string;
+    entry_count: number;
+    created_at: string;
+    updated_at: string;
+}
@@ -0,0 +1,8 @@
+export default interface IpListEntry {
+    id: number;
+    list: 'allow' | 'deny';
+    address: string;
+    comment?: string;
+    expires_at?: string;
+    created_at: string;
+}
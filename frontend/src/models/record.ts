@@ -1,3 +1,5 @@
+export type Decision = 'allow' | 'deny' | 'banned' | 'rate_limited' | 'monitor';
+
 export default interface Record {
     id: number;
     ip_address?: string;
@@ -9,5 +11,7 @@ export default interface Record {
     country_name?: string;
     country_code?: string;
     rule_id?: number;
+    decision: Decision;
+    reason?: string;
     created_at?: Date;
 }
@@ -1,7 +1,14 @@
+export type RuleAction = 'allow' | 'deny' | 'tarpit' | 'log_only' | 'challenge' | 'rate_limit_only';
+
+export type RateLimitKeyPart = 'ip' | 'prefix' | 'asn' | 'header' | 'host';
+
+export type RateLimitAlgorithm = 'sliding_window' | 'gcra';
+export type OverLimitStatus = 'auto' | '403' | '429';
+
 export default interface Rule {
     id: number;
     weight?: number;
-    allow?: boolean;
+    action?: RuleAction;
     store?: boolean;
     ip_address?: string;
     protocol?: string;
@@ -11,12 +18,25 @@ export default interface Rule {
     city_name?: string;
     country_name?: string;
     country_code?: string;
+    status_code?: string;
     active?: number;
     created_at?: Date;
     updated_at?: Date;
 
     // Rate limiting fields
     rate_limit_enabled?: boolean;
+    rate_limit_key?: RateLimitKeyPart[];
+    rate_limit_header?: string;
+    rate_limit_algorithm?: RateLimitAlgorithm;
+    rate_limit_rate?: number;
+    rate_limit_burst?: number;
+    over_limit_status?: OverLimitStatus;
+    aggregate_limit?: number;
+    aggregate_window_seconds?: number;
+    aggregate_cooldown_seconds?: number;
+    aggregate_action?: 'deny' | 'challenge';
+    feed_id?: number;
+    abuseipdb_categories?: number[];
     max_retry?: number;
     find_time_seconds?: number;
     ban_time_seconds?: number;
@@ -26,4 +46,9 @@ export default interface Rule {
     ban_count_decay_days?: number;
     ignoreip?: string[];
     webhook?: string;
+
+    // Action specific fields
+    tarpit_seconds?: number;
+    challenge_url?: string;
+    tag?: string;
 }
@@ -1,3 +1,5 @@
+import type { RuleAction } from "@/models/rule";
+
 export default interface Template {
     name: string;
     description: string;
@@ -6,7 +8,7 @@ export default interface Template {
     path: string | null;
     query: string | null;
     country_code: string | null;
-    allow: boolean;
+    action: RuleAction;
     store: boolean;
     rate_limit_enabled: boolean;
     max_retry: number | null;
@@ -1,21 +1,31 @@
 import React from "react";
 import { useNavigate } from 'react-router';
 import { useTranslation } from "react-i18next";
-import { Button, Space } from 'antd';
-import { DeleteFilled, PlusOutlined } from '@ant-design/icons';
+import { Button, Checkbox, Input, InputNumber, Modal, Space, message } from 'antd';
+import { ClockCircleOutlined, DeleteFilled, EditFilled, ImportOutlined, LockOutlined, PlusOutlined, StopOutlined } from '@ant-design/icons';
 import type Ban from "@/models/ban";
 import CustomTable from '@/components/custom_table';
 import type { FieldDefinition } from '@/common/types';
 import type { DialogMessages } from '@/components/dialogs/custom_dialog';
+import { BASE_URL } from '@/constants';
 const TITLE = "Active Bans";
 const ENDPOINT = "bans";
+const EXTEND_SECONDS = 3600;
 
 const FIELDS: FieldDefinition<Ban>[] = [
-    { key: 'id', label: 'Id', type: 'number', value: 0, editable: false, fixed: 'left', width: 80 },
-    { key: 'ip_address', label: 'IP Address', type: 'string', value: "", width: 150, visible: true },
-    { key: 'reason', label: 'Reason', type: 'string', value: "", width: 200, visible: true },
-    { key: 'ban_duration_seconds', label: 'Duration (s)', type: 'number', value: 0, width: 120, visible: true },
-    { key: 'escalation_level', label: 'Level', type: 'number', value: 0, width: 80, visible: true },
+    { key: 'ip_address', label: 'IP Address', type: 'string', value: "", width: 150, fixed: 'left', filterKey: 'cidr', visible: true },
+    { key: 'kind', label: 'Kind', type: 'string', value: "ip", editable: false, width: 80, visible: true },
+    { key: 'rule_id', label: 'Rule', type: 'number', value: 0, width: 80, visible: true },
+    { key: 'reason', label: 'Reason', type: 'string', value: "", width: 200, filterKey: 'reason', visible: true },
+    { key: 'country_code', label: 'Country', type: 'string', editable: false, width: 100, filterKey: 'country', visible: true },
+    { key: 'city_name', label: 'City', type: 'string', editable: false, width: 140, visible: true },
+    { key: 'asn', label: 'ASN', type: 'number', editable: false, width: 100, visible: true },
+    { key: 'as_org', label: 'AS Organization', type: 'string', editable: false, width: 180, visible: false },
+    { key: 'banned_at', label: 'Banned at', type: 'date', editable: false, width: 180, visible: true },
+    { key: 'ban_duration_seconds', label: 'Duration (s)', type: 'number', value: 3600, width: 120, visible: true },
+    { key: 'time_remaining_seconds', label: 'Remaining (s)', type: 'number', width: 130, visible: true },
+    { key: 'permanent', label: 'Permanent', type: 'boolean', value: false, width: 100, visible: true },
+    { key: 'escalation_level', label: 'Level', type: 'number', editable: false, width: 80, visible: true },
 ];
 
 const BAN_DIALOG_MESSAGES: DialogMessages = {
@@ -27,19 +37,169 @@ const BAN_DIALOG_MESSAGES: DialogMessages = {
         `Are you sure you want to unban IP "${id}"?`,
 };
 
-export class InnerPage extends React.Component<{ navigate: any; t: any }, {}> {
+interface State {
+    bulkBanOpen: boolean;
+    bulkBanText: string;
+    bulkBanReason: string;
+    bulkBanDuration: number | null;
+    bulkBanPermanent: boolean;
+    bulkUnbanOpen: boolean;
+    bulkUnbanRule: number | null;
+    bulkUnbanCountry: string;
+    bulkUnbanCidr: string;
+    bulkUnbanAll: boolean;
+    resetEscalation: boolean;
+    working: boolean;
+    tableKey: number;
+}
+
+export class InnerPage extends React.Component<{ navigate: any; t: any }, State> {
+    state: State = {
+        bulkBanOpen: false,
+        bulkBanText: "",
+        bulkBanReason: "",
+        bulkBanDuration: null,
+        bulkBanPermanent: false,
+        bulkUnbanOpen: false,
+        bulkUnbanRule: null,
+        bulkUnbanCountry: "",
+        bulkUnbanCidr: "",
+        bulkUnbanAll: false,
+        resetEscalation: false,
+        working: false,
+        tableKey: 0,
+    };
+
+    private reload = () => {
+        this.setState(prevState => ({ tableKey: prevState.tableKey + 1 }));
+    }
+
+    private request = async (method: string, path: string, body?: object) => {
+        this.setState({ working: true });
+        try {
+            const response = await fetch(`${BASE_URL}/api/v1/${ENDPOINT}${path}`, {
+                method: method,
+                headers: { 'Content-Type': 'application/json' },
+                body: body ? JSON.stringify(body) : undefined,
+            });
+            const json = await response.json();
+            if (!response.ok) {
+                message.error(json.message || 'Request failed');
+                return null;
+            }
+            return json;
+        } catch (error) {
+            console.error('Error managing bans:', error);
+            message.error('Request failed');
+            return null;
+        } finally {
+            this.setState({ working: false });
+        }
+    }
+
+    handleUpdate = async (item: Ban, change: object) => {
+        const json = await this.request('PATCH', '', { ip_address: item.ip_address, rule_id: item.rule_id, ...change });
+        if (json) {
+            message.success(this.props.t('Ban updated'));
+            this.reload();
+        }
+    }
+
+    handleUnban = (item: Ban) => {
+        let resetEscalation = false;
+        Modal.confirm({
+            title: this.props.t('Unban IP'),
+            content: (
+                <Space direction="vertical">
+                    {`${this.props.t('Are you sure you want to unban')} "${item.ip_address}"?`}
+                    <Checkbox onChange={(e) => { resetEscalation = e.target.checked; }}>
+                        {this.props.t('Reset escalation')}
+                    </Checkbox>
+                </Space>
+            ),
+            onOk: async () => {
+                const params = new URLSearchParams({ ip_address: item.ip_address, reset_escalation: String(resetEscalation) });
+                if (item.rule_id !== undefined && item.rule_id !== null) {
+                    params.set('rule_id', String(item.rule_id));
+                }
+                const json = await this.request('DELETE', `?${params.toString()}`);
+                if (json) {
+                    message.success(this.props.t('IP unbanned'));
+                    this.reload();
+                }
+            },
+        });
+    }
+
+    handleBulkBan = async () => {
+        const json = await this.request('POST', '/bulk', {
+            addresses: this.state.bulkBanText,
+            reason: this.state.bulkBanReason || undefined,
+            ban_duration_seconds: this.state.bulkBanDuration ?? undefined,
+            permanent: this.state.bulkBanPermanent,
+        });
+        if (json) {
+            const invalid: string[] = json.data?.invalid || [];
+            message.success(`${json.data?.banned ?? 0} ${this.props.t('IPs banned')}`);
+            if (invalid.length > 0) {
+                message.warning(`${this.props.t('Invalid entries')}: ${invalid.join(', ')}`);
+            }
+            this.setState({ bulkBanOpen: false, bulkBanText: "" });
+            this.reload();
+        }
+    }
+
+    handleBulkUnban = async () => {
+        const params = new URLSearchParams({ reset_escalation: String(this.state.resetEscalation) });
+        if (this.state.bulkUnbanAll) {
+            params.set('all', 'true');
+        }
+        if (this.state.bulkUnbanRule !== null) {
+            params.set('rule_id', String(this.state.bulkUnbanRule));
+        }
+        if (this.state.bulkUnbanCountry) {
+            params.set('country', this.state.bulkUnbanCountry);
+        }
+        if (this.state.bulkUnbanCidr) {
+            params.set('cidr', this.state.bulkUnbanCidr);
+        }
+        const json = await this.request('DELETE', `/bulk?${params.toString()}`);
+        if (json) {
+            message.success(`${json.data ?? 0} ${this.props.t('bans lifted')}`);
+            this.setState({ bulkUnbanOpen: false });
+            this.reload();
+        }
+    }
+
     private renderHeaderAction = (onCreate: () => void) => {
         return (
-            <Button type="primary" onClick={onCreate} icon={<PlusOutlined />}>
-                {this.props.t("Ban IP")}
-            </Button>
+            <Space>
+                <Button onClick={() => this.setState({ bulkUnbanOpen: true })} icon={<StopOutlined />}>
+                    {this.props.t("Bulk unban")}
+                </Button>
+                <Button onClick={() => this.setState({ bulkBanOpen: true })} icon={<ImportOutlined />}>
+                    {this.props.t("Bulk ban")}
+                </Button>
+                <Button type="primary" onClick={onCreate} icon={<PlusOutlined />}>
+                    {this.props.t("Ban IP")}
+                </Button>
+            </Space>
         );
     };
 
-    private renderActionColumn = (item: Ban, _onEdit: any, onDelete: (item: Ban) => void) => {
+    private renderActionColumn = (item: Ban, onEdit: (item: Ban) => void, _onDelete: (item: Ban) => void) => {
         return (
             <Space size="middle">
-                <Button onClick={() => onDelete(item)} title={this.props.t('Unban')} danger>
+                <Button onClick={() => onEdit(item)} title={this.props.t('Edit')}>
+                    <EditFilled />
+                </Button>
+                <Button onClick={() => this.handleUpdate(item, { extend_seconds: EXTEND_SECONDS })} title={this.props.t('Extend 1h')} disabled={item.permanent}>
+                    <ClockCircleOutlined />
+                </Button>
+                <Button onClick={() => this.handleUpdate(item, { permanent: true })} title={this.props.t('Make permanent')} disabled={item.permanent}>
+                    <LockOutlined />
+                </Button>
+                <Button onClick={() => this.handleUnban(item)} title={this.props.t('Unban')} danger>
                     <DeleteFilled />
                 </Button>
             </Space>
@@ -48,16 +208,82 @@ export class InnerPage extends React.Component<{ navigate: any; t: any }, {}> {
 
     render = () => {
         return (
-            <CustomTable<Ban>
-                title={TITLE}
-                endpoint={ENDPOINT}
-                fields={FIELDS}
-                dialogMessages={BAN_DIALOG_MESSAGES}
-                t={this.props.t}
-                hasActions={true}
-                renderHeaderAction={this.renderHeaderAction}
-                renderActionColumn={this.renderActionColumn}
-            />
+            <>
+                <CustomTable<Ban>
+                    key={this.state.tableKey}
+                    title={TITLE}
+                    endpoint={ENDPOINT}
+                    fields={FIELDS}
+                    dialogMessages={BAN_DIALOG_MESSAGES}
+                    t={this.props.t}
+                    hasActions={true}
+                    renderHeaderAction={this.renderHeaderAction}
+                    renderActionColumn={this.renderActionColumn}
+                />
+                <Modal
+                    title={this.props.t("Bulk ban")}
+                    open={this.state.bulkBanOpen}
+                    confirmLoading={this.state.working}
+                    onOk={this.handleBulkBan}
+                    onCancel={() => this.setState({ bulkBanOpen: false })}
+                >
+                    <Space direction="vertical" style={{ width: '100%' }}>
+                        <Input.TextArea
+                            rows={10}
+                            value={this.state.bulkBanText}
+                            placeholder={"1.2.3.4\n10.0.0.0/8 # office\nAS64500\ncountry:ES"}
+                            onChange={(e) => this.setState({ bulkBanText: e.target.value })}
+                        />
+                        <Input
+                            value={this.state.bulkBanReason}
+                            placeholder={this.props.t("Reason")}
+                            onChange={(e) => this.setState({ bulkBanReason: e.target.value })}
+                        />
+                        <InputNumber
+                            style={{ width: '100%' }}
+                            value={this.state.bulkBanDuration}
+                            placeholder={this.props.t("Duration (s)")}
+                            disabled={this.state.bulkBanPermanent}
+                            onChange={(bulkBanDuration) => this.setState({ bulkBanDuration })}
+                        />
+                        <Checkbox checked={this.state.bulkBanPermanent} onChange={(e) => this.setState({ bulkBanPermanent: e.target.checked })}>
+                            {this.props.t("Permanent")}
+                        </Checkbox>
+                    </Space>
+                </Modal>
+                <Modal
+                    title={this.props.t("Bulk unban")}
+                    open={this.state.bulkUnbanOpen}
+                    confirmLoading={this.state.working}
+                    onOk={this.handleBulkUnban}
+                    onCancel={() => this.setState({ bulkUnbanOpen: false })}
+                >
+                    <Space direction="vertical" style={{ width: '100%' }}>
+                        <InputNumber
+                            style={{ width: '100%' }}
+                            value={this.state.bulkUnbanRule}
+                            placeholder={this.props.t("Rule")}
+                            onChange={(bulkUnbanRule) => this.setState({ bulkUnbanRule })}
+                        />
+                        <Input
+                            value={this.state.bulkUnbanCountry}
+                            placeholder={this.props.t("Country")}
+                            onChange={(e) => this.setState({ bulkUnbanCountry: e.target.value })}
+                        />
+                        <Input
+                            value={this.state.bulkUnbanCidr}
+                            placeholder={"10.0.0.0/8"}
+                            onChange={(e) => this.setState({ bulkUnbanCidr: e.target.value })}
+                        />
+                        <Checkbox checked={this.state.bulkUnbanAll} onChange={(e) => this.setState({ bulkUnbanAll: e.target.checked })}>
+                            {this.props.t("Unban everything")}
+                        </Checkbox>
+                        <Checkbox checked={this.state.resetEscalation} onChange={(e) => this.setState({ resetEscalation: e.target.checked })}>
+                            {this.props.t("Reset escalation")}
+                        </Checkbox>
+                    </Space>
+                </Modal>
+            </>
         );
     }
 }
@@ -66,4 +292,4 @@ export default function Page() {
     const navigate = useNavigate();
     const { t } = useTranslation();
     return <InnerPage navigate={navigate} t={t} />;
-}
\ No newline at end of file
+}
@@ -0,0 +1,128 @@
+import React from "react";
+import { useNavigate } from 'react-router';
+import { useTranslation } from "react-i18next";
+import { Button, Input, Modal, Space, Typography, message } from 'antd';
+import { DeleteFilled, PlusOutlined } from '@ant-design/icons';
+import type Bouncer from "@/models/bouncer";
+import CustomTable from '@/components/custom_table';
+import type { FieldDefinition } from '@/common/types';
+import type { DialogMessages } from '@/components/dialogs/custom_dialog';
+import { BASE_URL } from '@/constants';
+const TITLE = "Bouncers";
+const ENDPOINT = "bouncers";
+
+const FIELDS: FieldDefinition<Bouncer>[] = [
+    { key: 'id', label: 'Id', type: 'number', value: 0, editable: false, fixed: 'left', width: 80 },
+    { key: 'name', label: 'Name', type: 'string', value: "", width: 200, visible: true, required: true },
+    { key: 'last_pull_at', label: 'Last pull', type: 'date', editable: false, width: 180, visible: true },
+    { key: 'created_at', label: 'Created at', type: 'date', editable: false, width: 180, visible: true },
+];
+
+const DIALOG_MESSAGES: DialogMessages = {
+    createTitle: 'Add bouncer',
+    readTitle: 'View bouncer',
+    updateTitle: 'Update bouncer',
+    deleteTitle: 'Delete bouncer',
+    confirmDeleteMessage: (id: number | string) =>
+        `Are you sure you want to delete bouncer "${id}"? Its API key stops working.`,
+};
+
+interface State {
+    createOpen: boolean;
+    name: string;
+    creating: boolean;
+    tableKey: number;
+}
+
+export class InnerPage extends React.Component<{ navigate: any; t: any }, State> {
+    state: State = {
+        createOpen: false,
+        name: "",
+        creating: false,
+        tableKey: 0,
+    };
+
+    handleCreate = async () => {
+        this.setState({ creating: true });
+        try {
+            const response = await fetch(`${BASE_URL}/api/v1/${ENDPOINT}`, {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ name: this.state.name }),
+            });
+            const json = await response.json();
+            if (response.ok) {
+                this.setState(prevState => ({ createOpen: false, name: "", tableKey: prevState.tableKey + 1 }));
+                Modal.info({
+                    title: this.props.t("Bouncer API key"),
+                    content: (
+                        <Space direction="vertical">
+                            <Typography.Text>{this.props.t("Copy it now, it won't be shown again")}</Typography.Text>
+                            <Typography.Text code copyable>{json.data?.api_key}</Typography.Text>
+                        </Space>
+                    ),
+                });
+            } else {
+                message.error(json.message || 'Failed to create bouncer');
+            }
+        } catch (error) {
+            console.error('Error creating bouncer:', error);
+            message.error('Failed to create bouncer');
+        } finally {
+            this.setState({ creating: false });
+        }
+    }
+
+    private renderHeaderAction = () => {
+        return (
+            <Button type="primary" onClick={() => this.setState({ createOpen: true })} icon={<PlusOutlined />}>
+                {this.props.t("Add Bouncer")}
+            </Button>
+        );
+    };
+
+    private renderActionColumn = (item: Bouncer, _onEdit: (item: Bouncer) => void, onDelete: (item: Bouncer) => void) => {
+        return (
+            <Button onClick={() => onDelete(item)} title={this.props.t('Delete')} danger>
+                <DeleteFilled />
+            </Button>
+        );
+    };
+
+    render = () => {
+        return (
+            <>
+                <CustomTable<Bouncer>
+                    key={this.state.tableKey}
+                    title={TITLE}
+                    endpoint={ENDPOINT}
+                    fields={FIELDS}
+                    dialogMessages={DIALOG_MESSAGES}
+                    t={this.props.t}
+                    hasActions={true}
+                    renderHeaderAction={this.renderHeaderAction}
+                    renderActionColumn={this.renderActionColumn}
+                />
+                <Modal
+                    title={this.props.t("Add bouncer")}
+                    open={this.state.createOpen}
+                    confirmLoading={this.state.creating}
+                    onOk={this.handleCreate}
+                    onCancel={() => this.setState({ createOpen: false })}
+                >
+                    <Input
+                        value={this.state.name}
+                        placeholder="cs-firewall-bouncer"
+                        onChange={(e) => this.setState({ name: e.target.value })}
+                    />
+                </Modal>
+            </>
+        );
+    }
+}
+
+export default function Page() {
+    const navigate = useNavigate();
+    const { t } = useTranslation();
+    return <InnerPage navigate={navigate} t={t} />;
+}
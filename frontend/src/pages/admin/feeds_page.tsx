@@ -0,0 +1,117 @@
+import React from "react";
+import { useNavigate } from 'react-router';
+import { useTranslation } from "react-i18next";
+import { Button, Space, message } from 'antd';
+import { DeleteFilled, EditFilled, PlusOutlined, ReloadOutlined } from '@ant-design/icons';
+import type Feed from "@/models/feed";
+import CustomTable from '@/components/custom_table';
+import type { FieldDefinition } from '@/common/types';
+import type { DialogMessages } from '@/components/dialogs/custom_dialog';
+import { BASE_URL } from '@/constants';
+const TITLE = "Feeds";
+const ENDPOINT = "feeds";
+
+const FIELDS: FieldDefinition<Feed>[] = [
+    { key: 'id', label: 'Id', type: 'number', value: 0, editable: false, fixed: 'left', width: 80 },
+    { key: 'name', label: 'Name', type: 'string', value: "", width: 150, visible: true, required: true },
+    { key: 'source', label: 'Source (URL or path)', type: 'string', value: "", width: 280, visible: true, required: true },
+    {
+        key: 'format', label: 'Format', type: 'select', value: 'plain', width: 140, visible: true,
+        options: [
+            { value: 'plain', label: 'Plain IP/CIDR' },
+            { value: 'spamhaus_drop', label: 'Spamhaus DROP' },
+            { value: 'firehol_netset', label: 'FireHOL netset' },
+            { value: 'csv', label: 'CSV' },
+        ],
+    },
+    { key: 'csv_column', label: 'CSV Column', type: 'number', value: 0, width: 110, visible: false },
+    { key: 'refresh_seconds', label: 'Refresh (s)', type: 'number', value: 3600, width: 110, visible: true },
+    { key: 'deny', label: 'Deny', type: 'boolean', value: false, width: 80, visible: true },
+    { key: 'active', label: 'Active', type: 'boolean', value: true, width: 80, visible: true },
+    { key: 'last_fetch_at', label: 'Last fetch', type: 'date', editable: false, width: 180, visible: true },
+    { key: 'last_status', label: 'Status', type: 'string', editable: false, width: 200, visible: true },
+    { key: 'entry_count', label: 'Entries', type: 'number', editable: false, width: 100, visible: true },
+];
+
+const DIALOG_MESSAGES: DialogMessages = {
+    createTitle: 'Create feed',
+    readTitle: 'View feed',
+    updateTitle: 'Update feed',
+    deleteTitle: 'Delete feed',
+    confirmDeleteMessage: (id: number | string) =>
+        `Are you sure you want to delete feed "${id}"?`,
+};
+
+interface State {
+    tableKey: number;
+}
+
+export class InnerPage extends React.Component<{ navigate: any; t: any }, State> {
+    state: State = {
+        tableKey: 0,
+    };
+
+    handleRefresh = async (item: Feed) => {
+        try {
+            const response = await fetch(`${BASE_URL}/api/v1/${ENDPOINT}/refresh?id=${item.id}`, {
+                method: 'POST',
+            });
+            const json = await response.json();
+            if (response.ok && json.data?.last_status === 'ok') {
+                message.success(`${json.data.entry_count} ${this.props.t('entries loaded')}`);
+            } else {
+                message.error(json.data?.last_status || json.message || 'Failed to refresh');
+            }
+            this.setState(prevState => ({ tableKey: prevState.tableKey + 1 }));
+        } catch (error) {
+            console.error('Error refreshing feed:', error);
+            message.error('Failed to refresh');
+        }
+    }
+
+    private renderHeaderAction = (onCreate: () => void) => {
+        return (
+            <Button type="primary" onClick={onCreate} icon={<PlusOutlined />}>
+                {this.props.t("Add Feed")}
+            </Button>
+        );
+    };
+
+    private renderActionColumn = (item: Feed, onEdit: (item: Feed) => void, onDelete: (item: Feed) => void) => {
+        return (
+            <Space size="middle">
+                <Button onClick={() => this.handleRefresh(item)} title={this.props.t('Refresh')}>
+                    <ReloadOutlined />
+                </Button>
+                <Button onClick={() => onEdit(item)} title={this.props.t('Edit')}>
+                    <EditFilled />
+                </Button>
+                <Button onClick={() => onDelete(item)} title={this.props.t('Delete')} danger>
+                    <DeleteFilled />
+                </Button>
+            </Space>
+        );
+    };
+
+    render = () => {
+        return (
+            <CustomTable<Feed>
+                key={this.state.tableKey}
+                title={TITLE}
+                endpoint={ENDPOINT}
+                fields={FIELDS}
+                dialogMessages={DIALOG_MESSAGES}
+                t={this.props.t}
+                hasActions={true}
+                renderHeaderAction={this.renderHeaderAction}
+                renderActionColumn={this.renderActionColumn}
+            />
+        );
+    }
+}
+
+export default function Page() {
+    const navigate = useNavigate();
+    const { t } = useTranslation();
+    return <InnerPage navigate={navigate} t={t} />;
+}
@@ -0,0 +1,151 @@
+import React from "react";
+import { useNavigate } from 'react-router';
+import { useTranslation } from "react-i18next";
+import { Button, Input, Modal, Select, Space, message } from 'antd';
+import { DeleteFilled, EditFilled, ImportOutlined, PlusOutlined } from '@ant-design/icons';
+import type IpListEntry from "@/models/ip_list";
+import CustomTable from '@/components/custom_table';
+import type { FieldDefinition } from '@/common/types';
+import type { DialogMessages } from '@/components/dialogs/custom_dialog';
+import { BASE_URL } from '@/constants';
+const TITLE = "IP Lists";
+const ENDPOINT = "ip-lists";
+
+const LIST_OPTIONS = [
+    { value: 'allow', label: 'Allow' },
+    { value: 'deny', label: 'Deny' },
+];
+
+const FIELDS: FieldDefinition<IpListEntry>[] = [
+    { key: 'id', label: 'Id', type: 'number', value: 0, editable: false, fixed: 'left', width: 80 },
+    { key: 'list', label: 'List', type: 'select', value: 'deny', width: 100, visible: true, options: LIST_OPTIONS },
+    { key: 'address', label: 'Address', type: 'string', value: "", width: 180, visible: true, required: true },
+    { key: 'comment', label: 'Comment', type: 'string', value: "", width: 250, visible: true },
+    { key: 'expires_at', label: 'Expires at', type: 'date', width: 180, visible: true },
+    { key: 'created_at', label: 'Created at', type: 'date', editable: false, width: 180, visible: true },
+];
+
+const DIALOG_MESSAGES: DialogMessages = {
+    createTitle: 'Add to list',
+    readTitle: 'View entry',
+    updateTitle: 'Update entry',
+    deleteTitle: 'Remove from list',
+    confirmDeleteMessage: (id: number | string) =>
+        `Are you sure you want to remove entry "${id}"?`,
+};
+
+interface State {
+    importOpen: boolean;
+    importList: 'allow' | 'deny';
+    importText: string;
+    importing: boolean;
+    tableKey: number;
+}
+
+export class InnerPage extends React.Component<{ navigate: any; t: any }, State> {
+    state: State = {
+        importOpen: false,
+        importList: 'deny',
+        importText: "",
+        importing: false,
+        tableKey: 0,
+    };
+
+    handleImport = async () => {
+        this.setState({ importing: true });
+        try {
+            const response = await fetch(`${BASE_URL}/api/v1/${ENDPOINT}/import`, {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ list: this.state.importList, text: this.state.importText }),
+            });
+            const json = await response.json();
+            if (response.ok) {
+                const invalid: string[] = json.data?.invalid || [];
+                message.success(`${json.data?.imported ?? 0} ${this.props.t('entries imported')}`);
+                if (invalid.length > 0) {
+                    message.warning(`${this.props.t('Invalid lines')}: ${invalid.join(', ')}`);
+                }
+                this.setState(prevState => ({ importOpen: false, importText: "", tableKey: prevState.tableKey + 1 }));
+            } else {
+                message.error(json.message || 'Failed to import');
+            }
+        } catch (error) {
+            console.error('Error importing IP list:', error);
+            message.error('Failed to import');
+        } finally {
+            this.setState({ importing: false });
+        }
+    }
+
+    private renderHeaderAction = (onCreate: () => void) => {
+        return (
+            <Space>
+                <Button onClick={() => this.setState({ importOpen: true })} icon={<ImportOutlined />}>
+                    {this.props.t("Import")}
+                </Button>
+                <Button type="primary" onClick={onCreate} icon={<PlusOutlined />}>
+                    {this.props.t("Add")}
+                </Button>
+            </Space>
+        );
+    };
+
+    private renderActionColumn = (item: IpListEntry, onEdit: (item: IpListEntry) => void, onDelete: (item: IpListEntry) => void) => {
+        return (
+            <Space size="middle">
+                <Button onClick={() => onEdit(item)} title={this.props.t('Edit')}>
+                    <EditFilled />
+                </Button>
+                <Button onClick={() => onDelete(item)} title={this.props.t('Remove')} danger>
+                    <DeleteFilled />
+                </Button>
+            </Space>
+        );
+    };
+
+    render = () => {
+        return (
+            <>
+                <CustomTable<IpListEntry>
+                    key={this.state.tableKey}
+                    title={TITLE}
+                    endpoint={ENDPOINT}
+                    fields={FIELDS}
+                    dialogMessages={DIALOG_MESSAGES}
+                    t={this.props.t}
+                    hasActions={true}
+                    renderHeaderAction={this.renderHeaderAction}
+                    renderActionColumn={this.renderActionColumn}
+                />
+                <Modal
+                    title={this.props.t("Import IP list")}
+                    open={this.state.importOpen}
+                    confirmLoading={this.state.importing}
+                    onOk={this.handleImport}
+                    onCancel={() => this.setState({ importOpen: false })}
+                >
+                    <Space direction="vertical" style={{ width: '100%' }}>
+                        <Select
+                            value={this.state.importList}
+                            options={LIST_OPTIONS}
+                            onChange={(importList) => this.setState({ importList })}
+                        />
+                        <Input.TextArea
+                            rows={10}
+                            value={this.state.importText}
+                            placeholder={"1.2.3.4\n10.0.0.0/8 # office"}
+                            onChange={(e) => this.setState({ importText: e.target.value })}
+                        />
+                    </Space>
+                </Modal>
+            </>
+        );
+    }
+}
+
+export default function Page() {
+    const navigate = useNavigate();
+    const { t } = useTranslation();
+    return <InnerPage navigate={navigate} t={t} />;
+}
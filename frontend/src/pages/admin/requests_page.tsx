@@ -17,6 +17,8 @@ const RecordsFields: FieldDefinition<Item>[] = [
     { key: 'city_name', label: 'City Name', type: 'string', filterKey: 'city_name' },
     { key: 'country_name', label: 'Country Name', type: 'string', filterKey: 'country_name' },
     { key: 'country_code', label: 'Country Code', type: 'string', filterKey: 'country_code' },
+    { key: 'decision', label: 'Decision', type: 'string', filterKey: 'decision' },
+    { key: 'reason', label: 'Reason', type: 'string', filterKey: 'reason' },
     { key: 'rule_id', label: 'Rule Id', type: 'number', filterKey: 'rule_id', fixed: 'right' }
 ];
 
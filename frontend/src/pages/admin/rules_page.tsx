@@ -18,7 +18,17 @@ const ENDPOINT = "rules";
 const FIELDS: FieldDefinition<Item>[] = [
     { key: 'id', label: 'Id', type: 'number', value: 0, editable: false, fixed: 'left', width: 80 },
     { key: 'active', label: 'Active', type: 'boolean', value: true, width: 80, visible: true },
-    { key: 'allow', label: 'Allow', type: 'boolean', value: false, width: 80, visible: true },
+    {
+        key: 'action', label: 'Action', type: 'select', value: 'allow', width: 130, visible: true,
+        options: [
+            { value: 'allow', label: 'Allow' },
+            { value: 'deny', label: 'Deny' },
+            { value: 'tarpit', label: 'Tarpit' },
+            { value: 'log_only', label: 'Log only' },
+            { value: 'challenge', label: 'Challenge' },
+            { value: 'rate_limit_only', label: 'Rate limit only' },
+        ],
+    },
     { key: 'store', label: 'Store', type: 'boolean', value: true, width: 80, visible: true },
     { key: 'weight', label: 'Weight', type: 'number', value: 100, width: 80, visible: true },
     { key: 'ip_address', label: 'IP Address', type: 'string', value: "", width: 150, filterKey: "ip_address", visible: true },
@@ -29,8 +39,40 @@ const FIELDS: FieldDefinition<Item>[] = [
     { key: 'city_name', label: 'City Name', type: 'string', value: "", width: 150, filterKey: "city_name", visible: true },
     { key: 'country_name', label: 'Contry Name', type: 'string', value: "", width: 150, filterKey: "country_name", visible: true },
     { key: 'country_code', label: 'Contry Code', type: 'string', value: "", width: 150, filterKey: "country_code", visible: true },
+    { key: 'status_code', label: 'Status (log)', type: 'string', value: "", width: 120, filterKey: "status_code", visible: true },
     // Rate limiting fields
     { key: 'rate_limit_enabled', label: 'Rate Limit', type: 'boolean', value: false, width: 100, visible: true },
+    {
+        key: 'rate_limit_algorithm', label: 'Algorithm', type: 'select', value: 'sliding_window', width: 140, visible: true,
+        options: [
+            { value: 'sliding_window', label: 'Sliding window (ban)' },
+            { value: 'gcra', label: 'Token bucket (429)' },
+        ],
+    },
+    { key: 'rate_limit_rate', label: 'Rate (req/s)', type: 'number', value: 1, width: 110, visible: true },
+    { key: 'rate_limit_burst', label: 'Burst', type: 'number', value: 10, width: 90, visible: true },
+    {
+        key: 'over_limit_status', label: 'Over Limit', type: 'select', value: 'auto', width: 120, visible: true,
+        options: [
+            { value: 'auto', label: 'Auto' },
+            { value: '403', label: '403 Forbidden' },
+            { value: '429', label: '429 Too Many Requests' },
+        ],
+    },
+    { key: 'rate_limit_key', label: 'Rate Limit Key', type: 'string', value: "", width: 140, visible: true },
+    { key: 'rate_limit_header', label: 'Rate Limit Header', type: 'string', value: "", width: 150, visible: false },
+    { key: 'aggregate_limit', label: 'Aggregate Limit', type: 'number', value: 0, width: 130, visible: true },
+    { key: 'aggregate_window_seconds', label: 'Aggregate Window (s)', type: 'number', value: 60, width: 150, visible: false },
+    { key: 'aggregate_cooldown_seconds', label: 'Cool-down (s)', type: 'number', value: 300, width: 120, visible: false },
+    {
+        key: 'aggregate_action', label: 'Aggregate Action', type: 'select', value: 'deny', width: 140, visible: false,
+        options: [
+            { value: 'deny', label: 'Deny' },
+            { value: 'challenge', label: 'Challenge' },
+        ],
+    },
+    { key: 'feed_id', label: 'Feed Id', type: 'number', width: 100, visible: false },
+    { key: 'abuseipdb_categories', label: 'AbuseIPDB Categories', type: 'string', value: "", width: 160, visible: false },
     { key: 'max_retry', label: 'Max Retry', type: 'number', value: 5, width: 100, visible: true },
     { key: 'find_time_seconds', label: 'Find Time (s)', type: 'number', value: 600, width: 120, visible: true },
     { key: 'ban_time_seconds', label: 'Ban Time (s)', type: 'number', value: 3600, width: 120, visible: true },
@@ -40,6 +82,10 @@ const FIELDS: FieldDefinition<Item>[] = [
     { key: 'ban_count_decay_days', label: 'Decay (d)', type: 'number', value: 30, width: 100, visible: true },
     { key: 'ignoreip', label: 'Ignore IPs', type: 'string', value: "", width: 150, visible: true },
     { key: 'webhook', label: 'Webhook', type: 'string', value: "", width: 200, visible: false },
+    // Action specific fields
+    { key: 'tarpit_seconds', label: 'Tarpit (s)', type: 'number', value: 10, width: 100, visible: true },
+    { key: 'challenge_url', label: 'Challenge URL', type: 'string', value: "", width: 200, visible: true },
+    { key: 'tag', label: 'Tag', type: 'string', value: "", width: 120, visible: true },
 ];
 
 // Mensajes específicos para el CustomDialog de Rules
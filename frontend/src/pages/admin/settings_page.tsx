@@ -1,13 +1,28 @@
 import React from 'react';
-import { Card, Form, InputNumber, Button, Typography, message, Flex } from 'antd';
+import { Card, Form, InputNumber, Button, Switch, Typography, message, Flex } from 'antd';
 import { BASE_URL } from '@/constants';
 
 const { Title } = Typography;
 
+interface RecidivePolicy {
+    enabled: boolean;
+    max_bans: number;
+    find_days: number;
+    ban_seconds: number;
+}
+
 interface Settings {
     log_retention_days: number;
+    recidive: RecidivePolicy;
 }
 
+const DEFAULT_RECIDIVE: RecidivePolicy = {
+    enabled: false,
+    max_bans: 5,
+    find_days: 1,
+    ban_seconds: 604800,
+};
+
 interface State {
     settings: Settings | null;
     loading: boolean;
@@ -43,7 +58,7 @@ export default class SettingsPage extends React.Component<{}, State> {
         }
     }
 
-    handleSave = async (values: { log_retention_days: number }) => {
+    handleSave = async (values: Partial<Settings>) => {
         this.setState({ saving: true });
         try {
             const response = await fetch(`${BASE_URL}/api/v1/settings`, {
@@ -96,6 +111,43 @@ export default class SettingsPage extends React.Component<{}, State> {
                         </Form.Item>
                     </Form>
                 </Card>
+                <Card title="Recidive Jail">
+                    <Form
+                        layout="vertical"
+                        onFinish={(recidive: RecidivePolicy) => this.handleSave({ recidive })}
+                        initialValues={this.state.settings?.recidive || DEFAULT_RECIDIVE}
+                    >
+                        <Form.Item label="Enabled" name="enabled" valuePropName="checked">
+                            <Switch />
+                        </Form.Item>
+                        <Form.Item
+                            label="Bans that trigger it"
+                            name="max_bans"
+                            rules={[{ required: true, type: 'number', min: 2, message: 'Must be at least 2' }]}
+                        >
+                            <InputNumber min={2} style={{ width: '100%' }} />
+                        </Form.Item>
+                        <Form.Item
+                            label="Counted within (days)"
+                            name="find_days"
+                            rules={[{ required: true, type: 'number', min: 1, max: 365, message: 'Must be between 1 and 365' }]}
+                        >
+                            <InputNumber min={1} max={365} style={{ width: '100%' }} />
+                        </Form.Item>
+                        <Form.Item
+                            label="Ban duration (seconds, -1 for permanent)"
+                            name="ban_seconds"
+                            rules={[{ required: true, message: 'Please set the ban duration' }]}
+                        >
+                            <InputNumber min={-1} style={{ width: '100%' }} />
+                        </Form.Item>
+                        <Form.Item>
+                            <Button type="primary" htmlType="submit" loading={this.state.saving}>
+                                Save Settings
+                            </Button>
+                        </Form.Item>
+                    </Form>
+                </Card>
             </Flex>
         );
     }
@@ -84,7 +84,7 @@ export default class TemplatesPage extends React.Component<{}, State> {
         try {
             const body: any = {
                 weight: 100,
-                allow: template.allow,
+                action: template.action,
                 store: template.store,
                 path: template.path,
                 query: template.query,
@@ -213,7 +213,7 @@ export default class TemplatesPage extends React.Component<{}, State> {
                                 </Flex>
                                 <Text type="secondary">{t.description}</Text>
                                 <Flex wrap gap="small" align="center">
-                                    {t.allow
+                                    {t.action === 'allow'
                                         ? <Tag icon={<CheckCircleOutlined />} color="success">Allow</Tag>
                                         : <Tag icon={<CloseCircleOutlined />} color="error">Deny</Tag>
                                     }
@@ -302,7 +302,7 @@ export default class TemplatesPage extends React.Component<{}, State> {
                             />
                         </Flex>
                         <Flex wrap gap="small">
-                            <Tag>{selected?.allow ? "Allow" : "Deny"}</Tag>
+                            <Tag>{selected?.action === 'allow' ? "Allow" : "Deny"}</Tag>
                             {selected?.path && <Tag color="blue">Path: {selected.path}</Tag>}
                             {selected?.query && <Tag color="purple">Query: {selected.query}</Tag>}
                             {selected?.rate_limit_enabled && <Tag color="orange">Rate limited</Tag>}